@@ -724,6 +724,75 @@ async function apiCall(endpoint, method = 'GET', data = null) {
     }
 }
 
+// Stream an AI analysis from one of the POST .../analyze/stream endpoints.
+// EventSource can only send GET requests, so this reads the Server-Sent Events from a fetch body instead.
+// onText(textSoFar) is called as each chunk arrives; resolves with the same shape as the non-streaming
+// endpoints: { success, analysis, error, token_usage, model, context, cache }
+async function streamAnalysis(endpoint, data, onText = () => {}) {
+    const response = await fetch(`${API_BASE}${endpoint}`, {
+        method: 'POST',
+        headers: {
+            'Content-Type': 'application/json',
+            'Accept': 'text/event-stream'
+        },
+        body: JSON.stringify(data)
+    });
+    
+    // Request problems (missing key, disallowed model, budget) come back as plain JSON
+    if (!response.ok || !(response.headers.get('Content-Type') || '').includes('text/event-stream')) {
+        const result = await response.json().catch(() => ({}));
+        return { success: false, analysis: null, error: result.error || `HTTP error! status: ${response.status}` };
+    }
+    
+    const result = { success: false, analysis: '', error: null, token_usage: null };
+    const reader = response.body.getReader();
+    const decoder = new TextDecoder();
+    let buffer = '';
+    
+    const handleEvent = (block) => {
+        let event = 'message';
+        const dataLines = [];
+        block.split('\n').forEach(line => {
+            if (line.startsWith('event:')) event = line.slice(6).trim();
+            else if (line.startsWith('data:')) dataLines.push(line.slice(5).trimStart());
+        });
+        if (dataLines.length === 0) return;
+        const payload = JSON.parse(dataLines.join('\n'));
+        if (event === 'start') {
+            result.model = payload.model;
+            result.context = payload.context;
+        } else if (event === 'chunk') {
+            result.analysis += payload.text;
+            onText(result.analysis);
+        } else if (event === 'done') {
+            result.success = true;
+            result.token_usage = payload.token_usage;
+            result.cache = payload.cache;
+        } else if (event === 'error') {
+            result.error = payload.error || 'Analysis failed';
+        }
+    };
+    
+    while (true) {
+        const { value, done } = await reader.read();
+        buffer += decoder.decode(value || new Uint8Array(), { stream: !done }).replace(/\r\n/g, '\n');
+        let separator;
+        while ((separator = buffer.indexOf('\n\n')) !== -1) {
+            handleEvent(buffer.slice(0, separator));
+            buffer = buffer.slice(separator + 2);
+        }
+        if (done) {
+            if (buffer.trim()) handleEvent(buffer);
+            break;
+        }
+    }
+    
+    if (!result.success && !result.error) {
+        result.error = 'The analysis stream ended before it finished';
+    }
+    return result;
+}
+
 // Common API connection error handler
 function handleApiConnectionError(error, containerId) {
     const container = document.getElementById(containerId);
@@ -1229,6 +1298,7 @@ if (document.readyState === 'loading') {
 // Make functions globally available
 window.handleApiConnectionError = handleApiConnectionError;
 window.apiCall = apiCall;
+window.streamAnalysis = streamAnalysis;
 window.showNotification = showNotification;
 window.formatDate = formatDate;
 window.safeGetElement = safeGetElement;
@@ -0,0 +1,337 @@
+// src/ai_cache.rs
+use actix_web::{web, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres, Row};
+use std::future::Future;
+use std::sync::Arc;
+
+use crate::ai_provider::TokenUsage;
+use crate::ApiState;
+
+/// Hours a cached analysis is served when AI_CACHE_TTL_HOURS isn't set; 0 turns the cache off
+pub const DEFAULT_TTL_HOURS: u64 = 24;
+
+/// How a request uses the shared cache: "use" (default) or "bypass", which skips the lookup
+/// and replaces any cached answer with a fresh one
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheMode {
+    #[default]
+    Use,
+    Bypass,
+}
+
+/// Cache outcome reported with each analysis
+#[derive(Debug, Serialize, Clone)]
+pub struct CacheStatus {
+    pub hit: bool,
+    pub bypassed: bool,
+    pub key: String,
+    /// When the served answer was generated, for hits
+    pub cached_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Identifies one analysis: the provider and model asked, the prompt (trimmed, with line endings
+/// normalized, plus any generation options) and the data context
+#[derive(Debug, Clone)]
+pub struct CacheKey {
+    provider: String,
+    model: String,
+    prompt_hash: String,
+    context_hash: String,
+}
+
+impl CacheKey {
+    /// `options` holds anything else that changes the answer, such as temperature or the context budget
+    pub fn new(provider: &str, model: &str, prompt: &str, context: Option<&serde_json::Value>, options: &str) -> Self {
+        // Only edge whitespace and line endings are ignored; spacing inside the prompt can change the answer
+        let prompt = prompt.trim().replace("\r\n", "\n");
+        CacheKey {
+            provider: provider.to_string(),
+            model: model.to_string(),
+            prompt_hash: fingerprint(&format!("{prompt}\u{0}{options}")),
+            context_hash: context.map(|c| fingerprint(&c.to_string())).unwrap_or_else(|| "none".to_string()),
+        }
+    }
+
+    pub fn id(&self) -> String {
+        format!("{}:{}:{}:{}", self.provider, self.model, self.prompt_hash, self.context_hash)
+    }
+}
+
+// SHA-256, so distinct prompts can't collide into one cached answer and keys survive toolchain upgrades
+fn fingerprint(value: &str) -> String {
+    format!("{:x}", Sha256::digest(value.as_bytes()))
+}
+
+/// A cached answer
+pub struct CachedAnalysis {
+    pub analysis: String,
+    pub token_usage: Option<TokenUsage>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Hours answers are cached for; 0 when the cache is off
+pub fn ttl_hours(data: &ApiState) -> u64 {
+    data.config.lock().unwrap().ai_cache_ttl_hours.unwrap_or(DEFAULT_TTL_HOURS)
+}
+
+/// An unexpired answer for `key`, counting the hit
+async fn lookup(pool: &Pool<Postgres>, key: &CacheKey) -> Option<CachedAnalysis> {
+    let row = sqlx::query(
+        r#"
+        UPDATE ai_response_cache
+        SET hit_count = hit_count + 1, last_hit_at = CURRENT_TIMESTAMP
+        WHERE cache_key = $1 AND expires_at > CURRENT_TIMESTAMP
+        RETURNING analysis, token_usage, created_at, expires_at
+        "#,
+    )
+    .bind(key.id())
+    .fetch_optional(pool)
+    .await;
+
+    match row {
+        Ok(row) => row.map(|row| CachedAnalysis {
+            analysis: row.get("analysis"),
+            token_usage: row
+                .get::<Option<serde_json::Value>, _>("token_usage")
+                .and_then(|usage| serde_json::from_value(usage).ok()),
+            created_at: row.get("created_at"),
+            expires_at: row.get("expires_at"),
+        }),
+        Err(e) => {
+            log::warn!("AI cache lookup failed: {e}");
+            None
+        }
+    }
+}
+
+/// Cache an answer for `ttl_hours`, replacing any earlier one; returns when it expires
+async fn store(pool: &Pool<Postgres>, key: &CacheKey, analysis: &str, token_usage: Option<&TokenUsage>, ttl_hours: u64) -> Option<DateTime<Utc>> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO ai_response_cache (cache_key, provider, model, prompt_hash, context_hash, analysis, token_usage, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, CURRENT_TIMESTAMP + make_interval(hours => $8))
+        ON CONFLICT (cache_key) DO UPDATE SET
+            analysis = EXCLUDED.analysis,
+            token_usage = EXCLUDED.token_usage,
+            created_at = CURRENT_TIMESTAMP,
+            expires_at = EXCLUDED.expires_at,
+            hit_count = 0,
+            last_hit_at = NULL
+        RETURNING expires_at
+        "#,
+    )
+    .bind(key.id())
+    .bind(&key.provider)
+    .bind(&key.model)
+    .bind(&key.prompt_hash)
+    .bind(&key.context_hash)
+    .bind(analysis)
+    .bind(token_usage.and_then(|usage| serde_json::to_value(usage).ok()))
+    .bind(ttl_hours.min(i32::MAX as u64) as i32)
+    .fetch_one(pool)
+    .await;
+
+    match result {
+        Ok(row) => Some(row.get("expires_at")),
+        Err(e) => {
+            log::warn!("AI cache store failed: {e}");
+            None
+        }
+    }
+}
+
+/// The cached answer for `key` and its status, unless the cache is off, bypassed or has none
+pub async fn hit(data: &ApiState, key: &CacheKey, mode: CacheMode) -> Option<(CachedAnalysis, CacheStatus)> {
+    if ttl_hours(data) == 0 || mode == CacheMode::Bypass {
+        return None;
+    }
+    let cached = lookup(&data.db, key).await?;
+    let status = CacheStatus {
+        hit: true,
+        bypassed: false,
+        key: key.id(),
+        cached_at: Some(cached.created_at),
+        expires_at: Some(cached.expires_at),
+    };
+    Some((cached, status))
+}
+
+/// Cache a fresh answer; the status is None when the cache is off
+pub async fn record(data: &ApiState, key: &CacheKey, mode: CacheMode, analysis: &str, token_usage: Option<&TokenUsage>) -> Option<CacheStatus> {
+    let ttl = ttl_hours(data);
+    if ttl == 0 {
+        return None;
+    }
+    let expires_at = store(&data.db, key, analysis, token_usage, ttl).await;
+    Some(CacheStatus {
+        hit: false,
+        bypassed: mode == CacheMode::Bypass,
+        key: key.id(),
+        cached_at: None,
+        expires_at,
+    })
+}
+
+/// Serve `key` from the cache unless bypassed, otherwise run `compute` and cache a successful answer.
+/// The status is None when the cache is off.
+pub async fn cached<F>(data: &ApiState, key: CacheKey, mode: CacheMode, compute: F) -> (anyhow::Result<(String, Option<TokenUsage>)>, Option<CacheStatus>)
+where
+    F: Future<Output = anyhow::Result<(String, Option<TokenUsage>)>>,
+{
+    if let Some((cached, status)) = hit(data, &key, mode).await {
+        return (Ok((cached.analysis, cached.token_usage)), Some(status));
+    }
+    let result = compute.await;
+    let status = match &result {
+        Ok((analysis, token_usage)) => record(data, &key, mode, analysis, token_usage.as_ref()).await,
+        Err(_) => None,
+    };
+    (result, status)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InvalidateQuery {
+    pub key: Option<String>,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    /// Only remove entries that have already expired
+    #[serde(default)]
+    pub expired: bool,
+    /// Required to clear the whole cache when no other filter is given
+    #[serde(default)]
+    pub all: bool,
+}
+
+/// Remove cached answers matching every given filter; clearing the whole cache takes an explicit all=true
+pub async fn invalidate(
+    data: web::Data<Arc<ApiState>>,
+    query: web::Query<InvalidateQuery>,
+) -> Result<HttpResponse> {
+    let filtered = [&query.key, &query.provider, &query.model].iter().any(|f| f.as_deref().is_some_and(|v| !v.is_empty()));
+    if !filtered && !query.expired && !query.all {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "success": false,
+            "error": "Give key, provider, model or expired=true, or all=true to clear the whole cache",
+        })));
+    }
+
+    let result = sqlx::query(
+        r#"
+        DELETE FROM ai_response_cache
+        WHERE ($1::TEXT IS NULL OR cache_key = $1)
+          AND ($2::TEXT IS NULL OR provider = $2)
+          AND ($3::TEXT IS NULL OR model = $3)
+          AND (NOT $4 OR expires_at <= CURRENT_TIMESTAMP)
+        "#,
+    )
+    .bind(query.key.as_deref().filter(|k| !k.is_empty()))
+    .bind(query.provider.as_deref().filter(|p| !p.is_empty()))
+    .bind(query.model.as_deref().filter(|m| !m.is_empty()))
+    .bind(query.expired)
+    .execute(&data.db)
+    .await;
+
+    match result {
+        Ok(done) => Ok(HttpResponse::Ok().json(json!({
+            "success": true,
+            "deleted": done.rows_affected(),
+        }))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({
+            "success": false,
+            "error": format!("Failed to clear AI cache: {e}"),
+        }))),
+    }
+}
+
+/// Entry and hit counts per provider and model
+pub async fn get_cache_stats(data: web::Data<Arc<ApiState>>) -> Result<HttpResponse> {
+    let rows = sqlx::query(
+        r#"
+        SELECT provider, model,
+               COUNT(*) AS entries,
+               COUNT(*) FILTER (WHERE expires_at > CURRENT_TIMESTAMP) AS live,
+               COALESCE(SUM(hit_count), 0)::BIGINT AS hits,
+               MAX(last_hit_at) AS last_hit_at
+        FROM ai_response_cache
+        GROUP BY provider, model
+        ORDER BY provider, model
+        "#,
+    )
+    .fetch_all(&data.db)
+    .await;
+
+    match rows {
+        Ok(rows) => {
+            let groups: Vec<serde_json::Value> = rows
+                .iter()
+                .map(|row| json!({
+                    "provider": row.get::<String, _>("provider"),
+                    "model": row.get::<String, _>("model"),
+                    "entries": row.get::<i64, _>("entries"),
+                    "live": row.get::<i64, _>("live"),
+                    "hits": row.get::<i64, _>("hits"),
+                    "last_hit_at": row.get::<Option<DateTime<Utc>>, _>("last_hit_at"),
+                }))
+                .collect();
+            let total = |field: &str| groups.iter().filter_map(|g| g[field].as_i64()).sum::<i64>();
+            Ok(HttpResponse::Ok().json(json!({
+                "success": true,
+                "ttl_hours": ttl_hours(&data),
+                "entries": total("entries"),
+                "live": total("live"),
+                "hits": total("hits"),
+                "groups": groups,
+            })))
+        }
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({
+            "success": false,
+            "error": format!("Failed to read AI cache: {e}"),
+        }))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn key(prompt: &str, context: Option<&serde_json::Value>, options: &str) -> String {
+        CacheKey::new("gemini", "gemini-pro", prompt, context, options).id()
+    }
+
+    #[test]
+    fn edge_whitespace_and_line_endings_share_a_key() {
+        let base = key("Summarize\nthe data", None, "");
+        assert_eq!(key("  Summarize\nthe data\n\n", None, ""), base);
+        assert_eq!(key("Summarize\r\nthe data", None, ""), base);
+        assert_eq!(key("\tSummarize\r\nthe data\r\n", None, ""), base);
+    }
+
+    #[test]
+    fn prompt_spacing_options_and_context_change_the_key() {
+        let context = json!({ "rows": [1, 2] });
+        let base = key("Summarize the data", Some(&context), "temperature=0.2");
+
+        assert_ne!(key("Summarize  the data", Some(&context), "temperature=0.2"), base);
+        assert_ne!(key("Summarize\nthe data", Some(&context), "temperature=0.2"), base);
+        assert_ne!(key("Summarize the data", Some(&context), "temperature=0.7"), base);
+        assert_ne!(key("Summarize the data", Some(&json!({ "rows": [1, 3] })), "temperature=0.2"), base);
+        assert_ne!(key("Summarize the data", None, "temperature=0.2"), base);
+        assert_eq!(key("Summarize the data", Some(&context), "temperature=0.2"), base);
+    }
+
+    #[test]
+    fn keys_hold_sha256_hex_digests() {
+        let key = CacheKey::new("claude-cli", "default", "hi", Some(&json!([])), "");
+        assert_eq!(key.prompt_hash.len(), 64);
+        assert_eq!(key.context_hash.len(), 64);
+        assert_eq!(CacheKey::new("claude-cli", "default", "hi", None, "").context_hash, "none");
+    }
+}
@@ -0,0 +1,564 @@
+// src/ai_provider.rs
+use actix_web::{web, HttpResponse, Result};
+use anyhow::Context;
+use notify::{Config as NotifyConfig, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::path::Path;
+use std::sync::mpsc::channel;
+use std::sync::{Arc, RwLock};
+
+use crate::ai_cache::{self, CacheKey, CacheMode, CacheStatus};
+use crate::ai_usage::{self, Call};
+use crate::data_context::{self, ContextReport};
+use crate::project_index::BoxFuture;
+use crate::{claude_insights, gemini_insights, ApiState};
+
+/// Provider list shared with the settings UI
+pub const PROVIDERS_PATH: &str = "config/cli.csv";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TokenUsage {
+    pub prompt_tokens: Option<u32>,
+    pub completion_tokens: Option<u32>,
+    pub total_tokens: Option<u32>,
+}
+
+/// A completed analysis
+#[derive(Debug, Clone)]
+pub struct AiResponse {
+    pub text: String,
+    pub token_usage: Option<TokenUsage>,
+}
+
+/// Something that can answer a prompt: a hosted API or a local CLI
+pub trait AiProvider: Send + Sync {
+    fn name(&self) -> &str;
+    fn model(&self) -> &str;
+    /// Whether the provider has what it needs (an API key, for hosted APIs) to be called
+    fn available(&self) -> bool;
+    /// Why the provider can't be called, reported when `available` is false
+    fn unavailable_reason(&self) -> String {
+        format!("AI provider {} is not configured", self.name())
+    }
+    /// The model's context window, in tokens, when configured
+    fn context_window(&self) -> Option<u32>;
+    /// Most tokens a reply may use, when configured
+    fn max_output_tokens(&self) -> Option<u32>;
+    fn analyze<'a>(&'a self, prompt: &'a str) -> BoxFuture<'a, anyhow::Result<AiResponse>>;
+}
+
+/// One row of config/cli.csv
+#[derive(Debug, Clone, Serialize)]
+pub struct ProviderConfig {
+    pub name: String,
+    pub id: String,
+    pub description: String,
+    pub auth_required: bool,
+    pub default_enabled: bool,
+    pub api_endpoint: String,
+    /// The model's context window, in tokens
+    pub token_limit: Option<u32>,
+    /// Most tokens a reply may use; left to the provider's default when empty
+    pub max_output_tokens: Option<u32>,
+    /// Sampling temperature; left to the provider's default when empty
+    pub temperature: Option<f64>,
+    pub model: String,
+    /// "gemini", "claude-cli" or "openai" (any OpenAI-compatible chat completions endpoint)
+    pub api_style: String,
+}
+
+/// Built-in providers, matching config/cli.csv
+fn default_provider_configs() -> Vec<ProviderConfig> {
+    let provider = |name: &str, id: &str, default_enabled: bool, api_endpoint: &str, token_limit: u32, max_output_tokens: Option<u32>, model: &str, api_style: &str| ProviderConfig {
+        name: name.to_string(),
+        id: id.to_string(),
+        description: String::new(),
+        auth_required: api_style != "claude-cli",
+        default_enabled,
+        api_endpoint: api_endpoint.to_string(),
+        token_limit: Some(token_limit),
+        max_output_tokens,
+        temperature: None,
+        model: model.to_string(),
+        api_style: api_style.to_string(),
+    };
+    vec![
+        provider("claude", "claude-code-cli", true, "https://api.anthropic.com/v1/messages", 100000, None, "claude-3-5-sonnet", "claude-cli"),
+        provider("qwen", "qwen-cli", false, "https://dashscope.aliyuncs.com/compatible-mode/v1/chat/completions", 128000, Some(8192), "qwen-plus", "openai"),
+        provider("gemini", "gemini-cli", false, gemini_insights::GEMINI_API_BASE, 32768, None, "gemini-1.5-pro", "gemini"),
+    ]
+}
+
+/// Rows before the api_style column was added are inferred from the provider name
+fn infer_api_style(name: &str) -> &'static str {
+    match name {
+        "claude" => "claude-cli",
+        "gemini" => "gemini",
+        _ => "openai",
+    }
+}
+
+pub fn parse_provider_configs(text: &str) -> anyhow::Result<Vec<ProviderConfig>> {
+    let mut rows = crate::csv::parse_csv(text).into_iter();
+    let headers: Vec<String> = rows.next().context("Provider list is empty")?.iter().map(|h| h.trim().to_lowercase()).collect();
+    let column = |name: &str| headers.iter().position(|h| h == name);
+    let name_idx = column("name").context("Provider list has no name column")?;
+
+    let mut configs = Vec::new();
+    for row in rows {
+        let field = |name: &str| column(name).and_then(|i| row.get(i)).map(|v| v.trim().to_string()).unwrap_or_default();
+        let flag = |name: &str| field(name).eq_ignore_ascii_case("true");
+        let name = row.get(name_idx).map(|v| v.trim().to_lowercase()).unwrap_or_default();
+        if name.is_empty() {
+            continue;
+        }
+        let api_style = match field("api_style") {
+            style if style.is_empty() => infer_api_style(&name).to_string(),
+            style => style.to_lowercase(),
+        };
+        configs.push(ProviderConfig {
+            id: field("id"),
+            description: field("description"),
+            auth_required: flag("auth_required"),
+            default_enabled: flag("default_enabled"),
+            api_endpoint: field("api_endpoint"),
+            token_limit: field("token_limit").parse().ok(),
+            max_output_tokens: field("max_output_tokens").parse().ok(),
+            temperature: field("temperature").parse().ok(),
+            model: field("model"),
+            api_style,
+            name,
+        });
+    }
+    Ok(configs)
+}
+
+/// Read config/cli.csv, falling back to the built-in providers
+pub fn load_provider_configs() -> Vec<ProviderConfig> {
+    match std::fs::read_to_string(PROVIDERS_PATH) {
+        Ok(text) => parse_provider_configs(&text).unwrap_or_else(|e| {
+            log::warn!("Failed to parse {PROVIDERS_PATH}: {e}");
+            default_provider_configs()
+        }),
+        Err(_) => default_provider_configs(),
+    }
+}
+
+/// The provider list the server uses; the watcher swaps in a new one when config/cli.csv changes
+pub type SharedProviderConfigs = Arc<RwLock<Arc<Vec<ProviderConfig>>>>;
+
+// Watch config/cli.csv so provider edits apply without a restart
+pub fn start_watcher(configs: SharedProviderConfigs) -> anyhow::Result<()> {
+    let path = Path::new(PROVIDERS_PATH);
+    let Some(dir) = path.parent().filter(|dir| dir.exists()) else {
+        log::warn!("No config directory found; AI providers will not hot reload");
+        return Ok(());
+    };
+    let file_name = path.file_name().map(|name| name.to_os_string());
+
+    let (tx, rx) = channel();
+    let mut watcher = RecommendedWatcher::new(tx, NotifyConfig::default())?;
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    log::info!("Started watching {PROVIDERS_PATH} for changes");
+
+    // notify delivers events over a blocking channel, so receive them on their own thread
+    std::thread::spawn(move || {
+        let _watcher = watcher;
+        for event in rx {
+            match event {
+                Ok(Event { kind: EventKind::Modify(_) | EventKind::Create(_), paths, .. })
+                    if paths.iter().any(|p| p.file_name() == file_name.as_deref()) => {
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+
+                    *configs.write().unwrap() = Arc::new(load_provider_configs());
+                    log::info!("AI providers reloaded");
+                }
+                Ok(_) => {} // Ignore other events
+                Err(e) => log::error!("File watcher error: {e}"),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// API key for a hosted provider: GEMINI_API_KEY from the server config for Gemini,
+/// otherwise the {NAME}_API_KEY environment variable, e.g. QWEN_API_KEY
+fn api_key_env(name: &str) -> String {
+    format!("{}_API_KEY", name.to_uppercase().replace(['-', ' '], "_"))
+}
+
+pub struct GeminiProvider {
+    config: ProviderConfig,
+    api_key: Option<String>,
+    settings: gemini_insights::GenerationSettings,
+    /// The model column names a model outside the Gemini allowlist
+    disallowed_model: Option<String>,
+}
+
+impl AiProvider for GeminiProvider {
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn model(&self) -> &str {
+        &self.settings.model
+    }
+
+    fn available(&self) -> bool {
+        self.api_key.is_some() && self.disallowed_model.is_none()
+    }
+
+    fn unavailable_reason(&self) -> String {
+        match &self.disallowed_model {
+            Some(model) => format!("AI provider {} uses model {model}, which is not an allowed Gemini model", self.config.name),
+            None => format!("AI provider {} is not configured", self.config.name),
+        }
+    }
+
+    fn context_window(&self) -> Option<u32> {
+        self.config.token_limit
+    }
+
+    fn max_output_tokens(&self) -> Option<u32> {
+        Some(self.settings.max_output_tokens)
+    }
+
+    fn analyze<'a>(&'a self, prompt: &'a str) -> BoxFuture<'a, anyhow::Result<AiResponse>> {
+        Box::pin(async move {
+            if let Some(model) = &self.disallowed_model {
+                anyhow::bail!("Model {model} is not allowed for {}", self.config.name);
+            }
+            let api_key = self.api_key.as_deref().context("Gemini API key not configured")?;
+            let endpoint = if self.config.api_endpoint.is_empty() { gemini_insights::GEMINI_API_BASE } else { &self.config.api_endpoint };
+            let (text, token_usage) = gemini_insights::call_gemini_api(api_key, endpoint, &self.settings, prompt).await?;
+            Ok(AiResponse { text, token_usage })
+        })
+    }
+}
+
+pub struct ClaudeCliProvider {
+    config: ProviderConfig,
+}
+
+impl AiProvider for ClaudeCliProvider {
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn model(&self) -> &str {
+        // The CLI runs without --model, so it answers with whatever model it is configured for
+        "default"
+    }
+
+    fn available(&self) -> bool {
+        true
+    }
+
+    fn context_window(&self) -> Option<u32> {
+        self.config.token_limit
+    }
+
+    fn max_output_tokens(&self) -> Option<u32> {
+        self.config.max_output_tokens
+    }
+
+    fn analyze<'a>(&'a self, prompt: &'a str) -> BoxFuture<'a, anyhow::Result<AiResponse>> {
+        Box::pin(async move {
+            let (text, token_usage) = claude_insights::call_claude_code_cli(prompt, &None).await?;
+            Ok(AiResponse { text, token_usage })
+        })
+    }
+}
+
+/// Any endpoint speaking the OpenAI chat completions API: Qwen (DashScope compatible mode),
+/// Ollama, llama.cpp's server, vLLM and so on
+pub struct OpenAiCompatibleProvider {
+    config: ProviderConfig,
+    api_key: Option<String>,
+}
+
+impl AiProvider for OpenAiCompatibleProvider {
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn model(&self) -> &str {
+        &self.config.model
+    }
+
+    fn available(&self) -> bool {
+        !self.config.api_endpoint.is_empty() && (self.api_key.is_some() || !self.config.auth_required)
+    }
+
+    fn context_window(&self) -> Option<u32> {
+        self.config.token_limit
+    }
+
+    fn max_output_tokens(&self) -> Option<u32> {
+        self.config.max_output_tokens
+    }
+
+    fn analyze<'a>(&'a self, prompt: &'a str) -> BoxFuture<'a, anyhow::Result<AiResponse>> {
+        Box::pin(async move {
+            if self.config.auth_required && self.api_key.is_none() {
+                anyhow::bail!("{} is not configured; set {}", self.config.name, api_key_env(&self.config.name));
+            }
+
+            let mut request_body = json!({
+                "model": self.config.model,
+                "messages": [{ "role": "user", "content": prompt }],
+            });
+            if let Some(temperature) = self.config.temperature {
+                request_body["temperature"] = json!(temperature);
+            }
+            if let Some(max_output_tokens) = self.config.max_output_tokens {
+                request_body["max_tokens"] = json!(max_output_tokens);
+            }
+
+            let mut request = reqwest::Client::new()
+                .post(&self.config.api_endpoint)
+                .json(&request_body)
+                .timeout(std::time::Duration::from_secs(120));
+            if let Some(api_key) = &self.api_key {
+                request = request.bearer_auth(api_key);
+            }
+
+            let response = request
+                .send()
+                .await
+                .with_context(|| format!("Failed to reach {} at {}", self.config.name, self.config.api_endpoint))?;
+            let status = response.status();
+            if !status.is_success() {
+                let error_text = response.text().await.unwrap_or_default();
+                anyhow::bail!("{} API error {status}: {error_text}", self.config.name);
+            }
+
+            let response_json: serde_json::Value = response
+                .json()
+                .await
+                .with_context(|| format!("Failed to parse {} response", self.config.name))?;
+            let text = response_json
+                .pointer("/choices/0/message/content")
+                .and_then(|t| t.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Invalid {} response format: {response_json}", self.config.name))?;
+
+            let token_usage = response_json.get("usage").map(|usage| {
+                let count = |key: &str| usage.get(key).and_then(|v| v.as_u64()).map(|v| v as u32);
+                TokenUsage {
+                    prompt_tokens: count("prompt_tokens"),
+                    completion_tokens: count("completion_tokens"),
+                    total_tokens: count("total_tokens"),
+                }
+            });
+
+            Ok(AiResponse { text: text.trim().to_string(), token_usage })
+        })
+    }
+}
+
+/// Providers built from config/cli.csv, in file order
+pub struct ProviderRegistry {
+    providers: Vec<(ProviderConfig, Box<dyn AiProvider>)>,
+}
+
+impl ProviderRegistry {
+    /// Build providers from the cached provider list and the current server config
+    pub fn load(state: &ApiState) -> Self {
+        let gemini_key = gemini_insights::configured_api_key(state);
+        let gemini_settings = gemini_insights::GenerationSettings::from_config(state);
+        let gemini_models = gemini_insights::allowed_models(state);
+        let configs = state.provider_configs.read().unwrap().clone();
+        let providers = configs
+            .iter()
+            .cloned()
+            .filter_map(|config| {
+                let env_key = std::env::var(api_key_env(&config.name)).ok().filter(|k| !k.trim().is_empty());
+                let provider: Box<dyn AiProvider> = match config.api_style.as_str() {
+                    "gemini" => {
+                        // The model, temperature and max_output_tokens columns override the GEMINI_* settings,
+                        // but the model must still be one requests could choose
+                        let mut settings = gemini_settings.clone();
+                        let mut disallowed_model = None;
+                        if !config.model.is_empty() {
+                            if gemini_models.contains(&config.model) {
+                                settings.model = config.model.clone();
+                            } else {
+                                disallowed_model = Some(config.model.clone());
+                            }
+                        }
+                        if let Some(temperature) = config.temperature {
+                            settings.temperature = temperature as f32;
+                        }
+                        if let Some(max_output_tokens) = config.max_output_tokens {
+                            settings.max_output_tokens = max_output_tokens;
+                        }
+                        Box::new(GeminiProvider { config: config.clone(), api_key: gemini_key.clone().or(env_key), settings, disallowed_model })
+                    }
+                    "claude-cli" => Box::new(ClaudeCliProvider { config: config.clone() }),
+                    "openai" => Box::new(OpenAiCompatibleProvider { config: config.clone(), api_key: env_key }),
+                    other => {
+                        log::warn!("Skipping AI provider {}: unknown api_style {other}", config.name);
+                        return None;
+                    }
+                };
+                Some((config, provider))
+            })
+            .collect();
+        ProviderRegistry { providers }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn AiProvider> {
+        self.providers
+            .iter()
+            .find(|(config, _)| config.name.eq_ignore_ascii_case(name) || config.id.eq_ignore_ascii_case(name))
+            .map(|(_, provider)| provider.as_ref())
+    }
+
+    /// The first default-enabled provider that can be called, else the first available one
+    pub fn default_provider(&self) -> Option<&dyn AiProvider> {
+        let available = || self.providers.iter().filter(|(_, p)| p.available());
+        available()
+            .find(|(config, _)| config.default_enabled)
+            .or_else(|| available().next())
+            .map(|(_, provider)| provider.as_ref())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProviderQuery {
+    pub provider: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnalysisRequest {
+    pub prompt: String,
+    /// Data appended to the prompt; large tables are summarized to fit max_context_tokens
+    #[serde(default, alias = "dataset_info")]
+    pub data_context: Option<serde_json::Value>,
+    /// Estimated tokens the data context may use, capped by the provider's token_limit
+    #[serde(default)]
+    pub max_context_tokens: Option<usize>,
+    /// "bypass" skips the shared cache and refreshes it
+    #[serde(default)]
+    pub cache: CacheMode,
+    /// Who is asking, recorded in the AI usage ledger
+    #[serde(default)]
+    pub user: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnalysisResponse {
+    pub success: bool,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub analysis: Option<String>,
+    pub error: Option<String>,
+    pub token_usage: Option<TokenUsage>,
+    pub context: Option<ContextReport>,
+    /// Whether the answer came from the shared cache; absent when the cache is off
+    pub cache: Option<CacheStatus>,
+}
+
+fn analysis_error(provider: Option<&dyn AiProvider>, error: String) -> AnalysisResponse {
+    AnalysisResponse {
+        success: false,
+        provider: provider.map(|p| p.name().to_string()),
+        model: provider.map(|p| p.model().to_string()),
+        analysis: None,
+        error: Some(error),
+        token_usage: None,
+        context: None,
+        cache: None,
+    }
+}
+
+/// Run a prompt through the provider named by ?provider=, or the default provider
+pub async fn analyze(
+    data: web::Data<Arc<ApiState>>,
+    query: web::Query<ProviderQuery>,
+    req: web::Json<AnalysisRequest>,
+) -> Result<HttpResponse> {
+    let registry = ProviderRegistry::load(&data);
+    let provider = match query.provider.as_deref().filter(|p| !p.trim().is_empty()) {
+        Some(name) => match registry.get(name.trim()) {
+            Some(provider) => provider,
+            None => return Ok(HttpResponse::BadRequest().json(analysis_error(None, format!("Unknown AI provider: {name}")))),
+        },
+        None => match registry.default_provider() {
+            Some(provider) => provider,
+            None => return Ok(HttpResponse::BadRequest().json(analysis_error(None, "No AI provider is configured".to_string()))),
+        },
+    };
+
+    if !provider.available() {
+        let error = provider.unavailable_reason();
+        return Ok(HttpResponse::BadRequest().json(analysis_error(Some(provider), error)));
+    }
+
+    let budget = data_context::context_budget(req.max_context_tokens, &req.prompt, provider.context_window(), provider.max_output_tokens());
+    let (prompt, context) = data_context::append_to_prompt(&req.prompt, req.data_context.as_ref(), budget);
+
+    let options = format!("{budget}");
+    let key = CacheKey::new(provider.name(), provider.model(), &req.prompt, req.data_context.as_ref(), &options);
+    let call = Call::new(provider.name(), provider.model(), req.user.as_deref(), "ai/analyze").estimated(&prompt, provider.max_output_tokens());
+    let compute = ai_usage::metered(&data, &call, async {
+        provider.analyze(&prompt).await.map(|response| (response.text, response.token_usage))
+    });
+    let (result, cache) = ai_cache::cached(&data, key, req.cache, compute).await;
+    match result {
+        Ok((analysis, token_usage)) => Ok(HttpResponse::Ok().json(AnalysisResponse {
+            success: true,
+            provider: Some(provider.name().to_string()),
+            model: Some(provider.model().to_string()),
+            analysis: Some(analysis),
+            error: None,
+            token_usage,
+            context,
+            cache,
+        })),
+        Err(e) => {
+            log::error!("{} analysis failed: {e:?}", provider.name());
+            let mut response = analysis_error(Some(provider), e.to_string());
+            response.context = context;
+            response.cache = cache;
+            if ai_usage::is_budget_exceeded(&e) {
+                return Ok(HttpResponse::TooManyRequests().json(response));
+            }
+            Ok(HttpResponse::InternalServerError().json(response))
+        }
+    }
+}
+
+/// Configured providers and whether each can be called
+pub async fn list_providers(data: web::Data<Arc<ApiState>>) -> Result<HttpResponse> {
+    let registry = ProviderRegistry::load(&data);
+    let default = registry.default_provider().map(|p| p.name().to_string());
+    let providers: Vec<serde_json::Value> = registry
+        .providers
+        .iter()
+        .map(|(config, provider)| json!({
+            "name": config.name,
+            "id": config.id,
+            "description": config.description,
+            "api_style": config.api_style,
+            "api_endpoint": config.api_endpoint,
+            "model": provider.model(),
+            "token_limit": config.token_limit,
+            "max_output_tokens": config.max_output_tokens,
+            "temperature": config.temperature,
+            "default_enabled": config.default_enabled,
+            "available": provider.available(),
+            "unavailable_reason": (!provider.available()).then(|| provider.unavailable_reason()),
+            "api_key_env": (config.api_style == "openai" && config.auth_required).then(|| api_key_env(&config.name)),
+        }))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "path": PROVIDERS_PATH,
+        "default": default,
+        "providers": providers
+    })))
+}
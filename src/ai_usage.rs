@@ -0,0 +1,473 @@
+// src/ai_usage.rs
+use actix_web::{web, HttpResponse, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{Pool, Postgres, Row};
+use std::future::Future;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::ai_provider::TokenUsage;
+use crate::ApiState;
+
+/// Days of history GET /api/ai/usage covers by default
+const DEFAULT_USAGE_DAYS: i32 = 30;
+
+/// Tokens counted for a ledger row: the reported total, or prompt plus completion
+const TOKENS_SQL: &str = "COALESCE(total_tokens, COALESCE(prompt_tokens, 0) + COALESCE(completion_tokens, 0))";
+
+/// Tokens counted against the budgets: settled rows as above, in-flight ("pending") calls at their reservation.
+/// A reservation left behind by a crash stops counting after an hour.
+const BUDGET_TOKENS_SQL: &str = "CASE WHEN status = 'pending' THEN \
+    CASE WHEN created_at > CURRENT_TIMESTAMP - INTERVAL '1 hour' THEN COALESCE(reserved_tokens, 0) ELSE 0 END \
+    ELSE COALESCE(total_tokens, COALESCE(prompt_tokens, 0) + COALESCE(completion_tokens, 0)) END";
+
+/// Output tokens reserved for providers that don't configure a reply limit, such as the Claude Code CLI
+const DEFAULT_OUTPUT_RESERVE: u32 = 4096;
+
+/// Rough characters-per-token ratio for estimates, matching data_context
+const CHARS_PER_TOKEN: usize = 4;
+
+fn estimate_tokens(chars: usize) -> u32 {
+    chars.div_ceil(CHARS_PER_TOKEN).min(u32::MAX as usize) as u32
+}
+
+/// One call to an AI provider, as recorded in the ai_usage ledger
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub provider: String,
+    pub model: String,
+    /// Who asked, when the client says
+    pub user: Option<String>,
+    pub endpoint: &'static str,
+    /// Estimated prompt tokens, reserved against the budgets with `max_output_tokens` until the call settles
+    pub prompt_tokens: u32,
+    pub max_output_tokens: u32,
+}
+
+impl Call {
+    pub fn new(provider: &str, model: &str, user: Option<&str>, endpoint: &'static str) -> Self {
+        Call {
+            provider: provider.to_string(),
+            model: model.to_string(),
+            user: user.map(str::trim).filter(|u| !u.is_empty()).map(String::from),
+            endpoint,
+            prompt_tokens: 0,
+            max_output_tokens: DEFAULT_OUTPUT_RESERVE,
+        }
+    }
+
+    /// Size the call's reservation from the prompt it sends and the longest reply it allows
+    pub fn estimated(mut self, prompt: &str, max_output_tokens: Option<u32>) -> Self {
+        self.prompt_tokens = estimate_tokens(prompt.len());
+        self.max_output_tokens = max_output_tokens.unwrap_or(DEFAULT_OUTPUT_RESERVE);
+        self
+    }
+
+    fn reservation(&self) -> u64 {
+        self.prompt_tokens as u64 + self.max_output_tokens as u64
+    }
+
+    /// Usage for a call the provider didn't report tokens for: the prompt estimate plus the text received
+    fn estimated_usage(&self, output: &str) -> TokenUsage {
+        let completion_tokens = estimate_tokens(output.len());
+        TokenUsage {
+            prompt_tokens: Some(self.prompt_tokens),
+            completion_tokens: Some(completion_tokens),
+            total_tokens: Some(self.prompt_tokens.saturating_add(completion_tokens)),
+        }
+    }
+}
+
+/// An error from a call that had already produced some output, such as a stream cut off midway,
+/// so the ledger can count what was generated. Displays as the underlying error.
+#[derive(Debug)]
+pub struct PartialOutput {
+    error: anyhow::Error,
+    text: String,
+}
+
+impl std::fmt::Display for PartialOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl std::error::Error for PartialOutput {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.error.source()
+    }
+}
+
+/// Attach the text received so far to a failed call's error; unchanged when nothing was received
+pub fn partial_output(error: anyhow::Error, text: &str) -> anyhow::Error {
+    if text.is_empty() {
+        error
+    } else {
+        PartialOutput { error, text: text.to_string() }.into()
+    }
+}
+
+/// A call refused because a token budget can't cover it
+#[derive(Debug, Clone, Serialize)]
+pub struct BudgetExceeded {
+    pub period: &'static str,
+    pub limit: u64,
+    pub used: u64,
+    /// Tokens the refused call would have reserved
+    pub requested: u64,
+}
+
+impl std::fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.used >= self.limit {
+            write!(f, "The {} AI token budget of {} is used up ({} tokens so far)", self.period, self.limit, self.used)
+        } else {
+            write!(
+                f,
+                "The {} AI token budget of {} has {} tokens left, fewer than this call may use (about {})",
+                self.period,
+                self.limit,
+                self.limit - self.used,
+                self.requested
+            )
+        }
+    }
+}
+
+impl std::error::Error for BudgetExceeded {}
+
+/// Whether an analysis failed because a budget is used up, so handlers can answer 429
+pub fn is_budget_exceeded(error: &anyhow::Error) -> bool {
+    error.chain().any(|e| e.is::<BudgetExceeded>())
+}
+
+#[derive(Debug, Serialize)]
+pub struct BudgetStatus {
+    /// "daily" or "monthly", in UTC
+    pub period: &'static str,
+    pub limit: Option<u64>,
+    pub used: u64,
+    pub remaining: Option<u64>,
+    pub exceeded: bool,
+}
+
+fn budgets(data: &ApiState) -> (Option<u64>, Option<u64>) {
+    let config_guard = data.config.lock().unwrap();
+    (config_guard.ai_daily_token_budget, config_guard.ai_monthly_token_budget)
+}
+
+/// Tokens used today and this month against AI_DAILY_TOKEN_BUDGET and AI_MONTHLY_TOKEN_BUDGET,
+/// counting in-flight calls at their reservation
+pub async fn budget_status(data: &ApiState) -> Result<Vec<BudgetStatus>, sqlx::Error> {
+    let (daily, monthly) = budgets(data);
+    let mut conn = data.db.acquire().await?;
+    budget_usage(&mut conn, daily, monthly).await
+}
+
+async fn budget_usage(conn: &mut sqlx::PgConnection, daily: Option<u64>, monthly: Option<u64>) -> Result<Vec<BudgetStatus>, sqlx::Error> {
+    let row = sqlx::query(&format!(
+        r#"
+        SELECT
+            COALESCE(SUM({BUDGET_TOKENS_SQL}) FILTER (WHERE created_at >= date_trunc('day', CURRENT_TIMESTAMP AT TIME ZONE 'UTC') AT TIME ZONE 'UTC'), 0)::BIGINT AS today,
+            COALESCE(SUM({BUDGET_TOKENS_SQL}), 0)::BIGINT AS month
+        FROM ai_usage
+        WHERE created_at >= date_trunc('month', CURRENT_TIMESTAMP AT TIME ZONE 'UTC') AT TIME ZONE 'UTC'
+        "#
+    ))
+    .fetch_one(conn)
+    .await?;
+
+    let status = |period: &'static str, limit: Option<u64>, used: i64| {
+        let used = used.max(0) as u64;
+        BudgetStatus {
+            period,
+            limit,
+            used,
+            remaining: limit.map(|limit| limit.saturating_sub(used)),
+            exceeded: limit.is_some_and(|limit| used >= limit),
+        }
+    };
+    Ok(vec![
+        status("daily", daily, row.get("today")),
+        status("monthly", monthly, row.get("month")),
+    ])
+}
+
+/// Reserve the call's estimated tokens as a "pending" ledger row, or Err(BudgetExceeded) when a configured
+/// budget can't cover them. The check and the reservation happen under one advisory lock, so concurrent
+/// calls can't together overshoot a budget. Returns the row to settle, or None when the ledger is
+/// unavailable, which doesn't block calls.
+async fn reserve(data: &ApiState, call: &Call) -> anyhow::Result<Option<Uuid>> {
+    let (daily, monthly) = budgets(data);
+    let requested = call.reservation();
+
+    let reserved: Result<Result<Uuid, BudgetExceeded>, sqlx::Error> = async {
+        let mut tx = data.db.begin().await?;
+        if daily.is_some() || monthly.is_some() {
+            sqlx::query("SELECT pg_advisory_xact_lock(hashtext('ai_usage_budget'))").execute(&mut *tx).await?;
+            let over = budget_usage(&mut tx, daily, monthly)
+                .await?
+                .into_iter()
+                .find(|b| b.limit.is_some_and(|limit| b.used + requested > limit));
+            if let Some(budget) = over {
+                return Ok(Err(BudgetExceeded { period: budget.period, limit: budget.limit.unwrap_or(0), used: budget.used, requested }));
+            }
+        }
+        let id: Uuid = sqlx::query_scalar(
+            r#"
+            INSERT INTO ai_usage (provider, model, user_id, endpoint, reserved_tokens, status)
+            VALUES ($1, $2, $3, $4, $5, 'pending')
+            RETURNING id
+            "#,
+        )
+        .bind(&call.provider)
+        .bind(&call.model)
+        .bind(&call.user)
+        .bind(call.endpoint)
+        .bind(requested.min(i32::MAX as u64) as i32)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(Ok(id))
+    }
+    .await;
+
+    match reserved {
+        Ok(Ok(id)) => Ok(Some(id)),
+        Ok(Err(exceeded)) => Err(exceeded.into()),
+        Err(e) => {
+            log::warn!("Could not reserve AI tokens for {}: {e}", call.provider);
+            Ok(None)
+        }
+    }
+}
+
+/// Add a ledger row; failures are logged rather than failing the call
+pub async fn record(pool: &Pool<Postgres>, call: &Call, status: &str, token_usage: Option<&TokenUsage>, latency_ms: u128, error: Option<&str>) {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO ai_usage (provider, model, user_id, endpoint, prompt_tokens, completion_tokens, total_tokens, latency_ms, status, error)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        "#,
+    )
+    .bind(&call.provider)
+    .bind(&call.model)
+    .bind(&call.user)
+    .bind(call.endpoint)
+    .bind(token_usage.and_then(|u| u.prompt_tokens).map(|t| t as i32))
+    .bind(token_usage.and_then(|u| u.completion_tokens).map(|t| t as i32))
+    .bind(token_usage.and_then(|u| u.total_tokens).map(|t| t as i32))
+    .bind(latency_ms.min(i32::MAX as u128) as i32)
+    .bind(status)
+    .bind(error)
+    .execute(pool)
+    .await;
+
+    if let Err(e) = result {
+        log::warn!("Failed to record AI usage for {}: {e}", call.provider);
+    }
+}
+
+/// Replace a reservation with what the call actually used; `estimated` marks usage the provider didn't report
+async fn settle(pool: &Pool<Postgres>, id: Uuid, status: &str, token_usage: &TokenUsage, estimated: bool, latency_ms: u128, error: Option<&str>) {
+    let result = sqlx::query(
+        r#"
+        UPDATE ai_usage
+        SET status = $2, prompt_tokens = $3, completion_tokens = $4, total_tokens = $5,
+            estimated = $6, latency_ms = $7, error = $8
+        WHERE id = $1
+        "#,
+    )
+    .bind(id)
+    .bind(status)
+    .bind(token_usage.prompt_tokens.map(|t| t as i32))
+    .bind(token_usage.completion_tokens.map(|t| t as i32))
+    .bind(token_usage.total_tokens.map(|t| t as i32))
+    .bind(estimated)
+    .bind(latency_ms.min(i32::MAX as u128) as i32)
+    .bind(error)
+    .execute(pool)
+    .await;
+
+    if let Err(e) = result {
+        log::warn!("Failed to settle AI usage row {id}: {e}");
+    }
+}
+
+/// Run `compute` if the token budgets can cover the call's estimate, holding that estimate as a reservation
+/// until the call finishes and then recording its real tokens and latency. Failed and interrupted calls are
+/// recorded with estimated tokens: the prompt plus whatever text arrived before the failure.
+pub async fn metered<F>(data: &ApiState, call: &Call, compute: F) -> anyhow::Result<(String, Option<TokenUsage>)>
+where
+    F: Future<Output = anyhow::Result<(String, Option<TokenUsage>)>>,
+{
+    let reservation = match reserve(data, call).await {
+        Ok(reservation) => reservation,
+        Err(e) => {
+            record(&data.db, call, "rejected", None, 0, Some(&e.to_string())).await;
+            return Err(e);
+        }
+    };
+
+    let start = std::time::Instant::now();
+    let result = compute.await;
+    let latency_ms = start.elapsed().as_millis();
+    let (status, usage, estimated, error) = match &result {
+        Ok((_, Some(token_usage))) => ("success", token_usage.clone(), false, None),
+        Ok((analysis, None)) => ("success", call.estimated_usage(analysis), true, None),
+        Err(e) => {
+            let partial = e.downcast_ref::<PartialOutput>().map(|p| p.text.as_str()).unwrap_or_default();
+            ("error", call.estimated_usage(partial), true, Some(e.to_string()))
+        }
+    };
+    match reservation {
+        Some(id) => settle(&data.db, id, status, &usage, estimated, latency_ms, error.as_deref()).await,
+        None => record(&data.db, call, status, Some(&usage), latency_ms, error.as_deref()).await,
+    }
+    result
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UsageQuery {
+    /// "day" (default), "user", "provider" or "model"
+    pub group_by: Option<String>,
+    /// How many days back to include, today counting as one
+    pub days: Option<i32>,
+    pub provider: Option<String>,
+    pub user: Option<String>,
+}
+
+/// Calls, tokens and latency from the ledger grouped by day, user, provider or model, with budget status
+pub async fn get_usage(
+    data: web::Data<Arc<ApiState>>,
+    query: web::Query<UsageQuery>,
+) -> Result<HttpResponse> {
+    let group_by = query.group_by.as_deref().unwrap_or("day");
+    let group_sql = match group_by {
+        "day" => "to_char(created_at AT TIME ZONE 'UTC', 'YYYY-MM-DD')",
+        "user" => "COALESCE(user_id, 'anonymous')",
+        "provider" => "provider",
+        "model" => "provider || ':' || model",
+        other => {
+            return Ok(HttpResponse::BadRequest().json(json!({
+                "success": false,
+                "error": format!("Unknown group_by '{other}'; use day, user, provider or model"),
+            })));
+        }
+    };
+    let days = query.days.unwrap_or(DEFAULT_USAGE_DAYS).max(1);
+
+    let rows = sqlx::query(&format!(
+        r#"
+        SELECT {group_sql} AS key,
+               COUNT(*) AS calls,
+               COUNT(*) FILTER (WHERE status = 'success') AS successes,
+               COUNT(*) FILTER (WHERE status = 'error') AS errors,
+               COUNT(*) FILTER (WHERE status = 'rejected') AS rejected,
+               COALESCE(SUM(prompt_tokens), 0)::BIGINT AS prompt_tokens,
+               COALESCE(SUM(completion_tokens), 0)::BIGINT AS completion_tokens,
+               COALESCE(SUM({TOKENS_SQL}), 0)::BIGINT AS total_tokens,
+               COALESCE(AVG(latency_ms) FILTER (WHERE status <> 'rejected'), 0)::FLOAT8 AS avg_latency_ms
+        FROM ai_usage
+        WHERE created_at >= date_trunc('day', CURRENT_TIMESTAMP AT TIME ZONE 'UTC') AT TIME ZONE 'UTC' - make_interval(days => $1 - 1)
+          AND ($2::TEXT IS NULL OR provider = $2)
+          AND ($3::TEXT IS NULL OR user_id = $3)
+        GROUP BY 1
+        ORDER BY 1
+        "#
+    ))
+    .bind(days)
+    .bind(query.provider.as_deref().filter(|p| !p.is_empty()))
+    .bind(query.user.as_deref().filter(|u| !u.is_empty()))
+    .fetch_all(&data.db)
+    .await;
+
+    let rows = match rows {
+        Ok(rows) => rows,
+        Err(e) => {
+            return Ok(HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "error": format!("Failed to read AI usage: {e}"),
+            })));
+        }
+    };
+
+    let groups: Vec<serde_json::Value> = rows
+        .iter()
+        .map(|row| json!({
+            "key": row.get::<String, _>("key"),
+            "calls": row.get::<i64, _>("calls"),
+            "successes": row.get::<i64, _>("successes"),
+            "errors": row.get::<i64, _>("errors"),
+            "rejected": row.get::<i64, _>("rejected"),
+            "prompt_tokens": row.get::<i64, _>("prompt_tokens"),
+            "completion_tokens": row.get::<i64, _>("completion_tokens"),
+            "total_tokens": row.get::<i64, _>("total_tokens"),
+            "avg_latency_ms": row.get::<f64, _>("avg_latency_ms").round(),
+        }))
+        .collect();
+    let total = |field: &str| groups.iter().filter_map(|g| g[field].as_i64()).sum::<i64>();
+    let totals = json!({
+        "calls": total("calls"),
+        "errors": total("errors"),
+        "rejected": total("rejected"),
+        "prompt_tokens": total("prompt_tokens"),
+        "completion_tokens": total("completion_tokens"),
+        "total_tokens": total("total_tokens"),
+    });
+
+    let budgets = budget_status(&data).await.unwrap_or_default();
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "group_by": group_by,
+        "days": days,
+        "groups": groups,
+        "totals": totals,
+        "budgets": budgets,
+    })))
+}
+
+/// Today's and this month's tokens against the configured budgets
+pub async fn get_budget(data: web::Data<Arc<ApiState>>) -> Result<HttpResponse> {
+    match budget_status(&data).await {
+        Ok(budgets) => Ok(HttpResponse::Ok().json(json!({
+            "success": true,
+            "budgets": budgets,
+        }))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({
+            "success": false,
+            "error": format!("Failed to read AI usage: {e}"),
+        }))),
+    }
+}
+
+/// A provider's ledger totals for today or, with `period` "month", this month (UTC), in the
+/// input_tokens/output_tokens shape the admin usage panel reads
+pub async fn provider_totals(pool: &Pool<Postgres>, provider: &str, period: &str) -> Result<serde_json::Value, sqlx::Error> {
+    let since = if period == "month" { "month" } else { "day" };
+    let row = sqlx::query(&format!(
+        r#"
+        SELECT COUNT(*) AS calls,
+               COALESCE(SUM(prompt_tokens), 0)::BIGINT AS input_tokens,
+               COALESCE(SUM(completion_tokens), 0)::BIGINT AS output_tokens,
+               MAX(created_at) AS last_call_at
+        FROM ai_usage
+        WHERE provider = $1
+          AND status <> 'rejected'
+          AND created_at >= date_trunc('{since}', CURRENT_TIMESTAMP AT TIME ZONE 'UTC') AT TIME ZONE 'UTC'
+        "#
+    ))
+    .bind(provider)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(json!({
+        "input_tokens": row.get::<i64, _>("input_tokens"),
+        "output_tokens": row.get::<i64, _>("output_tokens"),
+        "calls": row.get::<i64, _>("calls"),
+        "period": if since == "month" { "month" } else { "today" },
+        "last_call_at": row.get::<Option<chrono::DateTime<chrono::Utc>>, _>("last_call_at"),
+        "service_tier": "ledger",
+    }))
+}
@@ -2,11 +2,24 @@
 use actix_web::{web, HttpResponse, Result};
 use serde::{Deserialize, Serialize};
 use anyhow::Context;
+use serde_json::json;
+use std::sync::Arc;
+use crate::ai_cache::{self, CacheKey, CacheMode, CacheStatus};
+use crate::ai_provider::TokenUsage;
+use crate::ai_usage::{self, Call};
+use crate::sse;
+use crate::ApiState;
 
 #[derive(Debug, Deserialize)]
 pub struct ClaudeAnalysisRequest {
     pub prompt: String,
     pub dataset_info: Option<serde_json::Value>,
+    /// "bypass" skips the shared cache and refreshes it
+    #[serde(default)]
+    pub cache: CacheMode,
+    /// Who is asking, recorded in the AI usage ledger
+    #[serde(default)]
+    pub user: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -15,24 +28,37 @@ pub struct ClaudeAnalysisResponse {
     pub analysis: Option<String>,
     pub error: Option<String>,
     pub token_usage: Option<TokenUsage>,
+    /// Whether the answer came from the shared cache; absent when the cache is off
+    pub cache: Option<CacheStatus>,
 }
 
-#[derive(Debug, Serialize)]
-pub struct TokenUsage {
-    pub prompt_tokens: Option<u32>,
-    pub completion_tokens: Option<u32>,
-    pub total_tokens: Option<u32>,
+// The CLI answers with whichever model it is set up to use
+fn cache_key(req: &ClaudeAnalysisRequest) -> CacheKey {
+    CacheKey::new("claude-cli", "default", &req.prompt, req.dataset_info.as_ref(), "")
 }
 
 pub async fn analyze_with_claude_cli(
+    data: web::Data<Arc<ApiState>>,
     req: web::Json<ClaudeAnalysisRequest>,
 ) -> Result<HttpResponse> {
-    match call_claude_code_cli(&req.prompt, &req.dataset_info).await {
+    let estimate_prompt = full_prompt(&req.prompt, &req.dataset_info).unwrap_or_else(|_| req.prompt.clone());
+    let call = Call::new("claude", "default", req.user.as_deref(), "claude/analyze").estimated(&estimate_prompt, None);
+    let compute = ai_usage::metered(&data, &call, call_claude_code_cli(&req.prompt, &req.dataset_info));
+    let (result, cache) = ai_cache::cached(&data, cache_key(&req), req.cache, compute).await;
+    match result {
         Ok((analysis, token_usage)) => Ok(HttpResponse::Ok().json(ClaudeAnalysisResponse {
             success: true,
             analysis: Some(analysis),
             error: None,
             token_usage,
+            cache,
+        })),
+        Err(e) if ai_usage::is_budget_exceeded(&e) => Ok(HttpResponse::TooManyRequests().json(ClaudeAnalysisResponse {
+            success: false,
+            analysis: None,
+            error: Some(e.to_string()),
+            token_usage: None,
+            cache,
         })),
         Err(e) => {
             eprintln!("Claude Code CLI Error: {e:?}");
@@ -54,21 +80,17 @@ pub async fn analyze_with_claude_cli(
                 analysis: None,
                 error: Some(format!("Claude Code CLI execution failed: {e}")),
                 token_usage: fallback_token_usage,
+                cache,
             }))
         }
     }
 }
 
 // Call Claude Code CLI for dataset analysis
-async fn call_claude_code_cli(prompt: &str, dataset_info: &Option<serde_json::Value>) -> anyhow::Result<(String, Option<TokenUsage>)> {
+pub async fn call_claude_code_cli(prompt: &str, dataset_info: &Option<serde_json::Value>) -> anyhow::Result<(String, Option<TokenUsage>)> {
     use std::process::Command;
     
-    // Build the full prompt with dataset context
-    let full_prompt = if let Some(dataset) = dataset_info {
-        format!("{}\n\nDataset Context:\n{}", prompt, serde_json::to_string_pretty(dataset)?)
-    } else {
-        prompt.to_string()
-    };
+    let full_prompt = full_prompt(prompt, dataset_info)?;
     
     println!("Executing Claude Code CLI analysis...");
     
@@ -91,20 +113,168 @@ async fn call_claude_code_cli(prompt: &str, dataset_info: &Option<serde_json::Va
         return Err(anyhow::anyhow!("Claude Code CLI returned empty response"));
     }
     
-    // Estimate token usage based on text length
-    let prompt_len = full_prompt.len();
-    let response_len = analysis.len();
-    
-    let estimated_prompt_tokens = (prompt_len / 4) as u32;  // Rough estimate: 4 chars per token
-    let estimated_completion_tokens = (response_len / 4) as u32;
-    let estimated_total = estimated_prompt_tokens + estimated_completion_tokens;
-    
-    let token_usage = Some(TokenUsage {
-        prompt_tokens: Some(estimated_prompt_tokens),
-        completion_tokens: Some(estimated_completion_tokens),
-        total_tokens: Some(estimated_total),
-    });
-    
+    // Text output carries no token counts; the usage ledger estimates them
     println!("Claude Code CLI analysis completed successfully");
+    Ok((analysis, None))
+}
+
+// Build the full prompt with dataset context
+fn full_prompt(prompt: &str, dataset_info: &Option<serde_json::Value>) -> anyhow::Result<String> {
+    Ok(match dataset_info {
+        Some(dataset) => format!("{}\n\nDataset Context:\n{}", prompt, serde_json::to_string_pretty(dataset)?),
+        None => prompt.to_string(),
+    })
+}
+
+// Analyze with Claude Code CLI, streaming the answer as Server-Sent Events:
+// "start", "chunk" (text) as the CLI writes it, then "done" (token_usage) or "error"
+pub async fn analyze_with_claude_cli_stream(
+    data: web::Data<Arc<ApiState>>,
+    req: web::Json<ClaudeAnalysisRequest>,
+) -> Result<HttpResponse> {
+    let full_prompt = match full_prompt(&req.prompt, &req.dataset_info) {
+        Ok(full_prompt) => full_prompt,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(ClaudeAnalysisResponse {
+                success: false,
+                analysis: None,
+                error: Some(e.to_string()),
+                token_usage: None,
+                cache: None,
+            }));
+        }
+    };
+
+    let key = cache_key(&req);
+    let call = Call::new("claude", "default", req.user.as_deref(), "claude/analyze/stream").estimated(&full_prompt, None);
+    let cache_mode = req.cache;
+    let (events, response) = sse::channel();
+    actix_web::rt::spawn(async move {
+        if events.send("start", &json!({ "provider": "claude-cli" })).await.is_err() {
+            return;
+        }
+        if let Some((cached, cache)) = ai_cache::hit(&data, &key, cache_mode).await {
+            if events.send("chunk", &json!({ "text": cached.analysis })).await.is_ok() {
+                let _ = events.send("done", &json!({ "success": true, "token_usage": cached.token_usage, "cache": cache })).await;
+            }
+            return;
+        }
+        let _ = match ai_usage::metered(&data, &call, stream_claude_code_cli(&full_prompt, &events)).await {
+            Ok((analysis, token_usage)) => {
+                let cache = ai_cache::record(&data, &key, cache_mode, &analysis, token_usage.as_ref()).await;
+                events.send("done", &json!({ "success": true, "token_usage": token_usage, "cache": cache })).await
+            }
+            Err(e) => {
+                eprintln!("Claude Code CLI Error: {e:?}");
+                let error = if ai_usage::is_budget_exceeded(&e) { e.to_string() } else { format!("Claude Code CLI execution failed: {e}") };
+                events.send("error", &json!({ "success": false, "error": error })).await
+            }
+        };
+    });
+    Ok(response)
+}
+
+// Run Claude Code CLI with stream-json output, sending text to `events` as "chunk" events as it
+// arrives. Returns the whole answer and the token usage from the final result message, if any.
+async fn stream_claude_code_cli(full_prompt: &str, events: &sse::EventSender) -> anyhow::Result<(String, Option<TokenUsage>)> {
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
+
+    println!("Executing Claude Code CLI streaming analysis...");
+
+    let mut child = tokio::process::Command::new("claude")
+        .args(["--print", "--output-format", "stream-json", "--verbose", "--include-partial-messages"])
+        .arg(full_prompt)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to execute claude command. Make sure Claude Code CLI is installed and accessible.")?;
+
+    let mut stderr = child.stderr.take().context("Claude Code CLI stderr unavailable")?;
+    let stderr_task = tokio::spawn(async move {
+        let mut text = String::new();
+        let _ = stderr.read_to_string(&mut text).await;
+        text
+    });
+
+    let stdout = child.stdout.take().context("Claude Code CLI stdout unavailable")?;
+    let mut lines = BufReader::new(stdout).lines();
+    let mut analysis = String::new();
+    let mut streamed_deltas = false;
+    let mut token_usage = None;
+    // Errors after some text arrived keep it, so the usage ledger can count what was generated
+    let streamed: anyhow::Result<()> = async {
+        while let Some(line) = lines.next_line().await.context("Failed to read Claude Code CLI output")? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let Ok(message) = serde_json::from_str::<serde_json::Value>(&line) else {
+                // Plain text output from CLIs without stream-json
+                let text = format!("{line}\n");
+                events.send("chunk", &json!({ "text": text })).await?;
+                analysis.push_str(&text);
+                continue;
+            };
+            let text = match message.get("type").and_then(|t| t.as_str()) {
+                // Partial messages: token-level text deltas
+                Some("stream_event") => {
+                    let delta = message.pointer("/event/delta").filter(|d| d.get("type").and_then(|t| t.as_str()) == Some("text_delta"));
+                    let text = delta.and_then(|d| d.get("text")).and_then(|t| t.as_str()).unwrap_or_default().to_string();
+                    streamed_deltas |= !text.is_empty();
+                    text
+                }
+                // Whole assistant turns, only forwarded when the CLI isn't sending deltas
+                Some("assistant") if !streamed_deltas => message
+                    .pointer("/message/content")
+                    .and_then(|content| content.as_array())
+                    .map(|blocks| {
+                        blocks
+                            .iter()
+                            .filter(|b| b.get("type").and_then(|t| t.as_str()) == Some("text"))
+                            .filter_map(|b| b.get("text").and_then(|t| t.as_str()))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                Some("result") => {
+                    if message.get("is_error").and_then(|e| e.as_bool()).unwrap_or(false) {
+                        anyhow::bail!("{}", message.get("result").and_then(|r| r.as_str()).unwrap_or("Claude Code CLI reported an error"));
+                    }
+                    token_usage = message.get("usage").map(|usage| {
+                        let count = |field: &str| usage.get(field).and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+                        let prompt_tokens = count("input_tokens") + count("cache_creation_input_tokens") + count("cache_read_input_tokens");
+                        let completion_tokens = count("output_tokens");
+                        TokenUsage {
+                            prompt_tokens: Some(prompt_tokens),
+                            completion_tokens: Some(completion_tokens),
+                            total_tokens: Some(prompt_tokens + completion_tokens),
+                        }
+                    });
+                    String::new()
+                }
+                _ => String::new(),
+            };
+            if !text.is_empty() {
+                events.send("chunk", &json!({ "text": text })).await?;
+                analysis.push_str(&text);
+            }
+        }
+        Ok(())
+    }
+    .await;
+    if let Err(e) = streamed {
+        return Err(ai_usage::partial_output(e, &analysis));
+    }
+
+    let status = child.wait().await.context("Failed to wait for Claude Code CLI")?;
+    let stderr = stderr_task.await.unwrap_or_default();
+    if !status.success() {
+        return Err(ai_usage::partial_output(anyhow::anyhow!("Claude Code CLI failed: {}", stderr), &analysis));
+    }
+    if analysis.trim().is_empty() {
+        return Err(anyhow::anyhow!("Claude Code CLI returned empty response"));
+    }
+
+    // Without a result message the usage ledger estimates the tokens
+    println!("Claude Code CLI streaming analysis completed successfully");
     Ok((analysis, token_usage))
 }
\ No newline at end of file
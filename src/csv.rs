@@ -0,0 +1,78 @@
+// src/csv.rs
+// CSV parsing shared by the sync sources and the AI provider list
+
+/// Parse CSV text into rows, handling quoted fields, escaped quotes and embedded newlines
+pub fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes => {
+                if chars.peek() == Some(&'"') {
+                    field.push('"');
+                    chars.next();
+                } else {
+                    in_quotes = false;
+                }
+            }
+            '"' if field.is_empty() => in_quotes = true,
+            ',' if !in_quotes => row.push(std::mem::take(&mut field)),
+            '\r' if !in_quotes => {}
+            '\n' if !in_quotes => {
+                row.push(std::mem::take(&mut field));
+                if row.iter().any(|f| !f.is_empty()) {
+                    rows.push(std::mem::take(&mut row));
+                } else {
+                    row.clear();
+                }
+            }
+            _ => field.push(c),
+        }
+    }
+
+    row.push(field);
+    if row.iter().any(|f| !f.is_empty()) {
+        rows.push(row);
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(expected: &[&[&str]]) -> Vec<Vec<String>> {
+        expected.iter().map(|row| row.iter().map(|f| f.to_string()).collect()).collect()
+    }
+
+    #[test]
+    fn parse_csv_quoted_fields() {
+        // The last row has only empty fields and is dropped
+        assert_eq!(
+            parse_csv("name,notes\n\"Acme, Inc.\",\"says \"\"hi\"\"\"\n,\"\"\n"),
+            rows(&[&["name", "notes"], &["Acme, Inc.", "says \"hi\""]])
+        );
+        // A quote inside an unquoted field is kept as is
+        assert_eq!(parse_csv("5\" pipe,x"), rows(&[&["5\" pipe", "x"]]));
+    }
+
+    #[test]
+    fn parse_csv_embedded_newlines() {
+        assert_eq!(
+            parse_csv("name,notes\n\"Acme\",\"line one\nline two\"\nBeta,plain"),
+            rows(&[&["name", "notes"], &["Acme", "line one\nline two"], &["Beta", "plain"]])
+        );
+    }
+
+    #[test]
+    fn parse_csv_crlf_and_bom() {
+        assert_eq!(
+            parse_csv("\u{feff}name,notes\r\nAcme,\"a\r\nb\"\r\n\r\nBeta,c\r\n"),
+            rows(&[&["name", "notes"], &["Acme", "a\r\nb"], &["Beta", "c"]])
+        );
+    }
+}
@@ -0,0 +1,355 @@
+// src/data_context.rs
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Context budget when a request doesn't set one, in estimated tokens
+pub const DEFAULT_CONTEXT_TOKENS: usize = 32_000;
+
+/// Rough characters-per-token ratio used for every size estimate here
+const CHARS_PER_TOKEN: usize = 4;
+/// Tokens kept free in the context window for the "Data Context" heading and estimation error
+const PROMPT_OVERHEAD_TOKENS: usize = 256;
+/// Columns described in the schema before the rest are only counted
+const MAX_SCHEMA_COLUMNS: usize = 100;
+/// Longest string kept in column stats and sample rows
+const MAX_VALUE_CHARS: usize = 200;
+/// Distinct values tracked per column before counting stops
+const MAX_DISTINCT_TRACKED: usize = 1000;
+
+/// What part of the data context made it into the prompt
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ContextReport {
+    /// "full", "summarized" (schema, stats and sampled rows) or "truncated" (cut-off JSON)
+    pub strategy: String,
+    pub original_chars: usize,
+    pub included_chars: usize,
+    pub estimated_tokens: usize,
+    pub budget_tokens: usize,
+    /// Rows in the table, when the context was tabular
+    pub total_rows: Option<usize>,
+    pub rows_included: Option<usize>,
+    pub rows_dropped: Option<usize>,
+    pub columns: Option<usize>,
+}
+
+fn estimate_tokens(text: &str) -> usize {
+    text.len().div_ceil(CHARS_PER_TOKEN)
+}
+
+/// At most `max` bytes of `text`, cut at a character boundary and marked with an ellipsis
+fn truncate_to(text: &str, max: usize) -> String {
+    if text.len() <= max {
+        return text.to_string();
+    }
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}…", &text[..end])
+}
+
+type Row = Map<String, Value>;
+
+/// The rows of a tabular context: an array of objects, or an object holding one under
+/// "rows", "data", "records", "items" or "sample_data" (its other fields are kept as metadata)
+fn find_table(value: &Value) -> Option<(Vec<&Row>, Option<Row>)> {
+    fn as_rows(value: &Value) -> Option<Vec<&Row>> {
+        let rows: Vec<&Row> = value.as_array()?.iter().filter_map(|r| r.as_object()).collect();
+        (!rows.is_empty()).then_some(rows)
+    }
+    if let Some(rows) = as_rows(value) {
+        return Some((rows, None));
+    }
+    let object = value.as_object()?;
+    for key in ["rows", "data", "records", "items", "sample_data"] {
+        if let Some(rows) = object.get(key).and_then(as_rows) {
+            let metadata: Row = object.iter().filter(|(k, _)| *k != key).map(|(k, v)| (k.clone(), v.clone())).collect();
+            return Some((rows, (!metadata.is_empty()).then_some(metadata)));
+        }
+    }
+    None
+}
+
+#[derive(Default)]
+struct ColumnStats {
+    types: BTreeMap<&'static str, usize>,
+    nulls: usize,
+    distinct: HashMap<String, usize>,
+    distinct_overflow: bool,
+    min: Option<f64>,
+    max: Option<f64>,
+    sum: f64,
+    numbers: usize,
+}
+
+impl ColumnStats {
+    fn add(&mut self, value: Option<&Value>) {
+        let value = match value {
+            None | Some(Value::Null) => {
+                self.nulls += 1;
+                return;
+            }
+            Some(value) => value,
+        };
+        let kind = match value {
+            Value::Bool(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Array(_) => "array",
+            _ => "object",
+        };
+        *self.types.entry(kind).or_default() += 1;
+
+        if let Some(n) = value.as_f64() {
+            self.min = Some(self.min.map_or(n, |m| m.min(n)));
+            self.max = Some(self.max.map_or(n, |m| m.max(n)));
+            self.sum += n;
+            self.numbers += 1;
+        }
+
+        let text = match value {
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        if let Some(count) = self.distinct.get_mut(&text) {
+            *count += 1;
+        } else if self.distinct.len() < MAX_DISTINCT_TRACKED {
+            self.distinct.insert(text, 1);
+        } else {
+            self.distinct_overflow = true;
+        }
+    }
+
+    fn describe(&self, name: &str) -> String {
+        let types: Vec<String> = self.types.keys().map(|t| t.to_string()).collect();
+        let mut line = format!("- {name} ({})", if types.is_empty() { "null".to_string() } else { types.join("/") });
+        if self.nulls > 0 {
+            line.push_str(&format!(", {} null", self.nulls));
+        }
+        let distinct = if self.distinct_overflow { format!("{MAX_DISTINCT_TRACKED}+") } else { self.distinct.len().to_string() };
+        line.push_str(&format!(", {distinct} distinct"));
+        if let (Some(min), Some(max)) = (self.min, self.max) {
+            line.push_str(&format!(", min {min}, max {max}, mean {:.2}", self.sum / self.numbers as f64));
+        } else {
+            let mut top: Vec<(&String, &usize)> = self.distinct.iter().collect();
+            top.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+            let top: Vec<String> = top.iter().take(3).map(|(v, c)| format!("{:?} ×{c}", truncate_to(v, 40))).collect();
+            if !top.is_empty() {
+                line.push_str(&format!(", top: {}", top.join(", ")));
+            }
+        }
+        line
+    }
+}
+
+/// One sample row as compact JSON with long strings shortened
+fn sample_row(row: &Map<String, Value>) -> String {
+    let shortened: Map<String, Value> = row
+        .iter()
+        .map(|(k, v)| match v {
+            Value::String(s) => (k.clone(), Value::String(truncate_to(s, MAX_VALUE_CHARS))),
+            other => (k.clone(), other.clone()),
+        })
+        .collect();
+    Value::Object(shortened).to_string()
+}
+
+/// Render `value` for a prompt within `budget_tokens`: whole when it fits, otherwise schema,
+/// column stats and evenly spaced sample rows for tables, or cut-off JSON for anything else
+pub fn render(value: &Value, budget_tokens: usize) -> (String, ContextReport) {
+    let budget_chars = budget_tokens.saturating_mul(CHARS_PER_TOKEN);
+    let full = serde_json::to_string_pretty(value).unwrap_or_default();
+    let mut report = ContextReport {
+        original_chars: full.len(),
+        budget_tokens,
+        ..Default::default()
+    };
+
+    let table = find_table(value);
+    if let Some((rows, _)) = &table {
+        report.total_rows = Some(rows.len());
+    }
+
+    if full.len() <= budget_chars {
+        if let Some(total) = report.total_rows {
+            report.rows_included = Some(total);
+            report.rows_dropped = Some(0);
+        }
+        report.strategy = "full".to_string();
+        report.included_chars = full.len();
+        report.estimated_tokens = estimate_tokens(&full);
+        return (full, report);
+    }
+
+    let Some((rows, metadata)) = table else {
+        let mut text = truncate_to(&full, budget_chars.saturating_sub(100));
+        let dropped = full.len().saturating_sub(text.len());
+        text.push_str(&format!("\n[truncated {dropped} of {} characters to fit the context budget]", full.len()));
+        report.strategy = "truncated".to_string();
+        report.included_chars = text.len();
+        report.estimated_tokens = estimate_tokens(&text);
+        return (text, report);
+    };
+
+    // Column order follows first appearance across rows
+    let mut columns: Vec<&String> = Vec::new();
+    let mut seen = HashSet::new();
+    for row in &rows {
+        for key in row.keys() {
+            if seen.insert(key) {
+                columns.push(key);
+            }
+        }
+    }
+    report.columns = Some(columns.len());
+
+    let mut text = String::new();
+    if let Some(metadata) = &metadata {
+        let metadata = truncate_to(&Value::Object(metadata.clone()).to_string(), budget_chars / 10);
+        text.push_str(&format!("Metadata: {metadata}\n\n"));
+    }
+    text.push_str(&format!("Table with {} rows and {} columns.\nSchema and column stats:\n", rows.len(), columns.len()));
+    for column in columns.iter().take(MAX_SCHEMA_COLUMNS) {
+        let mut stats = ColumnStats::default();
+        rows.iter().for_each(|row| stats.add(row.get(column.as_str())));
+        text.push_str(&stats.describe(column));
+        text.push('\n');
+    }
+    if columns.len() > MAX_SCHEMA_COLUMNS {
+        text.push_str(&format!("- … {} more columns\n", columns.len() - MAX_SCHEMA_COLUMNS));
+    }
+    let text = truncate_to(&text, budget_chars * 2 / 3);
+
+    // Evenly spaced rows, so the sample covers the whole table rather than just its head
+    let samples: Vec<String> = rows.iter().map(|row| sample_row(row)).collect();
+    let average = samples.iter().map(|s| s.len() + 1).sum::<usize>() / samples.len().max(1);
+    let header = "\nSample rows (JSON, one per line):\n";
+    let room = budget_chars.saturating_sub(text.len() + header.len() + 80);
+    let wanted = (room / average.max(1)).clamp(1, samples.len());
+
+    let mut body = String::new();
+    let mut included = 0;
+    for i in 0..wanted {
+        let sample = &samples[i * samples.len() / wanted];
+        if body.len() + sample.len() + 1 > room {
+            if included == 0 {
+                body.push_str(&truncate_to(sample, room));
+                body.push('\n');
+                included += 1;
+            }
+            break;
+        }
+        body.push_str(sample);
+        body.push('\n');
+        included += 1;
+    }
+
+    let text = format!("{text}{header}{body}… {} of {} rows shown\n", included, rows.len());
+    report.strategy = "summarized".to_string();
+    report.rows_included = Some(included);
+    report.rows_dropped = Some(rows.len() - included);
+    report.included_chars = text.len();
+    report.estimated_tokens = estimate_tokens(&text);
+    (text, report)
+}
+
+/// Tokens the data context may use: the requested budget (default DEFAULT_CONTEXT_TOKENS), capped when the
+/// model's context window is known so the prompt, the context and the longest reply still fit in it
+pub fn context_budget(requested: Option<usize>, prompt: &str, context_window: Option<u32>, max_output_tokens: Option<u32>) -> usize {
+    let requested = requested.unwrap_or(DEFAULT_CONTEXT_TOKENS);
+    match context_window {
+        Some(window) => {
+            let reserved = estimate_tokens(prompt.trim()) + max_output_tokens.unwrap_or(0) as usize + PROMPT_OVERHEAD_TOKENS;
+            requested.min((window as usize).saturating_sub(reserved))
+        }
+        None => requested,
+    }
+}
+
+/// `prompt` followed by the rendered data context, if any, within `budget` tokens (see `context_budget`)
+pub fn append_to_prompt(prompt: &str, context: Option<&Value>, budget: usize) -> (String, Option<ContextReport>) {
+    match context {
+        Some(value) => {
+            let (rendered, report) = render(value, budget);
+            (format!("{prompt}\n\nData Context:\n{rendered}"), Some(report))
+        }
+        None => (prompt.to_string(), None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn table(rows: usize) -> Value {
+        Value::Array(
+            (0..rows)
+                .map(|i| json!({ "id": i, "name": format!("Project {i}"), "region": if i % 2 == 0 { "Africa" } else { "Asia" } }))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn small_context_is_sent_whole() {
+        let value = table(3);
+        let (text, report) = render(&value, DEFAULT_CONTEXT_TOKENS);
+
+        assert_eq!(report.strategy, "full");
+        assert_eq!(text, serde_json::to_string_pretty(&value).unwrap());
+        assert_eq!((report.total_rows, report.rows_included, report.rows_dropped), (Some(3), Some(3), Some(0)));
+        assert_eq!(report.included_chars, report.original_chars);
+    }
+
+    #[test]
+    fn large_tables_are_summarized_within_budget() {
+        let value = json!({ "sheet": "Projects", "sample_data": table(500) });
+        let (text, report) = render(&value, 500);
+
+        assert_eq!(report.strategy, "summarized");
+        assert_eq!(report.total_rows, Some(500));
+        assert_eq!(report.columns, Some(3));
+        let (included, dropped) = (report.rows_included.unwrap(), report.rows_dropped.unwrap());
+        assert!(included > 0 && dropped > 0);
+        assert_eq!(included + dropped, 500);
+        assert!(report.included_chars <= 500 * CHARS_PER_TOKEN, "{} chars", report.included_chars);
+        assert!(text.starts_with("Metadata: {\"sheet\":\"Projects\"}"));
+        assert!(text.contains("Table with 500 rows and 3 columns"));
+        assert!(text.contains("- region (string), 2 distinct"));
+        assert!(text.contains(&format!("{included} of 500 rows shown")));
+    }
+
+    #[test]
+    fn other_large_values_are_truncated_within_budget() {
+        let value = json!({ "notes": "x".repeat(10_000) });
+        let (text, report) = render(&value, 100);
+
+        assert_eq!(report.strategy, "truncated");
+        assert_eq!(report.total_rows, None);
+        assert!(report.included_chars <= 100 * CHARS_PER_TOKEN);
+        assert!(text.ends_with(&format!("of {} characters to fit the context budget]", report.original_chars)));
+    }
+
+    #[test]
+    fn truncation_never_splits_a_character() {
+        assert_eq!(truncate_to("héllo", 2), "h…");
+        assert_eq!(truncate_to("日本語", 4), "日…");
+        assert_eq!(truncate_to("日本語", 6), "日本…");
+        assert_eq!(truncate_to("日本語", 9), "日本語");
+    }
+
+    #[test]
+    fn budget_is_capped_by_the_context_window() {
+        let prompt = "p".repeat(400); // 100 tokens
+
+        assert_eq!(context_budget(None, &prompt, None, None), DEFAULT_CONTEXT_TOKENS);
+        assert_eq!(context_budget(Some(50_000), &prompt, None, Some(8_000)), 50_000);
+        // 1000 - 100 (prompt) - 200 (reply) - overhead
+        assert_eq!(context_budget(None, &prompt, Some(1_000), Some(200)), 1_000 - 100 - 200 - PROMPT_OVERHEAD_TOKENS);
+        assert_eq!(context_budget(Some(100), &prompt, Some(1_000), Some(200)), 100);
+        assert_eq!(context_budget(None, &prompt, Some(300), Some(200)), 0);
+        // Edge whitespace on the prompt doesn't shrink the budget
+        assert_eq!(context_budget(None, &format!("  {prompt}\n\n"), Some(1_000), Some(200)), context_budget(None, &prompt, Some(1_000), Some(200)));
+    }
+}
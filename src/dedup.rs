@@ -0,0 +1,383 @@
+// src/dedup.rs
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres, Row};
+use std::collections::HashMap;
+
+/// Location of the per-table duplicate-detection rules
+pub const DUPLICATE_RULES_PATH: &str = "config/duplicate_rules.json";
+
+/// Entry in the rules file applied to tables that have no rule of their own
+const DEFAULT_RULE_KEY: &str = "*";
+
+/// Legal suffixes dropped during normalization so "Acme Inc." matches "ACME"
+const LEGAL_SUFFIXES: &[&str] = &[
+    "inc", "incorporated", "llc", "ltd", "limited", "corp", "corporation",
+    "co", "company", "plc", "gmbh", "sa", "ag", "bv", "pty", "lp", "llp",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateRule {
+    /// Columns compared to decide whether two rows are the same record
+    pub fields: Vec<String>,
+    /// Lowercase, strip punctuation and legal suffixes before comparing
+    #[serde(default = "default_normalize")]
+    pub normalize: bool,
+    /// Jaro-Winkler similarity (0.0-1.0) at or above which a row is reported as a near-duplicate
+    #[serde(default)]
+    pub similarity_threshold: Option<f64>,
+}
+
+fn default_normalize() -> bool {
+    true
+}
+
+impl DuplicateRule {
+    /// Human readable list of the fields, as reported in import responses
+    pub fn describe(&self) -> String {
+        if self.fields.is_empty() {
+            "None".to_string()
+        } else {
+            self.fields.join(" + ")
+        }
+    }
+}
+
+/// Load the rule for a table from the rules file; tables without an entry use the "*" rule
+pub fn load_rule(table_name: &str) -> anyhow::Result<DuplicateRule> {
+    let content = std::fs::read_to_string(DUPLICATE_RULES_PATH)
+        .with_context(|| format!("Failed to read {DUPLICATE_RULES_PATH}"))?;
+    let mut rules: HashMap<String, DuplicateRule> = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse {DUPLICATE_RULES_PATH}"))?;
+
+    rules
+        .remove(table_name)
+        .or_else(|| rules.remove(DEFAULT_RULE_KEY))
+        .with_context(|| format!("{DUPLICATE_RULES_PATH} has no rule for {table_name} and no \"{DEFAULT_RULE_KEY}\" rule"))
+}
+
+/// Normalize a value for comparison: case, whitespace, punctuation and legal suffixes
+pub fn normalize_value(value: &str) -> String {
+    let cleaned: String = value
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c.is_whitespace() { c } else { ' ' })
+        .collect();
+
+    let mut words: Vec<&str> = cleaned.split_whitespace().collect();
+    while words.len() > 1 && words.last().is_some_and(|w| LEGAL_SUFFIXES.contains(w)) {
+        words.pop();
+    }
+    words.join(" ")
+}
+
+/// Jaro-Winkler similarity between two strings, 1.0 meaning identical
+pub fn jaro_winkler(a: &str, b: &str) -> f64 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+
+    let match_distance = (a.len().max(b.len()) / 2).saturating_sub(1);
+    let mut a_matches = vec![false; a.len()];
+    let mut b_matches = vec![false; b.len()];
+    let mut matches = 0usize;
+
+    for (i, ca) in a.iter().enumerate() {
+        let start = i.saturating_sub(match_distance);
+        let end = (i + match_distance + 1).min(b.len());
+        for j in start..end {
+            if !b_matches[j] && b[j] == *ca {
+                a_matches[i] = true;
+                b_matches[j] = true;
+                matches += 1;
+                break;
+            }
+        }
+    }
+
+    if matches == 0 {
+        return 0.0;
+    }
+
+    let mut transpositions = 0usize;
+    let mut k = 0usize;
+    for (i, ca) in a.iter().enumerate() {
+        if !a_matches[i] {
+            continue;
+        }
+        while !b_matches[k] {
+            k += 1;
+        }
+        if *ca != b[k] {
+            transpositions += 1;
+        }
+        k += 1;
+    }
+
+    let m = matches as f64;
+    let jaro = (m / a.len() as f64 + m / b.len() as f64 + (m - transpositions as f64 / 2.0) / m) / 3.0;
+
+    let prefix = a.iter().zip(b.iter()).take(4).take_while(|(x, y)| x == y).count() as f64;
+    jaro + prefix * 0.1 * (1.0 - jaro)
+}
+
+/// A row that was held back because it closely resembles an existing one
+#[derive(Debug, Clone, Serialize)]
+pub struct NearDuplicate {
+    pub row: usize,
+    pub values: HashMap<String, String>,
+    pub existing_id: String,
+    pub existing_values: HashMap<String, String>,
+    pub similarity: f64,
+}
+
+/// Result of checking one incoming record against the existing rows
+#[derive(Debug)]
+pub enum DuplicateCheck {
+    Unique,
+    Exact { existing_id: String },
+    Near { existing_id: String, existing_values: HashMap<String, String>, similarity: f64 },
+}
+
+#[derive(Debug)]
+struct IndexedRow {
+    id: String,
+    raw: HashMap<String, String>,
+    compared: HashMap<String, String>,
+}
+
+/// In-memory snapshot of a table's duplicate-key values, built once per import.
+/// Rows are bucketed per field by compared value and by its first character, so a check
+/// only scores rows that could match instead of the whole table.
+#[derive(Debug)]
+pub struct DuplicateIndex {
+    pub rule: DuplicateRule,
+    rows: Vec<IndexedRow>,
+    by_value: HashMap<(String, String), Vec<usize>>,
+    by_initial: HashMap<(String, char), Vec<usize>>,
+}
+
+impl DuplicateIndex {
+    /// An index with no existing rows
+    pub fn new(rule: DuplicateRule) -> Self {
+        DuplicateIndex { rule, rows: Vec::new(), by_value: HashMap::new(), by_initial: HashMap::new() }
+    }
+
+    /// Load the existing key values of a table; fields missing from the table are dropped from the rule
+    pub async fn load(
+        pool: &Pool<Postgres>,
+        table_name: &str,
+        mut rule: DuplicateRule,
+        columns: &[String],
+    ) -> Result<Self, sqlx::Error> {
+        rule.fields.retain(|f| columns.iter().any(|c| c == f));
+
+        if rule.fields.is_empty() {
+            return Ok(DuplicateIndex::new(rule));
+        }
+        // Matches are reported by id, so a table without one can't be checked
+        if !columns.iter().any(|c| c == "id") {
+            return Err(sqlx::Error::ColumnNotFound(format!("id (needed to check {table_name} for duplicates)")));
+        }
+
+        let selected: Vec<String> = rule.fields.iter().map(|f| format!("\"{f}\"::text")).collect();
+        let sql = format!("SELECT id::text, {} FROM \"{}\"", selected.join(", "), table_name);
+        let rows = sqlx::query(&sql).fetch_all(pool).await?;
+
+        let mut index = DuplicateIndex::new(rule);
+        index.rows.reserve(rows.len());
+        for row in rows {
+            let id: String = row.try_get(0)?;
+            let mut values = HashMap::new();
+            for (i, field) in index.rule.fields.iter().enumerate() {
+                if let Some(value) = row.try_get::<Option<String>, _>(i + 1)? {
+                    values.insert(field.clone(), value);
+                }
+            }
+            index.add(id, values);
+        }
+
+        Ok(index)
+    }
+
+    fn compare_form(&self, value: &str) -> String {
+        if self.rule.normalize {
+            normalize_value(value)
+        } else {
+            value.trim().to_string()
+        }
+    }
+
+    /// The rule's fields taken from a record's column values, leaving out empty ones
+    pub fn key(&self, values: &HashMap<String, Option<String>>) -> HashMap<String, String> {
+        self.rule
+            .fields
+            .iter()
+            .filter_map(|f| values.get(f).cloned().flatten().map(|v| (f.clone(), v)))
+            .filter(|(_, v)| !v.trim().is_empty())
+            .collect()
+    }
+
+    /// Check an incoming record; fields the record leaves empty are not compared.
+    /// Only identical values are Exact; values that match once normalized, or score at or
+    /// above the similarity threshold, are Near so someone can confirm them.
+    pub fn check(&self, values: &HashMap<String, String>) -> DuplicateCheck {
+        let incoming: Vec<(&String, &String, String)> = self
+            .rule
+            .fields
+            .iter()
+            .filter_map(|f| values.get(f).map(|v| (f, v, self.compare_form(v))))
+            .filter(|(_, _, v)| !v.is_empty())
+            .collect();
+
+        let Some((anchor_field, _, anchor)) = incoming.first() else {
+            return DuplicateCheck::Unique;
+        };
+
+        // Fuzzy matching only scores rows whose first compared field starts with the same
+        // character; without a threshold only rows with the same compared value can match
+        let candidates = match (self.rule.similarity_threshold, anchor.chars().next()) {
+            (Some(_), Some(initial)) => self.by_initial.get(&((*anchor_field).clone(), initial)),
+            _ => self.by_value.get(&((*anchor_field).clone(), anchor.clone())),
+        };
+
+        let mut best: Option<(&IndexedRow, f64)> = None;
+        for row in candidates.into_iter().flatten().map(|&i| &self.rows[i]) {
+            let mut identical = true;
+            let mut similarity = 1.0f64;
+            for (field, raw, value) in &incoming {
+                if row.raw.get(*field) != Some(*raw) {
+                    identical = false;
+                }
+                match row.compared.get(*field) {
+                    Some(existing) if existing == value => {}
+                    Some(existing) => similarity = similarity.min(jaro_winkler(existing, value)),
+                    None => similarity = 0.0,
+                }
+            }
+
+            if identical {
+                return DuplicateCheck::Exact { existing_id: row.id.clone() };
+            }
+            if best.is_none_or(|(_, s)| similarity > s) {
+                best = Some((row, similarity));
+            }
+        }
+
+        match best {
+            Some((row, similarity)) if similarity >= self.rule.similarity_threshold.unwrap_or(1.0) => DuplicateCheck::Near {
+                existing_id: row.id.clone(),
+                existing_values: row.raw.clone(),
+                similarity,
+            },
+            _ => DuplicateCheck::Unique,
+        }
+    }
+
+    /// Record a newly inserted row so later rows in the same import are checked against it
+    pub fn add(&mut self, id: String, values: HashMap<String, String>) {
+        let position = self.rows.len();
+        let compared: HashMap<String, String> = values
+            .iter()
+            .map(|(k, v)| (k.clone(), self.compare_form(v)))
+            .collect();
+        for (field, value) in &compared {
+            if let Some(initial) = value.chars().next() {
+                self.by_initial.entry((field.clone(), initial)).or_default().push(position);
+            }
+            self.by_value.entry((field.clone(), value.clone())).or_default().push(position);
+        }
+        self.rows.push(IndexedRow { id, raw: values, compared });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(fields: &[&str], similarity_threshold: Option<f64>) -> DuplicateRule {
+        DuplicateRule {
+            fields: fields.iter().map(|f| f.to_string()).collect(),
+            normalize: true,
+            similarity_threshold,
+        }
+    }
+
+    fn record(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn jaro_winkler_scores() {
+        assert_eq!(jaro_winkler("", ""), 1.0);
+        assert_eq!(jaro_winkler("abc", ""), 0.0);
+        assert_eq!(jaro_winkler("acme", "acme"), 1.0);
+        assert_eq!(jaro_winkler("abc", "xyz"), 0.0);
+        assert!((jaro_winkler("martha", "marhta") - 0.9611).abs() < 1e-4);
+        assert!((jaro_winkler("dwayne", "duane") - 0.84).abs() < 1e-4);
+        assert!((jaro_winkler("dixon", "dicksonx") - 0.8133).abs() < 1e-4);
+    }
+
+    #[test]
+    fn normalize_strips_case_punctuation_and_legal_suffixes() {
+        assert_eq!(normalize_value("  Acme, Inc. "), "acme");
+        assert_eq!(normalize_value("ACME Widgets LLC"), "acme widgets");
+        assert_eq!(normalize_value("Foo Corp Ltd"), "foo");
+        // A name that is only a suffix keeps it
+        assert_eq!(normalize_value("Co"), "co");
+        assert_eq!(normalize_value("Model-Based   Design"), "model based design");
+    }
+
+    #[test]
+    fn identical_values_are_exact() {
+        let mut index = DuplicateIndex::new(rule(&["name", "industry"], None));
+        index.add("1".into(), record(&[("name", "Acme Inc."), ("industry", "Tech")]));
+
+        assert!(matches!(index.check(&record(&[("name", "Acme Inc."), ("industry", "Tech")])), DuplicateCheck::Exact { existing_id } if existing_id == "1"));
+        // Fields the record leaves out aren't compared
+        assert!(matches!(index.check(&record(&[("name", "Acme Inc.")])), DuplicateCheck::Exact { .. }));
+    }
+
+    #[test]
+    fn normalized_matches_are_near_even_without_threshold() {
+        let mut index = DuplicateIndex::new(rule(&["name"], None));
+        index.add("1".into(), record(&[("name", "Acme Inc.")]));
+
+        match index.check(&record(&[("name", "ACME")])) {
+            DuplicateCheck::Near { existing_id, similarity, .. } => {
+                assert_eq!(existing_id, "1");
+                assert_eq!(similarity, 1.0);
+            }
+            other => panic!("expected Near, got {other:?}"),
+        }
+        assert!(matches!(index.check(&record(&[("name", "Acme Widgets")])), DuplicateCheck::Unique));
+    }
+
+    #[test]
+    fn similar_values_above_threshold_are_near() {
+        let mut index = DuplicateIndex::new(rule(&["name"], Some(0.9)));
+        index.add("1".into(), record(&[("name", "Open Data Portal")]));
+        index.add("2".into(), record(&[("name", "Food Bank Finder")]));
+
+        assert!(matches!(index.check(&record(&[("name", "Open Data Portals")])), DuplicateCheck::Near { existing_id, .. } if existing_id == "1"));
+        assert!(matches!(index.check(&record(&[("name", "Civic Tech Map")])), DuplicateCheck::Unique));
+        assert!(matches!(index.check(&record(&[("name", "  ")])), DuplicateCheck::Unique));
+    }
+
+    #[test]
+    fn key_uses_rule_fields_only() {
+        let index = DuplicateIndex::new(rule(&["name", "website"], None));
+        let values = HashMap::from([
+            ("name".to_string(), Some("Acme".to_string())),
+            ("website".to_string(), Some(" ".to_string())),
+            ("industry".to_string(), Some("Tech".to_string())),
+        ]);
+        assert_eq!(index.key(&values), record(&[("name", "Acme")]));
+    }
+}
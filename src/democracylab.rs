@@ -0,0 +1,498 @@
+// src/democracylab.rs
+use anyhow::Context;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::dedup::{self, DuplicateCheck, DuplicateIndex, NearDuplicate};
+use crate::tagger::Tagger;
+
+/// Default DemocracyLab project listing endpoint; override with DEMOCRACYLAB_API_URL
+pub const DEFAULT_API_URL: &str = "https://www.democracylab.org/api/projects";
+
+/// Source name stored in project_sources for DemocracyLab projects
+const SOURCE_NAME: &str = "democracylab";
+
+/// Safety cap on the number of pages fetched in one sync
+const MAX_PAGES: u32 = 200;
+
+/// One page of the DemocracyLab project listing
+#[derive(Debug, Deserialize)]
+struct ProjectPage {
+    #[serde(default, alias = "projects")]
+    records: Vec<SourceProject>,
+    #[serde(default, rename = "numPages")]
+    num_pages: Option<u32>,
+}
+
+/// A project as returned by the DemocracyLab API, keeping the fields we map
+#[derive(Debug, Clone, Deserialize)]
+pub struct SourceProject {
+    pub project_id: serde_json::Value,
+    pub project_name: String,
+    #[serde(default)]
+    pub project_description: Option<String>,
+    #[serde(default)]
+    pub project_url: Option<String>,
+    #[serde(default)]
+    pub project_location: Option<serde_json::Value>,
+    #[serde(default)]
+    pub project_skills: Vec<serde_json::Value>,
+    #[serde(default)]
+    pub project_issue_area: Vec<serde_json::Value>,
+    #[serde(default)]
+    pub project_technologies: Vec<serde_json::Value>,
+    #[serde(default)]
+    pub project_organization: Vec<serde_json::Value>,
+    #[serde(default)]
+    pub project_date_modified: Option<String>,
+}
+
+impl SourceProject {
+    fn source_id(&self) -> String {
+        match &self.project_id {
+            serde_json::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        }
+    }
+
+    fn location(&self) -> Option<String> {
+        match self.project_location.as_ref()? {
+            serde_json::Value::String(s) if !s.trim().is_empty() => Some(s.trim().to_string()),
+            serde_json::Value::Object(o) => {
+                let parts: Vec<&str> = ["city", "state", "country"]
+                    .iter()
+                    .filter_map(|k| o.get(*k).and_then(|v| v.as_str()))
+                    .filter(|v| !v.is_empty())
+                    .collect();
+                (!parts.is_empty()).then(|| parts.join(", "))
+            }
+            _ => None,
+        }
+    }
+
+    fn skills(&self) -> Vec<String> {
+        tag_names(&self.project_skills)
+    }
+
+    fn issue_areas(&self) -> Vec<String> {
+        tag_names(&self.project_issue_area)
+    }
+
+    fn organizations(&self) -> Vec<String> {
+        tag_names(&self.project_organization)
+    }
+
+    /// Database name, truncated to fit projects.name VARCHAR(50)
+    fn db_name(&self) -> String {
+        let name = self.project_name.trim();
+        if name.chars().count() > 50 {
+            format!("{}...", name.chars().take(47).collect::<String>())
+        } else {
+            name.to_string()
+        }
+    }
+
+    fn db_description(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if let Some(desc) = self.project_description.as_ref().filter(|d| !d.trim().is_empty()) {
+            parts.push(desc.trim().to_string());
+        }
+        let organizations = self.organizations();
+        if !organizations.is_empty() {
+            parts.push(format!("Organization: {}", organizations.join(", ")));
+        }
+        if let Some(location) = self.location() {
+            parts.push(format!("Location: {location}"));
+        }
+        let issue_areas = self.issue_areas();
+        if !issue_areas.is_empty() {
+            parts.push(format!("Issue Areas: {}", issue_areas.join(", ")));
+        }
+        let skills = self.skills();
+        if !skills.is_empty() {
+            parts.push(format!("Skills: {}", skills.join(", ")));
+        }
+        if let Some(url) = &self.project_url {
+            parts.push(format!("Project URL: {url}"));
+        }
+
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join("\n\n"))
+        }
+    }
+
+    /// Tags attached to the project: skills, issue areas and technologies
+    fn tags(&self) -> Vec<String> {
+        let mut tags = self.skills();
+        tags.extend(self.issue_areas());
+        tags.extend(tag_names(&self.project_technologies));
+        tags.sort();
+        tags.dedup();
+        tags
+    }
+
+    /// Marker used to detect whether a previously synced project changed: the modification date,
+    /// or a sha256 of the stored fields when DemocracyLab doesn't send one
+    fn revision(&self) -> String {
+        self.project_date_modified.clone().unwrap_or_else(|| {
+            // An array keeps the serialized order fixed
+            let canonical = serde_json::json!([self.db_name(), self.db_description(), self.tags()]);
+            format!("{:x}", Sha256::digest(canonical.to_string().as_bytes()))
+        })
+    }
+}
+
+/// DemocracyLab tag lists are objects with display_name/name, or plain strings
+fn tag_names(values: &[serde_json::Value]) -> Vec<String> {
+    values
+        .iter()
+        .filter_map(|v| match v {
+            serde_json::Value::String(s) => Some(s.clone()),
+            serde_json::Value::Object(o) => o
+                .get("display_name")
+                .or_else(|| o.get("name"))
+                .or_else(|| o.get("tag_name"))
+                .and_then(|n| n.as_str())
+                .map(String::from),
+            _ => None,
+        })
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SyncSummary {
+    pub pages_fetched: u32,
+    pub projects_fetched: usize,
+    pub inserted: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    /// Existing projects with the same name that were linked to their DemocracyLab id.
+    /// Their name and description are left as edited locally until DemocracyLab changes the project.
+    pub linked: usize,
+    /// The page cap was reached before the last page, so later projects were not synced
+    pub truncated: bool,
+    pub near_duplicates: Vec<NearDuplicate>,
+    pub errors: Vec<String>,
+}
+
+/// Fetch every page of the DemocracyLab project listing, up to MAX_PAGES.
+/// Returns the projects, the pages fetched and whether more pages were left.
+pub async fn fetch_projects(base_url: &str) -> anyhow::Result<(Vec<SourceProject>, u32, bool)> {
+    let client = reqwest::Client::new();
+    let base = url::Url::parse(base_url).context("Invalid DemocracyLab API URL")?;
+
+    collect_pages(MAX_PAGES, |page| {
+        let mut url = base.clone();
+        url.query_pairs_mut().append_pair("page", &page.to_string());
+        let request = client.get(url.clone()).timeout(std::time::Duration::from_secs(30));
+        async move {
+            println!("Fetching DemocracyLab projects page {page}: {url}");
+            let response = request.send().await.context("Failed to reach DemocracyLab API")?;
+            if !response.status().is_success() {
+                anyhow::bail!("DemocracyLab API returned HTTP {} for page {}", response.status(), page);
+            }
+            response.json::<ProjectPage>().await.context("Failed to parse DemocracyLab API response")
+        }
+    })
+    .await
+}
+
+/// Request pages from 1 until one is empty or the reported page count is reached, stopping at
+/// `max_pages`. Returns the projects, the pages fetched and whether more pages were left.
+async fn collect_pages<F, Fut>(max_pages: u32, mut fetch_page: F) -> anyhow::Result<(Vec<SourceProject>, u32, bool)>
+where
+    F: FnMut(u32) -> Fut,
+    Fut: std::future::Future<Output = anyhow::Result<ProjectPage>>,
+{
+    let mut projects = Vec::new();
+    let mut page = 1;
+
+    loop {
+        let body = fetch_page(page).await?;
+        let count = body.records.len();
+        projects.extend(body.records);
+
+        let last_page = match body.num_pages {
+            Some(total) => page >= total,
+            None => count == 0,
+        };
+        if last_page || count == 0 {
+            return Ok((projects, page, false));
+        }
+        if page >= max_pages {
+            log::warn!("Stopped DemocracyLab fetch at the {max_pages}-page cap; later pages were not synced");
+            return Ok((projects, page, true));
+        }
+        page += 1;
+    }
+}
+
+/// Pull all DemocracyLab projects and upsert them, keyed on the DemocracyLab project id
+pub async fn sync_projects(pool: &Pool<Postgres>, base_url: &str, tagger: &Tagger) -> anyhow::Result<SyncSummary> {
+    let (projects, pages, truncated) = fetch_projects(base_url).await?;
+    let mut summary = SyncSummary {
+        pages_fetched: pages,
+        truncated,
+        projects_fetched: projects.len(),
+        ..Default::default()
+    };
+
+    let known: HashMap<String, (Uuid, Option<String>, Vec<String>)> = sqlx::query_as::<_, (String, Uuid, Option<String>, Option<Vec<String>>)>(
+        "SELECT source_id, project_id, source_revision, synced_tags FROM project_sources WHERE source = $1"
+    )
+    .bind(SOURCE_NAME)
+    .fetch_all(pool)
+    .await
+    .context("Failed to read project_sources (run init-db to create it)")?
+    .into_iter()
+    .map(|(source_id, project_id, revision, tags)| (source_id, (project_id, revision, tags.unwrap_or_default())))
+    .collect();
+
+    let mut duplicate_index = DuplicateIndex::load(pool, "projects", dedup::load_rule("projects")?, &["id".to_string(), "name".to_string()])
+        .await
+        .context("Failed to load existing projects")?;
+
+    for (index, project) in projects.iter().enumerate() {
+        let source_id = project.source_id();
+        let revision = project.revision();
+
+        let result = match known.get(&source_id) {
+            Some((_, known_revision, _)) if known_revision.as_deref() == Some(revision.as_str()) => {
+                summary.unchanged += 1;
+                Ok(())
+            }
+            Some((project_id, _, synced_tags)) => update_project(pool, tagger, *project_id, project, &revision, synced_tags)
+                .await
+                .map(|_| summary.updated += 1),
+            None => {
+                let key = HashMap::from([("name".to_string(), project.db_name())]);
+                match duplicate_index.check(&key) {
+                    // Same name only: link the project but keep its locally edited fields
+                    DuplicateCheck::Exact { existing_id } => match Uuid::parse_str(&existing_id) {
+                        Ok(project_id) => link_project(pool, project_id, project, &revision)
+                            .await
+                            .map(|_| summary.linked += 1),
+                        Err(e) => Err(e.into()),
+                    },
+                    DuplicateCheck::Near { existing_id, existing_values, similarity } => {
+                        summary.near_duplicates.push(NearDuplicate {
+                            row: index + 1,
+                            values: key,
+                            existing_id,
+                            existing_values,
+                            similarity,
+                        });
+                        Ok(())
+                    }
+                    DuplicateCheck::Unique => match insert_project(pool, tagger, project, &revision).await {
+                        Ok(project_id) => {
+                            duplicate_index.add(project_id.to_string(), key);
+                            summary.inserted += 1;
+                            Ok(())
+                        }
+                        Err(e) => Err(e),
+                    },
+                }
+            }
+        };
+
+        if let Err(e) = result {
+            summary.errors.push(format!("{} ({}): {}", project.project_name, source_id, e));
+        }
+    }
+
+    println!(
+        "DemocracyLab sync complete - fetched: {}, inserted: {}, updated: {}, linked: {}, unchanged: {}, near-duplicates: {}, errors: {}, truncated: {}",
+        summary.projects_fetched, summary.inserted, summary.updated, summary.linked,
+        summary.unchanged, summary.near_duplicates.len(), summary.errors.len(), summary.truncated
+    );
+
+    Ok(summary)
+}
+
+async fn insert_project(pool: &Pool<Postgres>, tagger: &Tagger, project: &SourceProject, revision: &str) -> anyhow::Result<Uuid> {
+    let id = Uuid::new_v4();
+    let now = Utc::now();
+
+    sqlx::query(
+        r#"
+        INSERT INTO projects (
+            id, name, description, status,
+            date_entered, date_modified, created_by, modified_user_id
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#
+    )
+    .bind(id)
+    .bind(project.db_name())
+    .bind(project.db_description())
+    .bind("Active") // Default status
+    .bind(now)
+    .bind(now)
+    .bind("democracylab-sync")
+    .bind("democracylab-sync")
+    .execute(pool)
+    .await?;
+
+    record_source(pool, id, project, revision).await?;
+    crate::import::tag_record(pool, "projects", id, &project.tags()).await?;
+    crate::project_tags::tag_stored_project(pool, tagger, id, &project.db_name(), project.db_description().as_deref()).await?;
+    Ok(id)
+}
+
+/// Overwrite a synced project with DemocracyLab's current data, dropping tags it no longer lists
+async fn update_project(pool: &Pool<Postgres>, tagger: &Tagger, id: Uuid, project: &SourceProject, revision: &str, synced_tags: &[String]) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE projects
+        SET name = $2, description = $3, date_modified = $4, modified_user_id = $5
+        WHERE id = $1
+        "#
+    )
+    .bind(id)
+    .bind(project.db_name())
+    .bind(project.db_description())
+    .bind(Utc::now())
+    .bind("democracylab-sync")
+    .execute(pool)
+    .await?;
+
+    let tags = project.tags();
+    crate::import::untag_record(pool, "projects", id, &crate::tagger::dropped_tags(synced_tags, &tags)).await?;
+
+    record_source(pool, id, project, revision).await?;
+    crate::import::tag_record(pool, "projects", id, &tags).await?;
+    crate::project_tags::tag_stored_project(pool, tagger, id, &project.db_name(), project.db_description().as_deref()).await?;
+    Ok(())
+}
+
+/// Record the DemocracyLab id for an existing project matched by name, adding its tags
+/// without touching the local name or description
+async fn link_project(pool: &Pool<Postgres>, id: Uuid, project: &SourceProject, revision: &str) -> anyhow::Result<()> {
+    record_source(pool, id, project, revision).await?;
+    crate::import::tag_record(pool, "projects", id, &project.tags()).await?;
+    Ok(())
+}
+
+async fn record_source(pool: &Pool<Postgres>, id: Uuid, project: &SourceProject, revision: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO project_sources (project_id, source, source_id, source_revision, synced_tags, last_synced)
+        VALUES ($1, $2, $3, $4, $5, CURRENT_TIMESTAMP)
+        ON CONFLICT (source, source_id)
+        DO UPDATE SET project_id = EXCLUDED.project_id,
+                      source_revision = EXCLUDED.source_revision,
+                      synced_tags = EXCLUDED.synced_tags,
+                      last_synced = EXCLUDED.last_synced
+        "#
+    )
+    .bind(id)
+    .bind(SOURCE_NAME)
+    .bind(project.source_id())
+    .bind(revision)
+    .bind(project.tags())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(value: serde_json::Value) -> SourceProject {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn fixture() -> serde_json::Value {
+        serde_json::json!({
+            "project_id": 42,
+            "project_name": "Open Food Map",
+            "project_description": "Mapping food banks",
+            "project_url": "https://example.org/food",
+            "project_location": { "city": "Seattle", "state": "WA", "country": "" },
+            "project_skills": [{ "display_name": "Python" }, "GIS"],
+            "project_issue_area": [{ "name": "Hunger" }],
+            "project_technologies": ["PostGIS"],
+            "project_organization": []
+        })
+    }
+
+    fn page(projects: usize, num_pages: Option<u32>) -> ProjectPage {
+        ProjectPage {
+            records: (0..projects).map(|i| project(serde_json::json!({ "project_id": i, "project_name": format!("p{i}") }))).collect(),
+            num_pages,
+        }
+    }
+
+    #[test]
+    fn revision_is_a_stable_hash_of_the_stored_fields() {
+        let revision = project(fixture()).revision();
+        assert_eq!(revision, "295f2415070cf281787c399b06305c6066eed56a7ce0d6267ce405d6f7c54666");
+
+        // Duplicate or padded tags and fields that aren't stored don't change it
+        let mut reordered = fixture();
+        reordered["project_technologies"] = serde_json::json!([" PostGIS ", "PostGIS"]);
+        reordered["project_location"]["country"] = serde_json::json!(null);
+        reordered["project_thumbnail"] = serde_json::json!("https://example.org/food.png");
+        assert_eq!(project(reordered).revision(), revision);
+
+        let mut edited = fixture();
+        edited["project_description"] = serde_json::json!("Mapping food pantries");
+        assert_ne!(project(edited).revision(), revision);
+
+        // DemocracyLab's own modification date is used when it sends one
+        let mut dated = fixture();
+        dated["project_date_modified"] = serde_json::json!("2024-05-01T12:00:00Z");
+        assert_eq!(project(dated).revision(), "2024-05-01T12:00:00Z");
+    }
+
+    #[tokio::test]
+    async fn pages_are_fetched_until_the_reported_count() {
+        let mut requested = Vec::new();
+        let (projects, pages, truncated) = collect_pages(MAX_PAGES, |n| {
+            requested.push(n);
+            async move { Ok(page(2, Some(3))) }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!((projects.len(), pages, truncated), (6, 3, false));
+        assert_eq!(requested, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn pages_without_a_count_stop_at_the_first_empty_page() {
+        let (projects, pages, truncated) = collect_pages(MAX_PAGES, |n| async move { Ok(page(if n < 4 { 5 } else { 0 }, None)) })
+            .await
+            .unwrap();
+
+        assert_eq!((projects.len(), pages, truncated), (15, 4, false));
+    }
+
+    #[tokio::test]
+    async fn fetching_stops_at_the_page_cap() {
+        let (projects, pages, truncated) = collect_pages(3, |_| async { Ok(page(1, Some(10))) }).await.unwrap();
+        assert_eq!((projects.len(), pages, truncated), (3, 3, true));
+
+        // Reaching the cap on the last page isn't truncation
+        let (_, pages, truncated) = collect_pages(3, |_| async { Ok(page(1, Some(3))) }).await.unwrap();
+        assert_eq!((pages, truncated), (3, false));
+    }
+
+    #[tokio::test]
+    async fn a_failed_page_fails_the_fetch() {
+        let result = collect_pages(MAX_PAGES, |n| async move {
+            if n == 2 { anyhow::bail!("HTTP 500") } else { Ok(page(1, Some(3))) }
+        })
+        .await;
+        assert!(result.is_err());
+    }
+}
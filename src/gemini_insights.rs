@@ -1,16 +1,47 @@
 // src/gemini-insights.rs
 
-use actix_web::{web, HttpResponse, Result};
+use actix_web::{http::StatusCode, web, HttpResponse, Result};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use crate::ai_cache::{self, CacheKey, CacheMode, CacheStatus};
+use crate::ai_provider::TokenUsage;
+use crate::ai_usage::{self, Call};
+use crate::data_context::{self, ContextReport};
+use crate::sse;
 use crate::ApiState;
 // use google_sheets4::{Sheets, api::ValueRange};
 // use google_apis_common::auth::{ServiceAccountAuthenticator, ServiceAccountKey};
 use anyhow::Context;
 
+/// Base URL of the Gemini REST API; a model name and ":generateContent" are appended
+pub const GEMINI_API_BASE: &str = "https://generativelanguage.googleapis.com/v1beta/models";
+
+/// Model used when neither the request nor GEMINI_MODEL picks one
+pub const DEFAULT_GEMINI_MODEL: &str = "gemini-1.5-flash-latest";
+
+/// Models requests may choose when GEMINI_ALLOWED_MODELS isn't set
+const DEFAULT_ALLOWED_MODELS: &[&str] = &[
+    "gemini-1.5-flash-latest",
+    "gemini-1.5-flash",
+    "gemini-1.5-pro-latest",
+    "gemini-1.5-pro",
+    "gemini-2.0-flash",
+    "gemini-2.5-flash",
+    "gemini-2.5-pro",
+];
+
+const DEFAULT_TEMPERATURE: f32 = 0.3;
+const DEFAULT_MAX_OUTPUT_TOKENS: u32 = 8192;
+/// Upper bound on maxOutputTokens accepted from a request or config
+const MAX_OUTPUT_TOKENS_LIMIT: u32 = 65536;
+/// The Gemini API accepts at most five stop sequences
+const MAX_STOP_SEQUENCES: usize = 5;
+/// Streamed answers can run far longer than the 30 second blocking call allows
+const STREAM_TIMEOUT_SECS: u64 = 300;
+
 #[derive(Deserialize)]
+#[allow(dead_code)]
 pub struct MeetupRequest {
-    #[allow(dead_code)]
     meetup_link: String,
 }
 
@@ -22,29 +53,146 @@ pub struct GeminiTestResponse {
     api_key_present: bool,
     api_key_preview: Option<String>,
     error: Option<String>,
+    /// Model used when a request doesn't choose one
+    default_model: String,
+    /// Allowlisted models and whether this key can use each
+    models: Vec<ModelAccess>,
+    /// Every generateContent model the key can access, allowlisted or not
+    accessible_models: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModelAccess {
+    name: String,
+    accessible: Option<bool>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct GeminiAnalysisRequest {
     pub prompt: String,
-    #[allow(dead_code)]
+    /// Data to analyze, appended to the prompt; large tables are summarized to fit max_context_tokens
     pub data_context: Option<serde_json::Value>,
+    /// Estimated tokens the data context may use; defaults to DEFAULT_CONTEXT_TOKENS and is capped by
+    /// the Gemini provider's token_limit in config/cli.csv
+    #[serde(default)]
+    pub max_context_tokens: Option<usize>,
+    /// One of the allowlisted models; defaults to GEMINI_MODEL
+    #[serde(default)]
+    pub model: Option<String>,
+    /// 0.0-2.0; defaults to GEMINI_TEMPERATURE
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    /// Defaults to GEMINI_MAX_OUTPUT_TOKENS
+    #[serde(default, alias = "max_output_tokens")]
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub system_instruction: Option<String>,
+    #[serde(default)]
+    pub stop_sequences: Vec<String>,
+    /// "bypass" skips the shared cache and refreshes it
+    #[serde(default)]
+    pub cache: CacheMode,
+    /// Who is asking, recorded in the AI usage ledger
+    #[serde(default)]
+    pub user: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct GeminiAnalysisResponse {
     success: bool,
+    model: Option<String>,
     analysis: Option<String>,
     error: Option<String>,
     error_details: Option<GeminiErrorDetails>,
     token_usage: Option<TokenUsage>,
+    /// How much of data_context was sent
+    context: Option<ContextReport>,
+    /// Whether the answer came from the shared cache; absent when the cache is off
+    cache: Option<CacheStatus>,
 }
 
-#[derive(Debug, Serialize)]
-pub struct TokenUsage {
-    prompt_tokens: Option<u32>,
-    completion_tokens: Option<u32>,
-    total_tokens: Option<u32>,
+/// Model and generation parameters for one generateContent call
+#[derive(Debug, Clone)]
+pub struct GenerationSettings {
+    pub model: String,
+    pub temperature: f32,
+    pub top_k: u32,
+    pub top_p: f32,
+    pub max_output_tokens: u32,
+    pub system_instruction: Option<String>,
+    pub stop_sequences: Vec<String>,
+}
+
+impl GenerationSettings {
+    /// Defaults from GEMINI_MODEL, GEMINI_TEMPERATURE and GEMINI_MAX_OUTPUT_TOKENS
+    pub fn from_config(data: &ApiState) -> Self {
+        let config_guard = data.config.lock().unwrap();
+        GenerationSettings {
+            model: config_guard.gemini_model.clone()
+                .filter(|m| !m.trim().is_empty())
+                .unwrap_or_else(|| DEFAULT_GEMINI_MODEL.to_string()),
+            temperature: config_guard.gemini_temperature.unwrap_or(DEFAULT_TEMPERATURE),
+            top_k: 40,
+            top_p: 0.95,
+            max_output_tokens: config_guard.gemini_max_output_tokens.unwrap_or(DEFAULT_MAX_OUTPUT_TOKENS),
+            system_instruction: None,
+            stop_sequences: Vec::new(),
+        }
+    }
+
+    /// Apply a request's overrides, rejecting values outside the allowlist and API limits
+    pub fn with_request(mut self, req: &GeminiAnalysisRequest, allowed_models: &[String]) -> std::result::Result<Self, String> {
+        if let Some(model) = req.model.as_deref().map(normalize_model).filter(|m| !m.is_empty()) {
+            if !allowed_models.iter().any(|m| m == model) {
+                return Err(format!("Model {model} is not allowed; choose one of {}", allowed_models.join(", ")));
+            }
+            self.model = model.to_string();
+        }
+        if let Some(temperature) = req.temperature {
+            if !(0.0..=2.0).contains(&temperature) {
+                return Err("temperature must be between 0.0 and 2.0".to_string());
+            }
+            self.temperature = temperature;
+        }
+        if let Some(max_tokens) = req.max_tokens {
+            if !(1..=MAX_OUTPUT_TOKENS_LIMIT).contains(&max_tokens) {
+                return Err(format!("max_tokens must be between 1 and {MAX_OUTPUT_TOKENS_LIMIT}"));
+            }
+            self.max_output_tokens = max_tokens;
+        }
+        if req.stop_sequences.len() > MAX_STOP_SEQUENCES {
+            return Err(format!("At most {MAX_STOP_SEQUENCES} stop_sequences are allowed"));
+        }
+        if req.stop_sequences.iter().any(|s| s.is_empty()) {
+            return Err("stop_sequences must not contain empty strings".to_string());
+        }
+        self.stop_sequences = req.stop_sequences.clone();
+        self.system_instruction = req.system_instruction.clone().filter(|s| !s.trim().is_empty());
+        Ok(self)
+    }
+}
+
+/// "models/gemini-1.5-pro" -> "gemini-1.5-pro"
+fn normalize_model(model: &str) -> &str {
+    model.trim().trim_start_matches("models/")
+}
+
+/// Models requests may choose: GEMINI_ALLOWED_MODELS (comma-separated) or the built-in list,
+/// always including the configured default model
+pub fn allowed_models(data: &ApiState) -> Vec<String> {
+    let (configured, default_model) = {
+        let config_guard = data.config.lock().unwrap();
+        (config_guard.gemini_allowed_models.clone(), config_guard.gemini_model.clone())
+    };
+    let mut models: Vec<String> = match configured.filter(|m| !m.trim().is_empty()) {
+        Some(list) => list.split(',').map(normalize_model).filter(|m| !m.is_empty()).map(String::from).collect(),
+        None => DEFAULT_ALLOWED_MODELS.iter().map(|m| m.to_string()).collect(),
+    };
+    let default_model = default_model.filter(|m| !m.trim().is_empty()).unwrap_or_else(|| DEFAULT_GEMINI_MODEL.to_string());
+    if !models.contains(&default_model) {
+        models.insert(0, default_model);
+    }
+    models
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -69,36 +217,80 @@ impl std::fmt::Display for GeminiErrorDetails {
 impl std::error::Error for GeminiErrorDetails {}
 
 
+/// The Gemini API key, unless it is missing or still a placeholder
+pub fn configured_api_key(data: &ApiState) -> Option<String> {
+    let config_guard = data.config.lock().unwrap();
+    let key = config_guard.gemini_api_key.trim();
+    (!key.is_empty() && key != "dummy_key" && key != "get-key-at-aistudio.google.com").then(|| key.to_string())
+}
+
+// The Gemini model's context window: the token_limit of the gemini row in config/cli.csv
+fn context_window(data: &ApiState) -> Option<u32> {
+    let configs = data.provider_configs.read().unwrap().clone();
+    configs.iter().find(|config| config.api_style == "gemini").and_then(|config| config.token_limit)
+}
+
+// The prompt with its data context, within the requested budget and the model's context window
+fn prompt_with_context(data: &ApiState, req: &GeminiAnalysisRequest, settings: &GenerationSettings) -> (String, Option<ContextReport>, usize) {
+    let budget = data_context::context_budget(req.max_context_tokens, &req.prompt, context_window(data), Some(settings.max_output_tokens));
+    let (prompt, context) = data_context::append_to_prompt(&req.prompt, req.data_context.as_ref(), budget);
+    (prompt, context, budget)
+}
+
+// Shared cache key: generation settings and the context budget change the answer too
+fn cache_key(req: &GeminiAnalysisRequest, settings: &GenerationSettings, budget: usize) -> CacheKey {
+    let options = format!("{settings:?} {budget}");
+    CacheKey::new("gemini", &settings.model, &req.prompt, req.data_context.as_ref(), &options)
+}
+
+// The API key and generation settings for an analysis request, or the 400 response explaining why not
+fn prepare_request(data: &ApiState, req: &GeminiAnalysisRequest) -> std::result::Result<(String, GenerationSettings), HttpResponse> {
+    let bad_request = |error: String| HttpResponse::BadRequest().json(GeminiAnalysisResponse {
+        success: false,
+        model: None,
+        analysis: None,
+        error: Some(error),
+        error_details: None,
+        token_usage: None,
+        context: None,
+        cache: None,
+    });
+
+    let Some(gemini_api_key) = configured_api_key(data) else {
+        return Err(bad_request("Gemini API key not configured".to_string()));
+    };
+    let settings = GenerationSettings::from_config(data)
+        .with_request(req, &allowed_models(data))
+        .map_err(bad_request)?;
+    Ok((gemini_api_key, settings))
+}
+
 // Analyze data with Gemini AI
 pub async fn analyze_with_gemini(
     data: web::Data<std::sync::Arc<ApiState>>,
     req: web::Json<GeminiAnalysisRequest>,
 ) -> Result<HttpResponse> {
-    let (api_key_present, gemini_api_key) = {
-        let config_guard = data.config.lock().unwrap();
-        let api_key_present = !config_guard.gemini_api_key.is_empty() 
-            && config_guard.gemini_api_key != "dummy_key"
-            && config_guard.gemini_api_key != "get-key-at-aistudio.google.com";
-        (api_key_present, config_guard.gemini_api_key.clone())
+    let (gemini_api_key, settings) = match prepare_request(&data, &req) {
+        Ok(prepared) => prepared,
+        Err(response) => return Ok(response),
     };
-    
-    if !api_key_present {
-        return Ok(HttpResponse::BadRequest().json(GeminiAnalysisResponse {
-            success: false,
-            analysis: None,
-            error: Some("Gemini API key not configured".to_string()),
-            error_details: None,
-            token_usage: None,
-        }));
-    }
 
-    match call_gemini_api(&gemini_api_key, &req.prompt).await {
+    let (prompt, context, budget) = prompt_with_context(&data, &req, &settings);
+
+    let key = cache_key(&req, &settings, budget);
+    let call = Call::new("gemini", &settings.model, req.user.as_deref(), "gemini/analyze").estimated(&prompt, Some(settings.max_output_tokens));
+    let compute = ai_usage::metered(&data, &call, call_gemini_api(&gemini_api_key, GEMINI_API_BASE, &settings, &prompt));
+    let (result, cache) = ai_cache::cached(&data, key, req.cache, compute).await;
+    match result {
         Ok((analysis, token_usage)) => Ok(HttpResponse::Ok().json(GeminiAnalysisResponse {
             success: true,
+            model: Some(settings.model.clone()),
             analysis: Some(analysis),
             error: None,
             error_details: None,
             token_usage,
+            context,
+            cache,
         })),
         Err(e) => {
             // Log detailed error for debugging
@@ -109,37 +301,130 @@ pub async fn analyze_with_gemini(
                 .find_map(|err| err.downcast_ref::<GeminiErrorDetails>())
                 .cloned();
 
-            Ok(HttpResponse::InternalServerError().json(GeminiAnalysisResponse {
+            let status = if ai_usage::is_budget_exceeded(&e) { StatusCode::TOO_MANY_REQUESTS } else { StatusCode::INTERNAL_SERVER_ERROR };
+            Ok(HttpResponse::build(status).json(GeminiAnalysisResponse {
                 success: false,
+                model: Some(settings.model.clone()),
                 analysis: None,
                 error: Some(e.to_string()),
                 error_details,
                 token_usage: None,
+                context,
+                cache,
             }))
         }
     }
 }
 
-// Call Gemini API for text generation
-async fn call_gemini_api(api_key: &str, prompt: &str) -> anyhow::Result<(String, Option<TokenUsage>)> {
-    let client = reqwest::Client::new();
-    let url = format!(
-        "https://generativelanguage.googleapis.com/v1beta/models/gemini-1.5-flash-latest:generateContent?key={api_key}"
-    );
-    
-    let request_body = json!({
+// Analyze data with Gemini AI, streaming the answer as Server-Sent Events:
+// "start" (model, context), "chunk" (text) as it arrives, then "done" (token_usage) or "error"
+pub async fn analyze_with_gemini_stream(
+    data: web::Data<std::sync::Arc<ApiState>>,
+    req: web::Json<GeminiAnalysisRequest>,
+) -> Result<HttpResponse> {
+    let (gemini_api_key, settings) = match prepare_request(&data, &req) {
+        Ok(prepared) => prepared,
+        Err(response) => return Ok(response),
+    };
+    let (prompt, context, budget) = prompt_with_context(&data, &req, &settings);
+
+    let key = cache_key(&req, &settings, budget);
+    let call = Call::new("gemini", &settings.model, req.user.as_deref(), "gemini/analyze/stream").estimated(&prompt, Some(settings.max_output_tokens));
+    let cache_mode = req.cache;
+    let (events, response) = sse::channel();
+    actix_web::rt::spawn(async move {
+        if events.send("start", &json!({ "provider": "gemini", "model": settings.model, "context": context })).await.is_err() {
+            return;
+        }
+        if let Some((cached, cache)) = ai_cache::hit(&data, &key, cache_mode).await {
+            if events.send("chunk", &json!({ "text": cached.analysis })).await.is_ok() {
+                let _ = events.send("done", &json!({ "success": true, "model": settings.model, "token_usage": cached.token_usage, "cache": cache })).await;
+            }
+            return;
+        }
+        let result = ai_usage::metered(&data, &call, stream_gemini_api(&gemini_api_key, GEMINI_API_BASE, &settings, &prompt, &events)).await;
+        let _ = match result {
+            Ok((analysis, token_usage)) => {
+                let cache = ai_cache::record(&data, &key, cache_mode, &analysis, token_usage.as_ref()).await;
+                events.send("done", &json!({ "success": true, "model": settings.model, "token_usage": token_usage, "cache": cache })).await
+            }
+            Err(e) => {
+                eprintln!("Gemini API Error: {e:?}");
+                let error_details = e.chain().find_map(|err| err.downcast_ref::<GeminiErrorDetails>()).cloned();
+                events.send("error", &json!({ "success": false, "error": e.to_string(), "error_details": error_details })).await
+            }
+        };
+    });
+    Ok(response)
+}
+
+// generateContent request body, shared by the blocking and streaming calls
+fn request_body(settings: &GenerationSettings, prompt: &str) -> serde_json::Value {
+    let mut request_body = json!({
         "contents": [{
             "parts": [{
                 "text": prompt
             }]
         }],
         "generationConfig": {
-            "temperature": 0.3,
-            "topK": 40,
-            "topP": 0.95,
-            "maxOutputTokens": 8192,
+            "temperature": settings.temperature,
+            "topK": settings.top_k,
+            "topP": settings.top_p,
+            "maxOutputTokens": settings.max_output_tokens,
         }
     });
+    if !settings.stop_sequences.is_empty() {
+        request_body["generationConfig"]["stopSequences"] = json!(settings.stop_sequences);
+    }
+    if let Some(instruction) = &settings.system_instruction {
+        request_body["systemInstruction"] = json!({ "parts": [{ "text": instruction }] });
+    }
+    request_body
+}
+
+// The key goes in a header rather than the ?key= query parameter, so request URLs (which are
+// logged and returned in error details) never contain it
+const API_KEY_HEADER: &str = "x-goog-api-key";
+
+// Error details for a non-success Gemini API status
+fn error_details(status_code: u16, error_text: &str, request_size: usize, url: &str) -> GeminiErrorDetails {
+    GeminiErrorDetails {
+        status_code,
+        error_type: match status_code {
+            400 => "Bad Request".to_string(),
+            401 => "Unauthorized".to_string(),
+            403 => "Forbidden".to_string(),
+            429 => "Rate Limited".to_string(),
+            500 => "Internal Server Error".to_string(),
+            502 => "Bad Gateway".to_string(),
+            503 => "Service Unavailable".to_string(),
+            504 => "Gateway Timeout".to_string(),
+            _ => "Unknown Error".to_string(),
+        },
+        raw_response: Some(error_text.to_string()),
+        request_size,
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        api_endpoint: url.to_string(),
+    }
+}
+
+// Token counts from a response's usageMetadata
+fn parse_usage(response_json: &serde_json::Value) -> Option<TokenUsage> {
+    response_json.get("usageMetadata").map(|usage| {
+        let count = |field: &str| usage.get(field).and_then(|v| v.as_u64()).map(|v| v as u32);
+        TokenUsage {
+            prompt_tokens: count("promptTokenCount"),
+            completion_tokens: count("candidatesTokenCount"),
+            total_tokens: count("totalTokenCount"),
+        }
+    })
+}
+
+// Call Gemini API for text generation
+pub async fn call_gemini_api(api_key: &str, api_base: &str, settings: &GenerationSettings, prompt: &str) -> anyhow::Result<(String, Option<TokenUsage>)> {
+    let client = reqwest::Client::new();
+    let url = format!("{}/{}:generateContent", api_base.trim_end_matches('/'), settings.model);
+    let request_body = request_body(settings, prompt);
 
     let request_size = serde_json::to_string(&request_body)
         .map(|s| s.len())
@@ -152,6 +437,7 @@ async fn call_gemini_api(api_key: &str, prompt: &str) -> anyhow::Result<(String,
     let response = client
         .post(&url)
         .header("Content-Type", "application/json")
+        .header(API_KEY_HEADER, api_key)
         .json(&request_body)
         .timeout(std::time::Duration::from_secs(30))
         .send()
@@ -167,24 +453,7 @@ async fn call_gemini_api(api_key: &str, prompt: &str) -> anyhow::Result<(String,
     if !status.is_success() {
         let error_text = response.text().await.unwrap_or_else(|_| "Unable to read error response".to_string());
         
-        let error_details = GeminiErrorDetails {
-            status_code,
-            error_type: match status_code {
-                400 => "Bad Request".to_string(),
-                401 => "Unauthorized".to_string(),
-                403 => "Forbidden".to_string(),
-                429 => "Rate Limited".to_string(),
-                500 => "Internal Server Error".to_string(),
-                502 => "Bad Gateway".to_string(),
-                503 => "Service Unavailable".to_string(),
-                504 => "Gateway Timeout".to_string(),
-                _ => "Unknown Error".to_string(),
-            },
-            raw_response: Some(error_text.clone()),
-            request_size,
-            timestamp: chrono::Utc::now().to_rfc3339(),
-            api_endpoint: url.clone(),
-        };
+        let error_details = error_details(status_code, &error_text, request_size, &url);
         
         println!("Gemini API Error Details: {error_details:?}");
         
@@ -212,19 +481,7 @@ async fn call_gemini_api(api_key: &str, prompt: &str) -> anyhow::Result<(String,
     println!("Gemini API text extracted successfully - Length: {} chars", text.len());
     
     // Extract token usage information
-    let token_usage = response_json
-        .get("usageMetadata")
-        .map(|usage| {
-            let prompt_tokens = usage.get("promptTokenCount").and_then(|v| v.as_u64()).map(|v| v as u32);
-            let completion_tokens = usage.get("candidatesTokenCount").and_then(|v| v.as_u64()).map(|v| v as u32);
-            let total_tokens = usage.get("totalTokenCount").and_then(|v| v.as_u64()).map(|v| v as u32);
-            
-            TokenUsage {
-                prompt_tokens,
-                completion_tokens,
-                total_tokens,
-            }
-        });
+    let token_usage = parse_usage(&response_json);
     
     if let Some(ref usage) = token_usage {
         println!("Token usage - Prompt: {:?}, Completion: {:?}, Total: {:?}", 
@@ -234,27 +491,160 @@ async fn call_gemini_api(api_key: &str, prompt: &str) -> anyhow::Result<(String,
     Ok((text.to_string(), token_usage))
 }
 
+// The text and usage in one line of a streamGenerateContent SSE response; None for lines that carry no data
+fn parse_stream_line(line: &str) -> anyhow::Result<Option<(String, Option<TokenUsage>)>> {
+    let Some(payload) = line.trim().strip_prefix("data:") else {
+        return Ok(None);
+    };
+    let chunk: serde_json::Value = serde_json::from_str(payload.trim()).context("Failed to parse Gemini API stream chunk")?;
+    if let Some(error) = chunk.get("error") {
+        anyhow::bail!("Gemini API stream error: {error}");
+    }
+    let text: String = chunk
+        .pointer("/candidates/0/content/parts")
+        .and_then(|parts| parts.as_array())
+        .map(|parts| parts.iter().filter_map(|part| part.get("text").and_then(|t| t.as_str())).collect())
+        .unwrap_or_default();
+    Ok(Some((text, parse_usage(&chunk))))
+}
+
+// Remove the complete lines from a stream buffer, leaving a partial last line for the next chunk.
+// Once the body has ended the remainder is a line too, as the last may arrive without a newline.
+fn take_lines(buffer: &mut Vec<u8>, finished: bool) -> Vec<String> {
+    let mut lines = Vec::new();
+    while let Some(newline) = buffer.iter().position(|&b| b == b'\n') {
+        let line: Vec<u8> = buffer.drain(..=newline).collect();
+        lines.push(String::from_utf8_lossy(&line).into_owned());
+    }
+    if finished && !buffer.is_empty() {
+        lines.push(String::from_utf8_lossy(&std::mem::take(buffer)).into_owned());
+    }
+    lines
+}
+
+// Call Gemini's streamGenerateContent, sending each text chunk to `events` as a "chunk" event.
+// Returns the whole answer and the final token usage.
+pub async fn stream_gemini_api(api_key: &str, api_base: &str, settings: &GenerationSettings, prompt: &str, events: &sse::EventSender) -> anyhow::Result<(String, Option<TokenUsage>)> {
+    let client = reqwest::Client::new();
+    let url = format!("{}/{}:streamGenerateContent?alt=sse", api_base.trim_end_matches('/'), settings.model);
+    let request_body = request_body(settings, prompt);
+    let request_size = serde_json::to_string(&request_body).map(|s| s.len()).unwrap_or(0);
+
+    println!("Making Gemini API streaming request - Size: {request_size} bytes, Model: {}", settings.model);
+
+    let mut response = client
+        .post(&url)
+        .header("Content-Type", "application/json")
+        .header(API_KEY_HEADER, api_key)
+        .json(&request_body)
+        .timeout(std::time::Duration::from_secs(STREAM_TIMEOUT_SECS))
+        .send()
+        .await
+        .context("Failed to make request to Gemini API")?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let error_text = response.text().await.unwrap_or_else(|_| "Unable to read error response".to_string());
+        let error_details = error_details(status.as_u16(), &error_text, request_size, &url);
+        return Err(anyhow::Error::new(error_details).context(format!("Gemini API error {status}: {error_text}")));
+    }
+
+    // Each SSE "data:" line holds one GenerateContentResponse; usageMetadata on the last is the total
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut analysis = String::new();
+    let mut token_usage = None;
+    // Errors after some text arrived keep it, so the usage ledger can count what was generated
+    let streamed: anyhow::Result<()> = async {
+        loop {
+            let bytes = response.chunk().await.context("Gemini API stream interrupted")?;
+            let finished = bytes.is_none();
+            if let Some(bytes) = bytes {
+                buffer.extend_from_slice(&bytes);
+            }
+            for line in take_lines(&mut buffer, finished) {
+                let Some((text, usage)) = parse_stream_line(&line)? else {
+                    continue;
+                };
+                if !text.is_empty() {
+                    events.send("chunk", &json!({ "text": text })).await?;
+                    analysis.push_str(&text);
+                }
+                if usage.is_some() {
+                    token_usage = usage;
+                }
+            }
+            if finished {
+                break;
+            }
+        }
+        Ok(())
+    }
+    .await;
+    if let Err(e) = streamed {
+        return Err(ai_usage::partial_output(e, &analysis));
+    }
+
+    println!("Gemini API stream completed - Length: {} chars", analysis.len());
+    Ok((analysis, token_usage))
+}
+
+// Models the key can call generateContent on, without the "models/" prefix
+async fn list_gemini_models(api_key: &str, api_base: &str) -> anyhow::Result<Vec<String>> {
+    let url = format!("{}?pageSize=1000", api_base.trim_end_matches('/'));
+    let response = reqwest::Client::new()
+        .get(&url)
+        .header(API_KEY_HEADER, api_key)
+        .timeout(std::time::Duration::from_secs(30))
+        .send()
+        .await
+        .context("Failed to list Gemini models")?;
+    let status = response.status();
+    if !status.is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        anyhow::bail!("Gemini models list error {status}: {error_text}");
+    }
+
+    let response_json: serde_json::Value = response.json().await.context("Failed to parse Gemini models list")?;
+    let models = response_json
+        .get("models")
+        .and_then(|m| m.as_array())
+        .map(|models| {
+            models
+                .iter()
+                .filter(|m| {
+                    m.get("supportedGenerationMethods")
+                        .and_then(|methods| methods.as_array())
+                        .is_some_and(|methods| methods.iter().any(|method| method == "generateContent"))
+                })
+                .filter_map(|m| m.get("name").and_then(|n| n.as_str()))
+                .map(|name| normalize_model(name).to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(models)
+}
+
 // Test Gemini API key and connection
 pub async fn test_gemini_api(
     data: web::Data<std::sync::Arc<ApiState>>,
 ) -> Result<HttpResponse> {
-    let (api_key_present, gemini_api_key) = {
-        let config_guard = data.config.lock().unwrap();
-        let api_key_present = !config_guard.gemini_api_key.is_empty() 
-            && config_guard.gemini_api_key != "dummy_key"
-            && config_guard.gemini_api_key != "get-key-at-aistudio.google.com";
-        (api_key_present, config_guard.gemini_api_key.clone())
-    };
+    let gemini_api_key = configured_api_key(&data);
+    let settings = GenerationSettings::from_config(&data);
+    let allowed = allowed_models(&data);
+    let unchecked_models = || allowed.iter().map(|name| ModelAccess { name: name.clone(), accessible: None }).collect();
     
-    if !api_key_present {
+    let Some(gemini_api_key) = gemini_api_key else {
         return Ok(HttpResponse::Ok().json(GeminiTestResponse {
             success: false,
             message: "Gemini API key not configured".to_string(),
             api_key_present: false,
             api_key_preview: None,
             error: Some("Please configure GEMINI_API_KEY in your .env file".to_string()),
+            default_model: settings.model,
+            models: unchecked_models(),
+            accessible_models: Vec::new(),
         }));
-    }
+    };
     
     // Create API key preview (first 4 + "..." + last 4 characters)
     let api_key_preview = if gemini_api_key.len() >= 8 {
@@ -265,35 +655,110 @@ pub async fn test_gemini_api(
         "****".to_string()
     };
     
+    // Which allowlisted models this key can use
+    let (models, accessible_models) = match list_gemini_models(&gemini_api_key, GEMINI_API_BASE).await {
+        Ok(accessible) => {
+            let models = allowed
+                .iter()
+                .map(|name| ModelAccess { name: name.clone(), accessible: Some(accessible.contains(name)) })
+                .collect();
+            (models, accessible)
+        }
+        Err(e) => {
+            log::warn!("Could not list Gemini models: {e}");
+            (unchecked_models(), Vec::new())
+        }
+    };
+    
+    let respond = |success: bool, message: &str, error: Option<String>| GeminiTestResponse {
+        success,
+        message: message.to_string(),
+        api_key_present: true,
+        api_key_preview: Some(api_key_preview.clone()),
+        error,
+        default_model: settings.model.clone(),
+        models: Vec::new(),
+        accessible_models: Vec::new(),
+    };
+    
     // Test the API with a simple prompt
-    match call_gemini_api(&gemini_api_key, "Hello, please respond with 'API test successful'").await {
+    let mut response = match call_gemini_api(&gemini_api_key, GEMINI_API_BASE, &settings, "Hello, please respond with 'API test successful'").await {
         Ok((response, _)) => {
             if response.to_lowercase().contains("api test successful") {
-                Ok(HttpResponse::Ok().json(GeminiTestResponse {
-                    success: true,
-                    message: "Gemini API connection successful".to_string(),
-                    api_key_present: true,
-                    api_key_preview: Some(api_key_preview),
-                    error: None,
-                }))
+                respond(true, "Gemini API connection successful", None)
             } else {
-                Ok(HttpResponse::Ok().json(GeminiTestResponse {
-                    success: true,
-                    message: "Gemini API responded but with unexpected content".to_string(),
-                    api_key_present: true,
-                    api_key_preview: Some(api_key_preview),
-                    error: Some(format!("Expected test response, got: {}", response.chars().take(100).collect::<String>())),
-                }))
+                respond(
+                    true,
+                    "Gemini API responded but with unexpected content",
+                    Some(format!("Expected test response, got: {}", response.chars().take(100).collect::<String>())),
+                )
             }
         },
-        Err(e) => {
-            Ok(HttpResponse::Ok().json(GeminiTestResponse {
-                success: false,
-                message: "Gemini API key present but API call failed".to_string(),
-                api_key_present: true,
-                api_key_preview: Some(api_key_preview),
-                error: Some(e.to_string()),
-            }))
+        Err(e) => respond(false, "Gemini API key present but API call failed", Some(e.to_string())),
+    };
+    response.models = models;
+    response.accessible_models = accessible_models;
+    Ok(HttpResponse::Ok().json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_lines_carry_text_and_usage() {
+        let line = r#"data: {"candidates":[{"content":{"parts":[{"text":"Hello, "},{"text":"world"}]}}]}"#;
+        let (text, usage) = parse_stream_line(line).unwrap().unwrap();
+        assert_eq!(text, "Hello, world");
+        assert!(usage.is_none());
+
+        let last = "data: {\"candidates\":[],\"usageMetadata\":{\"promptTokenCount\":12,\"candidatesTokenCount\":5,\"totalTokenCount\":17}}\r\n";
+        let (text, usage) = parse_stream_line(last).unwrap().unwrap();
+        assert_eq!(text, "");
+        let usage = usage.unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens, usage.total_tokens), (Some(12), Some(5), Some(17)));
+    }
+
+    #[test]
+    fn other_lines_carry_nothing() {
+        assert!(parse_stream_line("").unwrap().is_none());
+        assert!(parse_stream_line("\r\n").unwrap().is_none());
+        assert!(parse_stream_line(": keep-alive").unwrap().is_none());
+        assert!(parse_stream_line("event: message").unwrap().is_none());
+    }
+
+    #[test]
+    fn error_chunks_and_bad_json_fail() {
+        let error = parse_stream_line(r#"data: {"error":{"code":429,"message":"Resource exhausted"}}"#).unwrap_err();
+        assert!(error.to_string().contains("Resource exhausted"));
+        assert!(parse_stream_line("data: {not json").is_err());
+    }
+
+    #[test]
+    fn take_lines_keeps_a_partial_line_until_the_body_ends() {
+        let mut buffer = b"data: {\"a\":1}\n\ndata: {\"b\"".to_vec();
+        assert_eq!(take_lines(&mut buffer, false), vec!["data: {\"a\":1}\n", "\n"]);
+        assert_eq!(buffer, b"data: {\"b\"");
+
+        buffer.extend_from_slice(b":2}");
+        assert!(take_lines(&mut buffer, false).is_empty());
+        assert_eq!(take_lines(&mut buffer, true), vec!["data: {\"b\":2}"]);
+        assert!(buffer.is_empty());
+        assert!(take_lines(&mut buffer, true).is_empty());
+    }
+
+    #[test]
+    fn a_final_line_without_a_newline_is_parsed() {
+        let mut buffer = b"data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"one\"}]}}]}\ndata: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\" two\"}]}}],\"usageMetadata\":{\"totalTokenCount\":9}}".to_vec();
+        let mut text = String::new();
+        let mut total = None;
+        for line in take_lines(&mut buffer, true) {
+            if let Some((chunk, usage)) = parse_stream_line(&line).unwrap() {
+                text.push_str(&chunk);
+                total = usage.and_then(|u| u.total_tokens).or(total);
+            }
         }
+        assert_eq!(text, "one two");
+        assert_eq!(total, Some(9));
     }
-}
\ No newline at end of file
+}
@@ -2,10 +2,13 @@
 use calamine::{Reader, Xlsx, open_workbook, Data};
 use serde::{Deserialize, Serialize};
 use sqlx::{Pool, Postgres};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use actix_web::{web, HttpResponse, Result};
 use uuid::Uuid;
 use chrono::Utc;
+use crate::dedup::{self, DuplicateCheck, DuplicateIndex, NearDuplicate};
+use crate::validation::{self, CellViolation, TableValidator};
+use crate::tagger::Tagger;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ImportRequest {
@@ -24,6 +27,12 @@ pub struct ImportResponse {
     pub records_skipped: Option<usize>,
     pub duplicate_check_columns: Option<String>,
     pub errors: Vec<String>,
+    /// Rows held back because they closely resemble existing records
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub near_duplicates: Vec<NearDuplicate>,
+    /// Cells that failed the table's validation rules; their rows were not imported
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub violations: Vec<CellViolation>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -49,6 +58,12 @@ pub struct DataImportRequest {
     pub table_name: String,
     pub source: String,
     pub file_source: String,
+    /// Columns used to detect existing rows; overrides the per-table duplicate rule
+    #[serde(default)]
+    pub duplicate_key: Option<Vec<String>>,
+    /// Similarity threshold for near-duplicate detection; overrides the per-table duplicate rule
+    #[serde(default)]
+    pub similarity_threshold: Option<f64>,
 }
 
 #[derive(Debug, Serialize)]
@@ -59,6 +74,12 @@ pub struct DataImportResponse {
     pub skipped_count: Option<usize>,
     pub duplicate_check_columns: Option<String>,
     pub errors: Vec<String>,
+    /// Rows held back because they closely resemble existing records
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub near_duplicates: Vec<NearDuplicate>,
+    /// Cells that failed the table's validation rules; their rows were not imported
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub violations: Vec<CellViolation>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -79,7 +100,7 @@ pub async fn import_excel_data(
     let mut errors = Vec::new();
     
     // Read Excel file
-    let records = match read_excel_file(&req.file_path, req.sheet_name.as_deref()) {
+    let (records, violations) = match read_excel_file(&req.file_path, req.sheet_name.as_deref()) {
         Ok(data) => data,
         Err(e) => {
             return Ok(HttpResponse::BadRequest().json(ImportResponse {
@@ -90,6 +111,8 @@ pub async fn import_excel_data(
                 records_skipped: None,
                 duplicate_check_columns: None,
                 errors: vec![format!("File path: {} - {}", req.file_path, e.to_string())],
+                near_duplicates: Vec::new(),
+                violations: Vec::new(),
             }));
         }
     };
@@ -97,18 +120,59 @@ pub async fn import_excel_data(
     // Process and insert records
     let mut inserted_count = 0;
     let mut skipped_count = 0;
+    let mut near_duplicates = Vec::new();
     let total_records = records.len();
 
+    let columns: Vec<String> = ["id"].iter().chain(EXCEL_PROJECT_KEY_COLUMNS).map(|c| c.to_string()).collect();
+    let loaded = match dedup::load_rule("projects") {
+        Ok(rule) => DuplicateIndex::load(&pool.db, "projects", rule, &columns).await.map_err(anyhow::Error::from),
+        Err(e) => Err(e),
+    };
+    let mut duplicate_index = match loaded {
+        Ok(index) => index,
+        Err(e) => {
+            return Ok(HttpResponse::InternalServerError().json(ImportResponse {
+                success: false,
+                message: format!("Failed to load existing projects: {e}"),
+                records_processed: None,
+                records_inserted: None,
+                records_skipped: None,
+                duplicate_check_columns: None,
+                errors: vec![e.to_string()],
+                near_duplicates: Vec::new(),
+                violations: Vec::new(),
+            }));
+        }
+    };
+
+    let tagger = pool.tagger.read().unwrap().clone();
     for (index, record) in records.iter().enumerate() {
-        match insert_project_record(&pool.db, record).await {
+        match insert_project_record(&pool.db, &tagger, &mut duplicate_index, record).await {
             Ok(InsertResult::Inserted) => inserted_count += 1,
             Ok(InsertResult::Skipped) => skipped_count += 1,
+            Ok(InsertResult::NearDuplicate(mut near)) => {
+                near.row = index + 1;
+                near_duplicates.push(near);
+            }
+            Ok(InsertResult::Invalid(row_violations)) => {
+                errors.push(format!("Row {}: failed validation ({})", index + 1,
+                    row_violations.iter().map(|v| v.message.as_str()).collect::<Vec<_>>().join("; ")));
+            }
             Err(e) => {
                 errors.push(format!("Row {}: {}", index + 1, e));
             }
         }
     }
 
+    if inserted_count > 0 {
+        pool.project_index.invalidate_database();
+    }
+
+    let invalid_rows = count_rows(&violations);
+    if invalid_rows > 0 {
+        errors.push(format!("{invalid_rows} rows failed validation and were not imported"));
+    }
+
     let message = if errors.is_empty() {
         if skipped_count > 0 {
             format!("Successfully imported {inserted_count} records, skipped {skipped_count} duplicates")
@@ -126,8 +190,10 @@ pub async fn import_excel_data(
         records_processed: Some(total_records),
         records_inserted: Some(inserted_count),
         records_skipped: Some(skipped_count),
-        duplicate_check_columns: Some("Name + Region + Department".to_string()),
+        duplicate_check_columns: Some(duplicate_index.rule.describe()),
         errors,
+        near_duplicates,
+        violations,
     }))
 }
 
@@ -136,7 +202,7 @@ pub async fn preview_excel_data(
     req: web::Json<ImportRequest>,
 ) -> Result<HttpResponse> {
     println!("Preview request - file_path: {}, sheet_name: {:?}", req.file_path, req.sheet_name);
-    let records = match read_excel_file(&req.file_path, req.sheet_name.as_deref()) {
+    let (records, violations) = match read_excel_file(&req.file_path, req.sheet_name.as_deref()) {
         Ok(data) => data,
         Err(e) => {
             return Ok(HttpResponse::BadRequest().json(ImportResponse {
@@ -147,6 +213,8 @@ pub async fn preview_excel_data(
                 records_skipped: None,
                 duplicate_check_columns: None,
                 errors: vec![format!("File path: {} - {}", req.file_path, e.to_string())],
+                near_duplicates: Vec::new(),
+                violations: Vec::new(),
             }));
         }
     };
@@ -158,7 +226,9 @@ pub async fn preview_excel_data(
         "success": true,
         "message": format!("Preview of {} records (showing first 10)", records.len()),
         "total_records": records.len(),
-        "preview": preview_records
+        "preview": preview_records,
+        "invalid_rows": count_rows(&violations),
+        "violations": violations
     })))
 }
 
@@ -191,7 +261,9 @@ pub async fn get_excel_sheets(
     }
 }
 
-fn read_excel_file(file_path: &str, sheet_name: Option<&str>) -> Result<Vec<ProjectRecord>, Box<dyn std::error::Error>> {
+/// Read project rows from a workbook; rows failing the projects validation rules are
+/// left out of the records and reported as violations instead
+fn read_excel_file(file_path: &str, sheet_name: Option<&str>) -> Result<(Vec<ProjectRecord>, Vec<CellViolation>), Box<dyn std::error::Error>> {
     let mut workbook: Xlsx<_> = open_workbook(file_path)
         .map_err(|e| format!("File not found at: {file_path} - {e}"))?;
     
@@ -203,7 +275,9 @@ fn read_excel_file(file_path: &str, sheet_name: Option<&str>) -> Result<Vec<Proj
     let range = workbook.worksheet_range(&sheet_name)
         .map_err(|e| format!("Error reading sheet: {e}"))?;
 
+    let validator = TableValidator::load("projects").map_err(|e| format!("{e:#}"))?;
     let mut records = Vec::new();
+    let mut violations = Vec::new();
     let mut headers = HashMap::new();
     
     // Get headers from first row
@@ -215,7 +289,8 @@ fn read_excel_file(file_path: &str, sheet_name: Option<&str>) -> Result<Vec<Proj
     }
 
     // Process data rows (skip header row)
-    for row in range.rows().skip(1) {
+    for (row_index, row) in range.rows().enumerate().skip(1) {
+        let mut raw_values: HashMap<String, Option<String>> = HashMap::new();
         let mut record = ProjectRecord {
             fiscal_year: None,
             project_number: None,
@@ -241,6 +316,7 @@ fn read_excel_file(file_path: &str, sheet_name: Option<&str>) -> Result<Vec<Proj
                     Data::Bool(b) => Some(b.to_string()),
                     _ => Some(cell.to_string()),
                 };
+                raw_values.insert(header.replace(' ', "_"), value.clone());
 
                 match header.as_str() {
                     "fiscal year" => record.fiscal_year = value,
@@ -252,7 +328,7 @@ fn read_excel_file(file_path: &str, sheet_name: Option<&str>) -> Result<Vec<Proj
                     "framework" => record.framework = value,
                     "project name" => record.project_name = value,
                     "committed" => {
-                        record.committed = value.and_then(|v| v.parse::<f64>().ok());
+                        record.committed = value.as_deref().and_then(validation::parse_currency);
                     }
                     "naics sector" => record.naics_sector = value,
                     "project description" => record.project_description = value,
@@ -263,12 +339,26 @@ fn read_excel_file(file_path: &str, sheet_name: Option<&str>) -> Result<Vec<Proj
         }
 
         // Only include records with at least a project name
-        if record.project_name.is_some() {
+        if record.project_name.is_none() {
+            continue;
+        }
+
+        // Validate against the projects table rules; "project name" lands in projects.name
+        raw_values.insert("name".to_string(), record.project_name.clone());
+        let row_violations = validator.validate(row_index + 1, &raw_values);
+        if row_violations.is_empty() {
             records.push(record);
+        } else {
+            violations.extend(row_violations);
         }
     }
 
-    Ok(records)
+    Ok((records, violations))
+}
+
+/// Number of distinct rows with at least one violation
+fn count_rows(violations: &[CellViolation]) -> usize {
+    violations.iter().map(|v| v.row).collect::<HashSet<_>>().len()
 }
 
 fn get_excel_sheet_names(file_path: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
@@ -281,36 +371,16 @@ fn get_excel_sheet_names(file_path: &str) -> Result<Vec<String>, Box<dyn std::er
 enum InsertResult {
     Inserted,
     Skipped,
+    NearDuplicate(NearDuplicate),
+    Invalid(Vec<CellViolation>),
 }
 
 async fn insert_project_record(
     pool: &Pool<Postgres>,
+    tagger: &Tagger,
+    duplicate_index: &mut DuplicateIndex,
     record: &ProjectRecord,
 ) -> Result<InsertResult, sqlx::Error> {
-    // Check for existing record based on name, region, and department
-    let existing_count = sqlx::query_scalar::<_, i64>(
-        r#"
-        SELECT COUNT(*) FROM projects 
-        WHERE name = $1 
-        AND (description LIKE '%Region: ' || $2 || '%' OR $2 IS NULL)
-        AND (description LIKE '%Department: ' || $3 || '%' OR $3 IS NULL)
-        "#
-    )
-    .bind(&record.project_name)
-    .bind(&record.region)
-    .bind(&record.department)
-    .fetch_one(pool)
-    .await?;
-
-    if existing_count > 0 {
-        // Record already exists, skip insertion
-        println!("Skipping duplicate project: {} (Region: {:?}, Department: {:?})", 
-                 record.project_name.as_deref().unwrap_or("Unknown"),
-                 record.region,
-                 record.department);
-        return Ok(InsertResult::Skipped);
-    }
-
     let id = Uuid::new_v4();
     let now = Utc::now();
     
@@ -369,12 +439,24 @@ async fn insert_project_record(
         _ => Some("Active".to_string()), // Default status
     };
 
+    let project_number = record.project_number.as_deref().map(str::trim).filter(|n| !n.is_empty());
+    let key = duplicate_index.key(&HashMap::from([
+        ("name".to_string(), record.project_name.clone()),
+        ("description".to_string(), description.clone()),
+        ("status".to_string(), status.clone()),
+        ("priority".to_string(), priority.clone()),
+        ("project_number".to_string(), project_number.map(String::from)),
+    ]));
+    if let Some(result) = check_duplicate(duplicate_index, &key, "project") {
+        return Ok(result);
+    }
+
     sqlx::query(
         r#"
         INSERT INTO projects (
-            id, name, description, status, priority,
+            id, name, description, status, priority, project_number,
             date_entered, date_modified, created_by, modified_user_id
-        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
         "#
     )
     .bind(id)
@@ -382,6 +464,7 @@ async fn insert_project_record(
     .bind(&description)
     .bind(&status)
     .bind(&priority)
+    .bind(project_number)
     .bind(now)
     .bind(now)
     .bind("excel-import") // Creator identifier
@@ -389,6 +472,9 @@ async fn insert_project_record(
     .execute(pool)
     .await?;
 
+    crate::project_tags::tag_stored_project(pool, tagger, id, record.project_name.as_deref().unwrap_or_default(), description.as_deref()).await?;
+
+    duplicate_index.add(id.to_string(), key);
     Ok(InsertResult::Inserted)
 }
 
@@ -400,88 +486,115 @@ pub async fn import_data(
     let mut errors = Vec::new();
     let mut imported_count = 0;
     let mut skipped_count = 0;
-    let mut actual_duplicate_check_columns = None;
+    let mut near_duplicates = Vec::new();
+    let mut violations = Vec::new();
+    let mut duplicate_check_columns = None;
     
     println!("Data import request - table: {}, source: {}, records: {}", 
         req.table_name, req.source, req.data.len());
     
-    match req.table_name.as_str() {
-        "accounts" => {
-            for (index, record) in req.data.iter().enumerate() {
-                match import_account_record(&pool.db, record).await {
-                    Ok((InsertResult::Inserted, fields_used)) => {
-                        imported_count += 1;
-                        if actual_duplicate_check_columns.is_none() {
-                            actual_duplicate_check_columns = Some(fields_used);
-                        }
-                    },
-                    Ok((InsertResult::Skipped, fields_used)) => {
-                        skipped_count += 1;
-                        if actual_duplicate_check_columns.is_none() {
-                            actual_duplicate_check_columns = Some(fields_used);
-                        }
-                    },
-                    Err(e) => {
-                        let error_msg = format!("Row {}: {}", index + 1, e);
-                        println!("Import error: {error_msg}");
-                        errors.push(error_msg);
+    match load_table_columns(&pool.db, &req.table_name).await {
+        Ok(columns) if !columns.is_empty() => {
+            let column_names: Vec<String> = columns.iter().map(|c| c.name.clone()).collect();
+            let rule = dedup::load_rule(&req.table_name).and_then(|mut rule| {
+                if let Some(keys) = req.duplicate_key.as_ref().filter(|k| !k.is_empty()) {
+                    rule.fields = keys.iter().map(|k| normalize_column_key(k)).collect();
+                    // The account and project importers only write some columns, so a key on any other can never match
+                    let usable: Vec<&str> = match req.table_name.as_str() {
+                        "accounts" => ACCOUNT_KEY_COLUMNS.to_vec(),
+                        "projects" => PROJECT_KEY_COLUMNS.to_vec(),
+                        _ => column_names.iter().map(String::as_str).collect(),
+                    };
+                    if let Some(field) = rule.fields.iter().find(|f| !usable.contains(&f.as_str())) {
+                        anyhow::bail!("duplicate_key column '{}' can't be used for {} (allowed: {})", field, req.table_name, usable.join(", "));
                     }
                 }
-            }
-        }
-        "projects" => {
-            for (index, record) in req.data.iter().enumerate() {
-                match import_project_record_from_json(&pool.db, record).await {
-                    Ok((InsertResult::Inserted, fields_used)) => {
-                        imported_count += 1;
-                        if actual_duplicate_check_columns.is_none() {
-                            actual_duplicate_check_columns = Some(fields_used);
-                        }
-                    },
-                    Ok((InsertResult::Skipped, fields_used)) => {
-                        skipped_count += 1;
-                        if actual_duplicate_check_columns.is_none() {
-                            actual_duplicate_check_columns = Some(fields_used);
+                if req.similarity_threshold.is_some() {
+                    rule.similarity_threshold = req.similarity_threshold;
+                }
+                Ok(rule)
+            });
+
+            let duplicate_index = match rule {
+                Ok(rule) => DuplicateIndex::load(&pool.db, &req.table_name, rule, &column_names)
+                    .await
+                    .map_err(|e| format!("Failed to load existing rows for {}: {}", req.table_name, e)),
+                Err(e) => Err(format!("{e:#}")),
+            };
+            let prepared = duplicate_index.and_then(|index| {
+                TableValidator::load(&req.table_name)
+                    .map(|validator| (index, validator))
+                    .map_err(|e| format!("{e:#}"))
+            });
+            match prepared {
+                Ok((mut duplicate_index, validator)) => {
+                    duplicate_check_columns = Some(duplicate_index.rule.describe());
+                    let tagger = pool.tagger.read().unwrap().clone();
+
+                    for (index, record) in req.data.iter().enumerate() {
+                        let result = match req.table_name.as_str() {
+                            "accounts" => import_account_record(&pool.db, &validator, &mut duplicate_index, record).await,
+                            "projects" => import_project_record_from_json(&pool.db, &validator, &tagger, &mut duplicate_index, record).await,
+                            _ => import_generic_record(&pool.db, &req.table_name, &columns, &validator, &mut duplicate_index, record).await,
+                        };
+
+                        match result {
+                            Ok(InsertResult::Inserted) => imported_count += 1,
+                            Ok(InsertResult::Skipped) => skipped_count += 1,
+                            Ok(InsertResult::NearDuplicate(mut near)) => {
+                                near.row = index + 1;
+                                near_duplicates.push(near);
+                            }
+                            Ok(InsertResult::Invalid(row_violations)) => {
+                                errors.push(format!("Row {}: failed validation ({})", index + 1,
+                                    row_violations.iter().map(|v| v.message.as_str()).collect::<Vec<_>>().join("; ")));
+                                violations.extend(row_violations.into_iter().map(|v| CellViolation { row: index + 1, ..v }));
+                            }
+                            Err(e) => {
+                                let error_msg = format!("Row {}: {}", index + 1, e);
+                                println!("Import error: {error_msg}");
+                                errors.push(error_msg);
+                            }
                         }
-                    },
-                    Err(e) => {
-                        let error_msg = format!("Row {}: {}", index + 1, e);
-                        println!("Import error: {error_msg}");
-                        errors.push(error_msg);
                     }
                 }
+                Err(e) => errors.push(e),
             }
         }
-        _ => {
-            errors.push(format!("Unsupported table: {}", req.table_name));
+        Ok(_) => {
+            errors.push(format!("Unsupported table: {} (not found in database)", req.table_name));
+        }
+        Err(e) => {
+            errors.push(format!("Failed to read columns for {}: {}", req.table_name, e));
         }
     }
     
+    if imported_count > 0 && req.table_name == "projects" {
+        pool.project_index.invalidate_database();
+    }
+
     let success = errors.is_empty() || (imported_count > 0 && errors.len() < req.data.len());
+    let near_duplicate_note = if near_duplicates.is_empty() {
+        String::new()
+    } else {
+        format!(", {} near-duplicates need review", near_duplicates.len())
+    };
     let message = if success {
         if errors.is_empty() {
             if skipped_count > 0 {
-                format!("Successfully imported {} records into {}, skipped {} duplicates", 
-                        imported_count, req.table_name, skipped_count)
+                format!("Successfully imported {} records into {}, skipped {} duplicates{}", 
+                        imported_count, req.table_name, skipped_count, near_duplicate_note)
             } else {
-                format!("Successfully imported {} records into {}", imported_count, req.table_name)
+                format!("Successfully imported {} records into {}{}", imported_count, req.table_name, near_duplicate_note)
             }
         } else {
-            format!("Imported {} of {} records into {} with {} errors, skipped {} duplicates", 
-                imported_count, req.data.len(), req.table_name, errors.len(), skipped_count)
+            format!("Imported {} of {} records into {} with {} errors, skipped {} duplicates{}", 
+                imported_count, req.data.len(), req.table_name, errors.len(), skipped_count, near_duplicate_note)
         }
     } else {
         format!("Failed to import data into {}", req.table_name)
     };
     
-    let duplicate_check_columns = actual_duplicate_check_columns.or_else(|| {
-        match req.table_name.as_str() {
-            "accounts" => Some("Name + Industry".to_string()),
-            "projects" => Some("Name + Region + Department".to_string()),
-            _ => None,
-        }
-    });
-    
     Ok(HttpResponse::Ok().json(DataImportResponse {
         success,
         message,
@@ -489,15 +602,49 @@ pub async fn import_data(
         skipped_count: Some(skipped_count),
         duplicate_check_columns,
         errors,
+        near_duplicates,
+        violations,
     }))
 }
 
+/// Check a record's duplicate key against the index.
+/// Returns the result to report when the record must not be inserted.
+fn check_duplicate(duplicate_index: &DuplicateIndex, key: &HashMap<String, String>, label: &str) -> Option<InsertResult> {
+    match duplicate_index.check(key) {
+        DuplicateCheck::Unique => None,
+        DuplicateCheck::Exact { existing_id } => {
+            println!("Skipping duplicate {label}: {key:?} (matches {existing_id})");
+            Some(InsertResult::Skipped)
+        }
+        DuplicateCheck::Near { existing_id, existing_values, similarity } => {
+            println!("Holding near-duplicate {label} for review: {key:?} ~ {existing_values:?} ({similarity:.2})");
+            Some(InsertResult::NearDuplicate(NearDuplicate {
+                row: 0,
+                values: key.clone(),
+                existing_id,
+                existing_values,
+                similarity,
+            }))
+        }
+    }
+}
+
+/// Columns the account importer writes, and so the only ones a duplicate key can use
+const ACCOUNT_KEY_COLUMNS: &[&str] = &["name", "account_type", "industry", "phone_office", "website"];
+
+/// Columns the JSON project importer writes, and so the only ones a duplicate key can use
+const PROJECT_KEY_COLUMNS: &[&str] = &["name", "description", "status"];
+
+/// Columns the Excel project importer writes, and so the only ones its duplicate rule can use
+const EXCEL_PROJECT_KEY_COLUMNS: &[&str] = &["name", "description", "status", "priority", "project_number"];
+
 /// Helper function to import a single account record
-/// Returns (InsertResult, fields_used_for_duplicate_check)
 async fn import_account_record(
     pool: &Pool<Postgres>,
+    validator: &TableValidator,
+    duplicate_index: &mut DuplicateIndex,
     record: &HashMap<String, serde_json::Value>,
-) -> Result<(InsertResult, String), Box<dyn std::error::Error>> {
+) -> Result<InsertResult, Box<dyn std::error::Error>> {
     // Extract fields from the record
     let name = record.get("Name")
         .or_else(|| record.get("name"))
@@ -522,59 +669,39 @@ async fn import_account_record(
         .or_else(|| record.get("sector"))
         .and_then(|v| v.as_str());
     
-    // Check for existing record based on available fields
-    let existing_count = if industry.is_some() {
-        // Use name + industry if industry is available
-        sqlx::query_scalar::<_, i64>(
-            r#"
-            SELECT COUNT(*) FROM accounts 
-            WHERE name = $1 AND industry = $2
-            "#
-        )
-        .bind(name)
-        .bind(industry)
-        .fetch_one(pool)
-        .await?
-    } else {
-        // Use only name if industry is not available
-        sqlx::query_scalar::<_, i64>(
-            r#"
-            SELECT COUNT(*) FROM accounts 
-            WHERE name = $1
-            "#
-        )
-        .bind(name)
-        .fetch_one(pool)
-        .await?
-    };
-
-    // Determine which fields were used for duplicate checking
-    let duplicate_check_fields = if industry.is_some() {
-        "Name + Industry".to_string()
-    } else {
-        "Name".to_string()
-    };
-
-    if existing_count > 0 {
-        // Record already exists, skip insertion
-        let fields_used = if industry.is_some() {
-            format!("Name + Industry: {name} (Industry: {industry:?})")
-        } else {
-            format!("Name: {name}")
-        };
-        println!("Skipping duplicate account: {fields_used}");
-        return Ok((InsertResult::Skipped, duplicate_check_fields));
+    let row_violations = validator.validate(0, &HashMap::from([
+        ("name".to_string(), Some(name.to_string())),
+        ("industry".to_string(), industry.map(String::from)),
+        ("website".to_string(), website.map(String::from)),
+        ("email".to_string(), email.map(String::from)),
+        ("phone".to_string(), phone.map(String::from)),
+    ]));
+    if !row_violations.is_empty() {
+        return Ok(InsertResult::Invalid(row_violations));
     }
 
-    let id = Uuid::new_v4();
-    let now = Utc::now().naive_utc();
-    
     // Set account type based on available data
     let account_type = if email.is_some() || phone.is_some() {
         Some("Customer")
     } else {
         Some("Prospect")
     };
+
+    // Fields the record leaves out (e.g. industry) are not compared
+    let key = duplicate_index.key(&HashMap::from([
+        ("name".to_string(), Some(name.to_string())),
+        ("account_type".to_string(), account_type.map(String::from)),
+        ("industry".to_string(), industry.map(String::from)),
+        ("phone_office".to_string(), phone.map(String::from)),
+        ("website".to_string(), website.map(String::from)),
+    ]));
+
+    if let Some(result) = check_duplicate(duplicate_index, &key, "account") {
+        return Ok(result);
+    }
+
+    let id = Uuid::new_v4();
+    let now = Utc::now().naive_utc();
     
     sqlx::query(
         r#"
@@ -597,13 +724,17 @@ async fn import_account_record(
     .execute(pool)
     .await?;
 
-    Ok((InsertResult::Inserted, duplicate_check_fields))
+    duplicate_index.add(id.to_string(), key);
+    Ok(InsertResult::Inserted)
 }
 
 async fn import_project_record_from_json(
     pool: &Pool<Postgres>,
+    validator: &TableValidator,
+    tagger: &Tagger,
+    duplicate_index: &mut DuplicateIndex,
     record: &HashMap<String, serde_json::Value>,
-) -> Result<(InsertResult, String), Box<dyn std::error::Error>> {
+) -> Result<InsertResult, Box<dyn std::error::Error>> {
     // Handle different field name formats for DemocracyLab vs other sources
     let raw_name = record.get("project_name")
         .and_then(|v| v.as_str())
@@ -622,20 +753,24 @@ async fn import_project_record_from_json(
         .and_then(|v| v.as_str())
         .or_else(|| record.get("description").and_then(|v| v.as_str()));
 
-    // Check for existing record based on name
-    let existing_count = sqlx::query_scalar::<_, i64>(
-        r#"
-        SELECT COUNT(*) FROM projects 
-        WHERE name = $1
-        "#
-    )
-    .bind(&name)
-    .fetch_one(pool)
-    .await?;
+    let mut values: HashMap<String, Option<String>> = record
+        .iter()
+        .map(|(k, v)| (normalize_column_key(k), v.as_str().map(String::from).or_else(|| (!v.is_null()).then(|| v.to_string()))))
+        .collect();
+    values.insert("name".to_string(), Some(name.clone()));
+    let row_violations = validator.validate(0, &values);
+    if !row_violations.is_empty() {
+        return Ok(InsertResult::Invalid(row_violations));
+    }
 
-    if existing_count > 0 {
-        println!("Skipping duplicate project: {name}");
-        return Ok((InsertResult::Skipped, "Name".to_string()));
+    let status = "Active"; // Default status
+    let key = duplicate_index.key(&HashMap::from([
+        ("name".to_string(), Some(name.clone())),
+        ("description".to_string(), description.map(String::from)),
+        ("status".to_string(), Some(status.to_string())),
+    ]));
+    if let Some(result) = check_duplicate(duplicate_index, &key, "project") {
+        return Ok(result);
     }
 
     let id = Uuid::new_v4();
@@ -652,15 +787,17 @@ async fn import_project_record_from_json(
     .bind(id)
     .bind(&name)
     .bind(description)
-    .bind("Active") // Default status
+    .bind(status)
     .bind(now)
     .bind(now)
     .bind("json-import")
     .bind("json-import")
     .execute(pool)
     .await?;
+    crate::project_tags::tag_stored_project(pool, tagger, id, &name, description).await?;
 
-    Ok((InsertResult::Inserted, "Name".to_string()))
+    duplicate_index.add(id.to_string(), key);
+    Ok(InsertResult::Inserted)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -675,18 +812,52 @@ pub async fn import_democracylab_projects(
     let mut errors = Vec::new();
     let mut inserted_count = 0;
     let mut skipped_count = 0;
+    let mut near_duplicates = Vec::new();
     let total_records = req.projects.len();
 
+    // Only the name is known for these projects, so the rule is narrowed to it
+    let columns = vec!["id".to_string(), "name".to_string()];
+    let loaded = match dedup::load_rule("projects") {
+        Ok(rule) => DuplicateIndex::load(&pool.db, "projects", rule, &columns).await.map_err(anyhow::Error::from),
+        Err(e) => Err(e),
+    };
+    let mut duplicate_index = match loaded {
+        Ok(index) => index,
+        Err(e) => {
+            return Ok(HttpResponse::InternalServerError().json(ImportResponse {
+                success: false,
+                message: format!("Failed to load existing projects: {e}"),
+                records_processed: None,
+                records_inserted: None,
+                records_skipped: None,
+                duplicate_check_columns: None,
+                errors: vec![e.to_string()],
+                near_duplicates: Vec::new(),
+                violations: Vec::new(),
+            }));
+        }
+    };
+
+    let tagger = pool.tagger.read().unwrap().clone();
     for (index, project) in req.projects.iter().enumerate() {
-        match insert_democracylab_project(&pool.db, project).await {
+        match insert_democracylab_project(&pool.db, &tagger, &mut duplicate_index, project).await {
             Ok(InsertResult::Inserted) => inserted_count += 1,
             Ok(InsertResult::Skipped) => skipped_count += 1,
+            Ok(InsertResult::NearDuplicate(mut near)) => {
+                near.row = index + 1;
+                near_duplicates.push(near);
+            }
+            Ok(InsertResult::Invalid(_)) => {}
             Err(e) => {
                 errors.push(format!("Row {}: {}", index + 1, e));
             }
         }
     }
 
+    if inserted_count > 0 {
+        pool.project_index.invalidate_database();
+    }
+
     let message = if errors.is_empty() {
         if skipped_count > 0 {
             format!("Successfully imported {inserted_count} projects, skipped {skipped_count} duplicates")
@@ -704,29 +875,22 @@ pub async fn import_democracylab_projects(
         records_processed: Some(total_records),
         records_inserted: Some(inserted_count),
         records_skipped: Some(skipped_count),
-        duplicate_check_columns: Some("Name".to_string()),
+        duplicate_check_columns: Some(duplicate_index.rule.describe()),
         errors,
+        near_duplicates,
+        violations: Vec::new(),
     }))
 }
 
 async fn insert_democracylab_project(
     pool: &Pool<Postgres>,
+    tagger: &Tagger,
+    duplicate_index: &mut DuplicateIndex,
     project: &DemocracyLabProject,
 ) -> Result<InsertResult, sqlx::Error> {
-    // Check for existing record based on name
-    let existing_count = sqlx::query_scalar::<_, i64>(
-        r#"
-        SELECT COUNT(*) FROM projects
-        WHERE name = $1
-        "#
-    )
-    .bind(&project.name)
-    .fetch_one(pool)
-    .await?;
-
-    if existing_count > 0 {
-        println!("Skipping duplicate project: {}", &project.name);
-        return Ok(InsertResult::Skipped);
+    let key = HashMap::from([("name".to_string(), project.name.clone())]);
+    if let Some(result) = check_duplicate(duplicate_index, &key, "project") {
+        return Ok(result);
     }
 
     let id = Uuid::new_v4();
@@ -765,6 +929,561 @@ async fn insert_democracylab_project(
     .bind("democracylab-import")
     .execute(pool)
     .await?;
+    crate::project_tags::tag_stored_project(pool, tagger, id, &project.name, description.as_deref()).await?;
+
+    duplicate_index.add(id.to_string(), key);
+    Ok(InsertResult::Inserted)
+}
+
+/// Pull projects straight from the DemocracyLab API instead of a posted payload
+pub async fn sync_democracylab_projects(
+    pool: web::Data<std::sync::Arc<crate::ApiState>>,
+) -> Result<HttpResponse> {
+    let base_url = {
+        let config_guard = pool.config.lock().unwrap();
+        config_guard.democracylab_api_url()
+    };
+
+    let tagger = pool.tagger.read().unwrap().clone();
+    let result = crate::democracylab::sync_projects(&pool.db, &base_url, &tagger).await;
+    pool.project_index.invalidate_database();
+    match result {
+        Ok(summary) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": summary.errors.is_empty() || summary.inserted + summary.updated > 0,
+            "message": format!(
+                "Synced {} DemocracyLab projects: {} new, {} updated, {} linked by name, {} unchanged{}",
+                summary.projects_fetched, summary.inserted, summary.updated, summary.linked, summary.unchanged,
+                if summary.truncated { format!(" (stopped after {} pages; later pages were not synced)", summary.pages_fetched) } else { String::new() }
+            ),
+            "summary": summary
+        }))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(serde_json::json!({
+            "success": false,
+            "message": format!("DemocracyLab sync from {base_url} failed: {e}")
+        }))),
+    }
+}
+
+/// Attach tags to a record through the tags/taggables tables, creating missing tags
+pub async fn tag_record(
+    pool: &Pool<Postgres>,
+    taggable_type: &str,
+    taggable_id: Uuid,
+    tags: &[String],
+) -> Result<(), sqlx::Error> {
+    for tag in tags {
+        let existing = sqlx::query_scalar::<_, Uuid>("SELECT id FROM tags WHERE LOWER(name) = LOWER($1) LIMIT 1")
+            .bind(tag)
+            .fetch_optional(pool)
+            .await?;
+
+        let tag_id = match existing {
+            Some(id) => id,
+            None => {
+                sqlx::query_scalar::<_, Uuid>("INSERT INTO tags (name) VALUES ($1) RETURNING id")
+                    .bind(tag)
+                    .fetch_one(pool)
+                    .await?
+            }
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO taggables (tag_id, taggable_type, taggable_id)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (tag_id, taggable_type, taggable_id) DO NOTHING
+            "#
+        )
+        .bind(tag_id)
+        .bind(taggable_type)
+        .bind(taggable_id)
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Detach tags from a record; the tags themselves are kept for other records
+pub async fn untag_record(
+    pool: &Pool<Postgres>,
+    taggable_type: &str,
+    taggable_id: Uuid,
+    tags: &[String],
+) -> Result<(), sqlx::Error> {
+    if tags.is_empty() {
+        return Ok(());
+    }
+    let lowered: Vec<String> = tags.iter().map(|t| t.to_lowercase()).collect();
+    sqlx::query(
+        r#"
+        DELETE FROM taggables
+        WHERE taggable_type = $1 AND taggable_id = $2
+          AND tag_id IN (SELECT id FROM tags WHERE LOWER(name) = ANY($3))
+        "#
+    )
+    .bind(taggable_type)
+    .bind(taggable_id)
+    .bind(&lowered)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Column metadata read from information_schema, used by the generic importer
+#[derive(Debug, Clone)]
+pub struct TableColumn {
+    pub name: String,
+    pub udt_name: String,
+    pub max_length: Option<i32>,
+}
+
+/// Columns filled in by the importer or the database when the payload omits them
+const GENERIC_AUDIT_COLUMNS: &[&str] = &["created_by", "modified_user_id"];
+
+fn is_valid_identifier(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 63
+        && name.chars().next().is_some_and(|c| c.is_ascii_lowercase() || c == '_')
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// Normalize an incoming key so "First Name", "first-name" and "FIRST_NAME" all map to first_name
+fn normalize_column_key(key: &str) -> String {
+    key.trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_whitespace() || c == '-' || c == '.' { '_' } else { c })
+        .collect()
+}
+
+/// Read the columns of a public table; returns an empty list when the table does not exist
+pub async fn load_table_columns(
+    pool: &Pool<Postgres>,
+    table_name: &str,
+) -> Result<Vec<TableColumn>, sqlx::Error> {
+    if !is_valid_identifier(table_name) {
+        return Ok(Vec::new());
+    }
+
+    let rows = sqlx::query_as::<_, (String, String, Option<i32>)>(
+        r#"
+        SELECT column_name::text, udt_name::text, character_maximum_length::int4
+        FROM information_schema.columns
+        WHERE table_schema = 'public' AND table_name = $1
+        ORDER BY ordinal_position
+        "#
+    )
+    .bind(table_name)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(name, udt_name, max_length)| TableColumn { name, udt_name, max_length })
+        .collect())
+}
+
+/// Coerce a JSON value into the text form of the column's type, validating it along the way
+fn coerce_value(value: &serde_json::Value, column: &TableColumn) -> Result<Option<String>, String> {
+    let raw = match value {
+        serde_json::Value::Null => return Ok(None),
+        serde_json::Value::String(s) => {
+            let trimmed = s.trim();
+            if trimmed.is_empty() {
+                return Ok(None);
+            }
+            trimmed.to_string()
+        }
+        serde_json::Value::Bool(b) => b.to_string(),
+        serde_json::Value::Number(n) => n.to_string(),
+        other => other.to_string(),
+    };
+
+    match column.udt_name.as_str() {
+        "uuid" => Uuid::parse_str(&raw)
+            .map(|u| Some(u.to_string()))
+            .map_err(|_| format!("{}: '{}' is not a valid UUID", column.name, raw)),
+        "date" => parse_date(&raw)
+            .map(|d| Some(d.format("%Y-%m-%d").to_string()))
+            .ok_or_else(|| format!("{}: '{}' is not a valid date", column.name, raw)),
+        "timestamp" | "timestamptz" => parse_timestamp(&raw)
+            .map(|t| Some(t.format("%Y-%m-%d %H:%M:%S+00").to_string()))
+            .ok_or_else(|| format!("{}: '{}' is not a valid timestamp", column.name, raw)),
+        "bool" => match raw.to_lowercase().as_str() {
+            "true" | "t" | "yes" | "y" | "1" => Ok(Some("true".to_string())),
+            "false" | "f" | "no" | "n" | "0" => Ok(Some("false".to_string())),
+            _ => Err(format!("{}: '{}' is not a valid boolean", column.name, raw)),
+        },
+        "int2" | "int4" | "int8" => {
+            let number = number_text(&raw)
+                .ok_or_else(|| format!("{}: '{}' is not a valid integer", column.name, raw))?;
+            // "12.00" is fine, "12.5" is not; exponents aren't whole-number notation
+            let whole = match number.split_once('.') {
+                Some((whole, fraction)) if fraction.chars().all(|c| c == '0') => whole,
+                Some(_) => return Err(format!("{}: '{}' is not a whole number", column.name, raw)),
+                None => number.as_str(),
+            };
+            let in_range = match column.udt_name.as_str() {
+                "int2" => whole.parse::<i16>().is_ok(),
+                "int4" => whole.parse::<i32>().is_ok(),
+                _ => whole.parse::<i64>().is_ok(),
+            };
+            if !in_range {
+                return Err(format!("{}: '{}' is not a whole number in range for {}", column.name, raw, column.udt_name));
+            }
+            Ok(Some(whole.to_string()))
+        }
+        // Passed to Postgres as text so numeric columns keep every digit
+        "numeric" | "float4" | "float8" => number_text(&raw)
+            .map(Some)
+            .ok_or_else(|| format!("{}: '{}' is not a valid number", column.name, raw)),
+        _ => {
+            if let Some(max) = column.max_length {
+                if raw.chars().count() > max as usize {
+                    return Err(format!("{}: value exceeds {} characters", column.name, max));
+                }
+            }
+            Ok(Some(raw))
+        }
+    }
+}
+
+/// The plain decimal text of a number like "$1,234.50" or "(12)", without going through f64
+fn number_text(raw: &str) -> Option<String> {
+    let trimmed = raw.trim();
+    let (negative, body) = match trimmed.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+        Some(inner) => (true, inner),
+        None => (false, trimmed),
+    };
+    let cleaned: String = body.chars().filter(|c| !matches!(c, '$' | ',' | ' ')).collect();
+    let (sign, unsigned) = match cleaned.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", cleaned.strip_prefix('+').unwrap_or(&cleaned)),
+    };
+    let (mantissa, exponent) = match unsigned.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, Some(exponent)),
+        None => (unsigned, None),
+    };
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    let exponent_ok = exponent.is_none_or(|e| {
+        let e = e.strip_prefix(['+', '-']).unwrap_or(e);
+        !e.is_empty() && digits(e)
+    });
+    if (whole.is_empty() && fraction.is_empty()) || !digits(whole) || !digits(fraction) || !exponent_ok {
+        return None;
+    }
+    // "(5)" is negative; "(-5)" is ambiguous and rejected
+    let sign = match (negative, sign) {
+        (true, "-") => return None,
+        (true, _) => "-",
+        (false, sign) => sign,
+    };
+    Some(format!("{sign}{unsigned}"))
+}
+
+fn parse_date(raw: &str) -> Option<chrono::NaiveDate> {
+    ["%Y-%m-%d", "%m/%d/%Y", "%Y/%m/%d"]
+        .iter()
+        .find_map(|fmt| chrono::NaiveDate::parse_from_str(raw, fmt).ok())
+        .or_else(|| parse_timestamp(raw).map(|t| t.date_naive()))
+}
+
+fn parse_timestamp(raw: &str) -> Option<chrono::DateTime<Utc>> {
+    if let Ok(t) = chrono::DateTime::parse_from_rfc3339(raw) {
+        return Some(t.with_timezone(&Utc));
+    }
+    if let Ok(t) = chrono::NaiveDateTime::parse_from_str(raw, "%Y-%m-%d %H:%M:%S") {
+        return Some(t.and_utc());
+    }
+    ["%Y-%m-%d", "%m/%d/%Y"]
+        .iter()
+        .find_map(|fmt| chrono::NaiveDate::parse_from_str(raw, fmt).ok())
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|t| t.and_utc())
+}
+
+/// Map incoming keys onto table columns case-insensitively and coerce each value to its column type
+fn map_record_values<'a>(
+    columns: &'a [TableColumn],
+    record: &HashMap<String, serde_json::Value>,
+) -> Result<Vec<(&'a TableColumn, Option<String>)>, String> {
+    let mut values: Vec<(&TableColumn, Option<String>)> = Vec::new();
+    for (key, value) in record {
+        let normalized = normalize_column_key(key);
+        if let Some(column) = columns.iter().find(|c| c.name == normalized) {
+            if values.iter().any(|(c, _)| c.name == column.name) {
+                continue;
+            }
+            values.push((column, coerce_value(value, column)?));
+        }
+    }
+
+    if values.iter().all(|(_, v)| v.is_none()) {
+        return Err("No fields matched the table's columns".to_string());
+    }
+
+    Ok(values)
+}
+
+/// Values of the duplicate-rule fields present in a mapped record
+fn duplicate_key_values(duplicate_index: &DuplicateIndex, values: &[(&TableColumn, Option<String>)]) -> HashMap<String, String> {
+    duplicate_index.key(&values.iter().map(|(c, v)| (c.name.clone(), v.clone())).collect())
+}
+
+/// Insert mapped values, filling audit columns the payload didn't provide; returns the new id
+async fn insert_mapped_values(
+    pool: &Pool<Postgres>,
+    table_name: &str,
+    columns: &[TableColumn],
+    mut values: Vec<(&TableColumn, Option<String>)>,
+    creator: &str,
+) -> Result<String, sqlx::Error> {
+    for audit in GENERIC_AUDIT_COLUMNS {
+        if let Some(column) = columns.iter().find(|c| c.name == *audit) {
+            if !values.iter().any(|(c, _)| c.name == column.name) {
+                values.push((column, Some(creator.to_string())));
+            }
+        }
+    }
+
+    let column_list: Vec<String> = values.iter().map(|(c, _)| format!("\"{}\"", c.name)).collect();
+    let placeholders: Vec<String> = values
+        .iter()
+        .enumerate()
+        .map(|(i, (c, _))| format!("${}::{}", i + 1, c.udt_name))
+        .collect();
+    let sql = format!(
+        "INSERT INTO \"{}\" ({}) VALUES ({}) RETURNING id::text",
+        table_name,
+        column_list.join(", "),
+        placeholders.join(", ")
+    );
+
+    let mut query = sqlx::query_scalar::<_, String>(&sql);
+    for (_, value) in &values {
+        query = query.bind(value.clone());
+    }
+    query.fetch_one(pool).await
+}
+
+/// Update an existing row with mapped values; returns false when nothing changed
+async fn update_mapped_values(
+    pool: &Pool<Postgres>,
+    table_name: &str,
+    columns: &[TableColumn],
+    id: &str,
+    values: &[(&TableColumn, Option<String>)],
+    modifier: &str,
+) -> Result<bool, sqlx::Error> {
+    // date_modified and modified_user_id are set below, so mapped values for them are dropped
+    let updatable: Vec<&(&TableColumn, Option<String>)> = values
+        .iter()
+        .filter(|(c, _)| !["id", "date_modified", "modified_user_id"].contains(&c.name.as_str()))
+        .collect();
+    if updatable.is_empty() {
+        return Ok(false);
+    }
+
+    let assignments: Vec<String> = updatable
+        .iter()
+        .enumerate()
+        .map(|(i, (c, _))| format!("\"{}\" = ${}::{}", c.name, i + 2, c.udt_name))
+        .collect();
+    let changed: Vec<String> = updatable
+        .iter()
+        .enumerate()
+        .map(|(i, (c, _))| format!("\"{}\" IS DISTINCT FROM ${}::{}", c.name, i + 2, c.udt_name))
+        .collect();
+
+    let mut extra = String::new();
+    if columns.iter().any(|c| c.name == "date_modified") {
+        extra.push_str(", \"date_modified\" = CURRENT_TIMESTAMP");
+    }
+    let track_modifier = columns.iter().any(|c| c.name == "modified_user_id");
+    if track_modifier {
+        extra.push_str(&format!(", \"modified_user_id\" = ${}", updatable.len() + 2));
+    }
+
+    let sql = format!(
+        "UPDATE \"{}\" SET {}{} WHERE id = $1::uuid AND ({})",
+        table_name,
+        assignments.join(", "),
+        extra,
+        changed.join(" OR ")
+    );
+
+    let mut query = sqlx::query(&sql).bind(id);
+    for (_, value) in &updatable {
+        query = query.bind(value.clone());
+    }
+    if track_modifier {
+        query = query.bind(modifier);
+    }
 
+    Ok(query.execute(pool).await?.rows_affected() > 0)
+}
+
+/// Import one record into any table using its information_schema column list
+async fn import_generic_record(
+    pool: &Pool<Postgres>,
+    table_name: &str,
+    columns: &[TableColumn],
+    validator: &TableValidator,
+    duplicate_index: &mut DuplicateIndex,
+    record: &HashMap<String, serde_json::Value>,
+) -> Result<InsertResult, Box<dyn std::error::Error>> {
+    let values = map_record_values(columns, record)?;
+    let row_violations = validator.validate(0, &values.iter().map(|(c, v)| (c.name.clone(), v.clone())).collect());
+    if !row_violations.is_empty() {
+        return Ok(InsertResult::Invalid(row_violations));
+    }
+    let key = duplicate_key_values(duplicate_index, &values);
+
+    if let Some(result) = check_duplicate(duplicate_index, &key, table_name) {
+        return Ok(result);
+    }
+
+    let id = insert_mapped_values(pool, table_name, columns, values, "json-import").await?;
+    duplicate_index.add(id, key);
     Ok(InsertResult::Inserted)
-}
\ No newline at end of file
+}
+
+/// Outcome of upserting one record during a scheduled sync
+#[derive(Debug)]
+pub enum UpsertOutcome {
+    Inserted { id: String, values: HashMap<String, Option<String>> },
+    Updated { id: String, values: HashMap<String, Option<String>> },
+    Unchanged,
+    NearDuplicate(NearDuplicate),
+    Invalid(Vec<CellViolation>),
+}
+
+/// Insert a record, or update the existing row that matches its duplicate key exactly
+pub async fn upsert_generic_record(
+    pool: &Pool<Postgres>,
+    table_name: &str,
+    columns: &[TableColumn],
+    duplicate_index: &mut DuplicateIndex,
+    record: &HashMap<String, serde_json::Value>,
+    validator: &TableValidator,
+    actor: &str,
+) -> Result<UpsertOutcome, Box<dyn std::error::Error + Send + Sync>> {
+    let values = map_record_values(columns, record)?;
+    let logged: HashMap<String, Option<String>> = values.iter().map(|(c, v)| (c.name.clone(), v.clone())).collect();
+    let row_violations = validator.validate(0, &logged);
+    if !row_violations.is_empty() {
+        return Ok(UpsertOutcome::Invalid(row_violations));
+    }
+    let key = duplicate_key_values(duplicate_index, &values);
+
+    let outcome = match duplicate_index.check(&key) {
+        DuplicateCheck::Exact { existing_id } => {
+            if update_mapped_values(pool, table_name, columns, &existing_id, &values, actor).await? {
+                Ok(UpsertOutcome::Updated { id: existing_id, values: logged })
+            } else {
+                Ok(UpsertOutcome::Unchanged)
+            }
+        }
+        DuplicateCheck::Near { existing_id, existing_values, similarity } => {
+            Ok(UpsertOutcome::NearDuplicate(NearDuplicate {
+                row: 0,
+                values: key,
+                existing_id,
+                existing_values,
+                similarity,
+            }))
+        }
+        DuplicateCheck::Unique => {
+            let id = insert_mapped_values(pool, table_name, columns, values, actor).await?;
+            duplicate_index.add(id.clone(), key);
+            Ok(UpsertOutcome::Inserted { id, values: logged })
+        }
+    };
+
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn column(udt_name: &str, max_length: Option<i32>) -> TableColumn {
+        TableColumn { name: "field".to_string(), udt_name: udt_name.to_string(), max_length }
+    }
+
+    fn coerce(value: serde_json::Value, udt_name: &str) -> Result<Option<String>, String> {
+        coerce_value(&value, &column(udt_name, None))
+    }
+
+    #[test]
+    fn number_text_keeps_plain_decimal_digits() {
+        assert_eq!(number_text("$1,234.50").as_deref(), Some("1234.50"));
+        assert_eq!(number_text(" (12) ").as_deref(), Some("-12"));
+        assert_eq!(number_text("+7").as_deref(), Some("7"));
+        assert_eq!(number_text("-0.000000000000000001").as_deref(), Some("-0.000000000000000001"));
+        assert_eq!(number_text("1.5E+3").as_deref(), Some("1.5E+3"));
+        assert_eq!(number_text(".5").as_deref(), Some(".5"));
+
+        for invalid in ["", "(-5)", "12abc", "1.2.3", "1e", "e5", ".", "--5", "N/A"] {
+            assert_eq!(number_text(invalid), None, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn integers_accept_whole_valued_decimals_within_range() {
+        assert_eq!(coerce(json!(12.0), "int4"), Ok(Some("12".to_string())));
+        assert_eq!(coerce(json!("1,200.00"), "int8"), Ok(Some("1200".to_string())));
+        assert_eq!(coerce(json!("(3)"), "int2"), Ok(Some("-3".to_string())));
+
+        assert_eq!(coerce(json!(12.5), "int4"), Err("field: '12.5' is not a whole number".to_string()));
+        assert_eq!(coerce(json!("1e3"), "int4"), Err("field: '1e3' is not a whole number in range for int4".to_string()));
+        assert_eq!(coerce(json!(40000), "int2"), Err("field: '40000' is not a whole number in range for int2".to_string()));
+        assert_eq!(coerce(json!("twelve"), "int4"), Err("field: 'twelve' is not a valid integer".to_string()));
+    }
+
+    #[test]
+    fn numerics_keep_every_digit() {
+        assert_eq!(coerce(json!("$12,345,678.901234567890123"), "numeric"), Ok(Some("12345678.901234567890123".to_string())));
+        assert_eq!(coerce(json!(0.25), "float8"), Ok(Some("0.25".to_string())));
+        assert_eq!(coerce(json!(true), "numeric"), Err("field: 'true' is not a valid number".to_string()));
+    }
+
+    #[test]
+    fn dates_and_timestamps_are_normalized() {
+        assert_eq!(coerce(json!("03/15/2024"), "date"), Ok(Some("2024-03-15".to_string())));
+        assert_eq!(coerce(json!("2024/03/15"), "date"), Ok(Some("2024-03-15".to_string())));
+        assert_eq!(coerce(json!("2024-03-15T23:30:00Z"), "date"), Ok(Some("2024-03-15".to_string())));
+        assert_eq!(coerce(json!("2024-03-15T10:00:00-05:00"), "timestamptz"), Ok(Some("2024-03-15 15:00:00+00".to_string())));
+        assert_eq!(coerce(json!("03/15/2024"), "timestamp"), Ok(Some("2024-03-15 00:00:00+00".to_string())));
+
+        assert_eq!(coerce(json!("2024-02-30"), "date"), Err("field: '2024-02-30' is not a valid date".to_string()));
+        assert_eq!(coerce(json!("soon"), "timestamptz"), Err("field: 'soon' is not a valid timestamp".to_string()));
+    }
+
+    #[test]
+    fn booleans_accept_common_spellings() {
+        for yes in [json!(true), json!("Yes"), json!("t"), json!(1), json!(" Y ")] {
+            assert_eq!(coerce(yes.clone(), "bool"), Ok(Some("true".to_string())), "{yes}");
+        }
+        for no in [json!(false), json!("NO"), json!("f"), json!(0)] {
+            assert_eq!(coerce(no.clone(), "bool"), Ok(Some("false".to_string())), "{no}");
+        }
+        assert_eq!(coerce(json!("maybe"), "bool"), Err("field: 'maybe' is not a valid boolean".to_string()));
+    }
+
+    #[test]
+    fn blanks_uuids_and_text_limits() {
+        assert_eq!(coerce(json!(null), "int4"), Ok(None));
+        assert_eq!(coerce(json!("   "), "date"), Ok(None));
+
+        let id = "6F9619FF-8B86-D011-B42D-00C04FC964FF";
+        assert_eq!(coerce(json!(id), "uuid"), Ok(Some(id.to_lowercase())));
+        assert_eq!(coerce(json!("not-a-uuid"), "uuid"), Err("field: 'not-a-uuid' is not a valid UUID".to_string()));
+
+        assert_eq!(coerce_value(&json!(" café "), &column("varchar", Some(4))), Ok(Some("café".to_string())));
+        assert_eq!(coerce_value(&json!("cafés"), &column("varchar", Some(4))), Err("field: value exceeds 4 characters".to_string()));
+    }
+}
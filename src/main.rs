@@ -7,7 +7,7 @@ use clap::{Parser, Subcommand};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use sqlx::{postgres::PgPoolOptions, Pool, Postgres, Row, Column, ValueRef};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
 use std::collections::HashMap;
 use std::process::{Child, Command};
 use std::time::{SystemTime, UNIX_EPOCH};
@@ -24,9 +24,24 @@ use std::sync::mpsc::channel;
 // use hyper_rustls::HttpsConnectorBuilder;
 
 mod import;
+mod dedup;
+mod democracylab;
+mod sync;
+mod csv;
+mod validation;
 mod gemini_insights;
 mod claude_insights;
+mod ai_provider;
+mod ai_cache;
+mod ai_usage;
+mod data_context;
+mod sse;
 mod recommendations;
+mod project_index;
+mod project_feedback;
+mod skills_matching;
+mod tagger;
+mod project_tags;
 use recommendations::RecommendationRequest;
 
 // Configuration structure
@@ -38,10 +53,25 @@ struct Config {
     server_port: u16,
     excel_file_path: String,
     site_favicon: Option<String>,
+    democracylab_api_url: Option<String>,
+    democracylab_sync_interval_minutes: Option<u64>,
+    /// Comma-separated recommendation sources in priority order: "excel", "postgres" or both
+    recommendation_sources: Option<String>,
+    /// Gemini defaults; requests may override them within the allowlist
+    gemini_model: Option<String>,
+    gemini_allowed_models: Option<String>,
+    gemini_temperature: Option<f32>,
+    gemini_max_output_tokens: Option<u32>,
+    /// Hours AI answers stay in the shared cache; 0 turns it off
+    ai_cache_ttl_hours: Option<u64>,
+    /// Tokens all AI calls may use per UTC day and month before further calls are refused
+    ai_daily_token_budget: Option<u64>,
+    ai_monthly_token_budget: Option<u64>,
 }
 
 // Thread-safe configuration holder
 type SharedConfig = Arc<Mutex<Config>>;
+type SharedMappings = Arc<RwLock<recommendations::PreferenceMappings>>;
 
 impl Config {
     fn from_env() -> anyhow::Result<Self> {
@@ -68,6 +98,28 @@ impl Config {
                 excel_file_path: std::env::var("EXCEL_FILE_PATH")
                     .unwrap_or_else(|_| "preferences/projects/DFC-ActiveProjects.xlsx".to_string()),
                 site_favicon: std::env::var("SITE_FAVICON").ok(),
+                democracylab_api_url: std::env::var("DEMOCRACYLAB_API_URL").ok(),
+                democracylab_sync_interval_minutes: std::env::var("DEMOCRACYLAB_SYNC_INTERVAL_MINUTES")
+                    .ok()
+                    .and_then(|v| v.parse().ok()),
+                recommendation_sources: std::env::var("RECOMMENDATION_SOURCES").ok(),
+                gemini_model: std::env::var("GEMINI_MODEL").ok(),
+                gemini_allowed_models: std::env::var("GEMINI_ALLOWED_MODELS").ok(),
+                gemini_temperature: std::env::var("GEMINI_TEMPERATURE")
+                    .ok()
+                    .and_then(|v| v.parse().ok()),
+                gemini_max_output_tokens: std::env::var("GEMINI_MAX_OUTPUT_TOKENS")
+                    .ok()
+                    .and_then(|v| v.parse().ok()),
+                ai_cache_ttl_hours: std::env::var("AI_CACHE_TTL_HOURS")
+                    .ok()
+                    .and_then(|v| v.parse().ok()),
+                ai_daily_token_budget: std::env::var("AI_DAILY_TOKEN_BUDGET")
+                    .ok()
+                    .and_then(|v| v.parse().ok()),
+                ai_monthly_token_budget: std::env::var("AI_MONTHLY_TOKEN_BUDGET")
+                    .ok()
+                    .and_then(|v| v.parse().ok()),
             })
         }
     }
@@ -94,6 +146,24 @@ impl Config {
         Self::from_env()
     }
     
+    fn democracylab_api_url(&self) -> String {
+        self.democracylab_api_url
+            .clone()
+            .filter(|url| !url.is_empty())
+            .unwrap_or_else(|| democracylab::DEFAULT_API_URL.to_string())
+    }
+    
+    fn recommendation_sources(&self) -> Vec<String> {
+        let sources: Vec<String> = self.recommendation_sources
+            .as_deref()
+            .unwrap_or("excel")
+            .split(',')
+            .map(|s| s.trim().to_lowercase())
+            .filter(|s| !s.is_empty())
+            .collect();
+        if sources.is_empty() { vec!["excel".to_string()] } else { sources }
+    }
+    
     fn build_database_url() -> String {
         // First, try COMMONS component variables (more secure)
         if let (Ok(host), Ok(port), Ok(name), Ok(user), Ok(password)) = (
@@ -181,12 +251,68 @@ enum Commands {
     Serve,
     /// Initialize database schema
     InitDb,
+    /// Pull projects from the DemocracyLab API into the projects table
+    SyncDemocracylab,
 }
 
 // API State
 struct ApiState {
     db: Pool<Postgres>,
     config: SharedConfig,
+    recommendation_mappings: SharedMappings,
+    project_index: Arc<project_index::ProjectIndexCache>,
+    tagger: tagger::SharedTagger,
+    provider_configs: ai_provider::SharedProviderConfigs,
+    running_syncs: sync::RunningSources,
+}
+
+// Watch the recommendation mappings file so edits apply without a restart
+fn start_mappings_watcher(mappings: SharedMappings) -> anyhow::Result<()> {
+    use notify::{Event, EventKind};
+
+    let mappings_path = Path::new(recommendations::MAPPINGS_PATH);
+    let Some(config_dir) = mappings_path.parent().filter(|dir| dir.exists()) else {
+        log::warn!("No config directory found; recommendation mappings will not hot reload");
+        return Ok(());
+    };
+    let file_name = mappings_path.file_name().map(|name| name.to_os_string());
+
+    let (tx, rx) = channel();
+    let mut watcher = RecommendedWatcher::new(tx, NotifyConfig::default())?;
+    // Watch the directory rather than the file so replacing the file (as editors and PUT do) is seen
+    watcher.watch(config_dir, RecursiveMode::NonRecursive)?;
+    log::info!("Started watching {} for changes", recommendations::MAPPINGS_PATH);
+
+    // notify delivers events over a blocking channel, so receive them on their own thread
+    std::thread::spawn(move || {
+        let _watcher = watcher;
+        for event in rx {
+            match event {
+                Ok(Event { kind: EventKind::Modify(_) | EventKind::Create(_), paths, .. })
+                    if paths.iter().any(|path| path.file_name() == file_name.as_deref()) => {
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+
+                    match recommendations::load_mappings(recommendations::MAPPINGS_PATH) {
+                        Ok(new_mappings) => {
+                            if let Ok(mut guard) = mappings.write() {
+                                if *guard != new_mappings {
+                                    *guard = new_mappings;
+                                    log::info!("Recommendation mappings reloaded ({} preferences)", guard.len());
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            log::error!("Failed to reload recommendation mappings, keeping the previous ones: {e}");
+                        }
+                    }
+                }
+                Ok(_) => {} // Ignore other events
+                Err(e) => log::error!("File watcher error: {e}"),
+            }
+        }
+    });
+
+    Ok(())
 }
 
 // Function to start watching .env file for changes
@@ -202,40 +328,40 @@ fn start_env_watcher(config: SharedConfig) -> anyhow::Result<()> {
         watcher.watch(env_path, RecursiveMode::NonRecursive)?;
         log::info!("Started watching .env file for changes");
         
-        // Spawn a background thread to handle file change events
+        // Spawn a background thread to handle file change events; notify's channel
+        // blocks, so this must not run on the async runtime
         let config_clone = config.clone();
-        tokio::spawn(async move {
+        std::thread::spawn(move || {
+            let _watcher = watcher;
             loop {
                 match rx.recv() {
                     Ok(event) => {
                         match event {
                             Ok(Event { kind: EventKind::Modify(_), paths, .. }) |
-                            Ok(Event { kind: EventKind::Create(_), paths, .. }) => {
-                                if paths.iter().any(|path| path.file_name() == Some(std::ffi::OsStr::new(".env"))) {
-                                    log::info!(".env file changed, reloading configuration...");
-                                    
-                                    // Add a small delay to ensure file write is complete
-                                    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-                                    
-                                    match Config::reload() {
-                                        Ok(new_config) => {
-                                            if let Ok(mut config_guard) = config_clone.lock() {
-                                                *config_guard = new_config;
-                                                log::info!("Configuration reloaded successfully");
-                                            } else {
-                                                log::error!("Failed to acquire config lock for reload");
-                                            }
-                                        }
-                                        Err(e) => {
-                                            log::error!("Failed to reload configuration: {e}");
+                            Ok(Event { kind: EventKind::Create(_), paths, .. })
+                                if paths.iter().any(|path| path.file_name() == Some(std::ffi::OsStr::new(".env"))) => {
+                                log::info!(".env file changed, reloading configuration...");
+                                
+                                // Add a small delay to ensure file write is complete
+                                std::thread::sleep(std::time::Duration::from_millis(100));
+                                
+                                match Config::reload() {
+                                    Ok(new_config) => {
+                                        if let Ok(mut config_guard) = config_clone.lock() {
+                                            *config_guard = new_config;
+                                            log::info!("Configuration reloaded successfully");
+                                        } else {
+                                            log::error!("Failed to acquire config lock for reload");
                                         }
                                     }
+                                    Err(e) => {
+                                        log::error!("Failed to reload configuration: {e}");
+                                    }
                                 }
                             }
-                            Ok(Event { kind: EventKind::Remove(_), paths, .. }) => {
-                                if paths.iter().any(|path| path.file_name() == Some(std::ffi::OsStr::new(".env"))) {
-                                    log::warn!(".env file was removed");
-                                }
+                            Ok(Event { kind: EventKind::Remove(_), paths, .. })
+                                if paths.iter().any(|path| path.file_name() == Some(std::ffi::OsStr::new(".env"))) => {
+                                log::warn!(".env file was removed");
                             }
                             _ => {} // Ignore other events
                         }
@@ -247,9 +373,7 @@ fn start_env_watcher(config: SharedConfig) -> anyhow::Result<()> {
                 }
             }
         });
-        
-        // Keep the watcher alive by storing it
-        std::mem::forget(watcher);
+
     } else {
         log::warn!("No .env file found to watch");
     }
@@ -257,6 +381,28 @@ fn start_env_watcher(config: SharedConfig) -> anyhow::Result<()> {
     Ok(())
 }
 
+// Periodically pull DemocracyLab projects when DEMOCRACYLAB_SYNC_INTERVAL_MINUTES is set
+fn start_democracylab_sync_schedule(state: Arc<ApiState>) {
+    let interval_minutes = state.config.lock().unwrap().democracylab_sync_interval_minutes;
+    let Some(minutes) = interval_minutes.filter(|m| *m > 0) else {
+        return;
+    };
+    
+    log::info!("Scheduling DemocracyLab sync every {minutes} minutes");
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(minutes * 60));
+        loop {
+            interval.tick().await;
+            let base_url = state.config.lock().unwrap().democracylab_api_url();
+            let tagger = state.tagger.read().unwrap().clone();
+            if let Err(e) = democracylab::sync_projects(&state.db, &base_url, &tagger).await {
+                log::error!("Scheduled DemocracyLab sync failed: {e}");
+            }
+            state.project_index.invalidate_database();
+        }
+    });
+}
+
 // Request/Response types for projects
 #[derive(Debug, Serialize, Deserialize)]
 struct CreateProjectRequest {
@@ -267,6 +413,12 @@ struct CreateProjectRequest {
     estimated_end_date: Option<String>,
 }
 
+// Query string for listing projects, e.g. ?tags=healthcare,agriculture
+#[derive(Debug, Deserialize)]
+struct ProjectsQuery {
+    tags: Option<String>,
+}
+
 // Google Cloud project creation request
 #[derive(Debug, Serialize, Deserialize)]
 struct CreateGoogleProjectRequest {
@@ -285,6 +437,7 @@ struct GoogleAuthRequest {
 
 // Google OAuth verification response
 #[derive(Debug, Serialize, Deserialize)]
+#[allow(dead_code)]
 struct GoogleAuthResponse {
     success: bool,
     name: String,
@@ -725,7 +878,7 @@ async fn create_google_project(req: web::Json<CreateGoogleProjectRequest>) -> Re
     }
     
     // Validate service key is valid JSON
-    if let Err(_) = serde_json::from_str::<serde_json::Value>(&req.service_key) {
+    if serde_json::from_str::<serde_json::Value>(&req.service_key).is_err() {
         return Ok(HttpResponse::BadRequest().json(json!({
             "success": false,
             "error": "Service account key must be valid JSON",
@@ -990,7 +1143,7 @@ async fn get_member_by_email(path: web::Path<String>) -> Result<HttpResponse> {
             })))
         }
         Err(e) => {
-            return Ok(HttpResponse::BadRequest().json(json!({
+            Ok(HttpResponse::BadRequest().json(json!({
                 "success": false,
                 "error": format!("Google Sheets credentials invalid: {}", e),
                 "email": email,
@@ -998,7 +1151,7 @@ async fn get_member_by_email(path: web::Path<String>) -> Result<HttpResponse> {
                     "env_variable": "GOOGLE_SERVICE_KEY",
                     "format": "Valid JSON service account key from Google Cloud Console"
                 }
-            })));
+            })))
         }
     }
 }
@@ -1065,7 +1218,7 @@ async fn save_member_data(req: web::Json<GoogleSheetsMemberRequest>) -> Result<H
             })))
         }
         Err(e) => {
-            return Ok(HttpResponse::BadRequest().json(json!({
+            Ok(HttpResponse::BadRequest().json(json!({
                 "success": false,
                 "error": format!("Google Sheets credentials invalid: {}", e),
                 "email": req.email,
@@ -1073,7 +1226,7 @@ async fn save_member_data(req: web::Json<GoogleSheetsMemberRequest>) -> Result<H
                     "env_variable": "GOOGLE_SERVICE_KEY",
                     "format": "Valid JSON service account key from Google Cloud Console"
                 }
-            })));
+            })))
         }
     }
 }
@@ -1154,14 +1307,141 @@ struct ProxyResponse {
 
 // Analyze data with Claude Code CLI
 async fn get_recommendations_handler(req: web::Json<RecommendationRequest>, data: web::Data<Arc<ApiState>>) -> Result<HttpResponse> {
-    let excel_file_path = {
-        let config_guard = data.config.lock().unwrap();
-        config_guard.excel_file_path.clone()
+    let sources = project_index::configured_sources(&data);
+    let projects = match project_index::load_projects(&data.project_index, &sources).await {
+        Ok(projects) => projects,
+        Err(e) => return Ok(HttpResponse::InternalServerError().json(json!({ "error": e.to_string() }))),
+    };
+
+    let mappings = data.recommendation_mappings.read().unwrap().clone();
+    let scored = recommendations::score_projects(&projects, &req, &mappings);
+    
+    let weight = req.collaborative_weight.unwrap_or(0.0).clamp(0.0, 1.0);
+    let (scored, collaborative) = if weight > 0.0 {
+        // Collaborative filtering only adds projects that pass the hard filters
+        let candidates: Vec<recommendations::Project> =
+            projects.projects.iter().filter(|p| req.filters.matches(p)).cloned().collect();
+        collaborative_blend(&data.db, scored, &candidates, req.user_id.as_deref(), weight).await
+    } else {
+        (scored, None)
+    };
+    
+    let scored = match req.diversity {
+        Some(diversity) if diversity > 0.0 => recommendations::diversify(scored, diversity, req.limit, req.offset),
+        _ => scored,
     };
-    match recommendations::get_recommendations(&req.preferences, &excel_file_path) {
-        Ok(projects) => Ok(HttpResponse::Ok().json(projects)),
-        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({ "error": e.to_string() }))),
+    
+    let mut response = recommendations::paginate(scored, req.limit, req.offset);
+    response.collaborative = collaborative;
+    response.facets = Some(recommendations::facets(&projects.projects, &req.filters));
+    
+    // Feedback is optional; recommendations still work before init-db creates the tables
+    let results = response.results.iter_mut().map(|r| &mut r.project).collect();
+    if let Err(e) = project_feedback::annotate_projects(&data.db, results, req.user_id.as_deref()).await {
+        log::warn!("Could not load project feedback: {e}");
     }
+    
+    Ok(HttpResponse::Ok().json(response))
+}
+
+// Blend in collaborative filtering, falling back to content-only ranking when the user
+// has no votes or stars yet (or nothing similar was found)
+async fn collaborative_blend(
+    pool: &Pool<Postgres>,
+    scored: Vec<recommendations::ScoredProject>,
+    projects: &[recommendations::Project],
+    user_id: Option<&str>,
+    weight: f64,
+) -> (Vec<recommendations::ScoredProject>, Option<recommendations::CollaborativeInfo>) {
+    let info = |applied: bool, user_items: usize, fallback: Option<&str>| Some(recommendations::CollaborativeInfo {
+        weight,
+        applied,
+        user_items,
+        fallback: fallback.map(String::from),
+    });
+    
+    let Some(user_id) = user_id.filter(|u| !u.trim().is_empty()) else {
+        return (scored, info(false, 0, Some("user_id is required for collaborative filtering")));
+    };
+    let interactions = match project_feedback::load_interactions(pool).await {
+        Ok(interactions) => interactions,
+        Err(e) => {
+            log::warn!("Could not load votes for collaborative filtering: {e}");
+            return (scored, info(false, 0, Some("votes and stars are unavailable")));
+        }
+    };
+    
+    let user_items = interactions.iter().filter(|i| i.user_id == user_id).count();
+    if user_items == 0 {
+        return (scored, info(false, 0, Some("user has not rated or starred any projects yet")));
+    }
+    let mut predictions = recommendations::collaborative_scores(&interactions, user_id);
+    predictions.retain(|key, _| projects.iter().any(|p| &p.key == key));
+    if predictions.is_empty() {
+        return (scored, info(false, user_items, Some("no other users share this user's ratings yet")));
+    }
+    
+    let blended = recommendations::blend_collaborative(scored, projects, &predictions, weight);
+    (blended, info(true, user_items, None))
+}
+
+async fn get_recommendation_source(data: web::Data<Arc<ApiState>>) -> Result<HttpResponse> {
+    let index = data.project_index.snapshot();
+    let sources = data.config.lock().unwrap().recommendation_sources();
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "sources": sources,
+        "source": index.status()
+    })))
+}
+
+// Filter values and counts across every configured source, before any filter is applied
+async fn get_recommendation_facets(data: web::Data<Arc<ApiState>>) -> Result<HttpResponse> {
+    let sources = project_index::configured_sources(&data);
+    match project_index::load_projects(&data.project_index, &sources).await {
+        Ok(projects) => Ok(HttpResponse::Ok().json(json!({
+            "success": true,
+            "total": projects.projects.len(),
+            "facets": recommendations::facets(&projects.projects, &Default::default())
+        }))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({
+            "success": false,
+            "error": e.to_string()
+        }))),
+    }
+}
+
+async fn get_recommendation_mappings(data: web::Data<Arc<ApiState>>) -> Result<HttpResponse> {
+    let mappings = data.recommendation_mappings.read().unwrap().clone();
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "path": recommendations::MAPPINGS_PATH,
+        "mappings": mappings
+    })))
+}
+
+// Replace the preference mappings; the file is rewritten so the change survives a restart
+async fn update_recommendation_mappings(
+    req: web::Json<recommendations::PreferenceMappings>,
+    data: web::Data<Arc<ApiState>>,
+) -> Result<HttpResponse> {
+    let mappings = req.into_inner();
+
+    if let Err(e) = recommendations::save_mappings(recommendations::MAPPINGS_PATH, &mappings) {
+        return Ok(HttpResponse::BadRequest().json(json!({
+            "success": false,
+            "error": e.to_string()
+        })));
+    }
+
+    let count = mappings.len();
+    *data.recommendation_mappings.write().unwrap() = mappings;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "message": format!("Saved {count} preference mappings"),
+        "path": recommendations::MAPPINGS_PATH
+    })))
 }
 
 
@@ -1561,23 +1841,55 @@ async fn db_execute_query(
 
 // Create a new project
 // Get all projects from database
-async fn get_projects(data: web::Data<Arc<ApiState>>) -> Result<HttpResponse> {
+async fn get_projects(data: web::Data<Arc<ApiState>>, query: web::Query<ProjectsQuery>) -> Result<HttpResponse> {
+    // Projects must carry every requested tag, compared case-insensitively
+    let tag_filter: Vec<String> = query.tags.as_deref().unwrap_or_default()
+        .split(',')
+        .map(|t| t.trim().to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect();
+
     let projects_query = sqlx::query(
-        "SELECT id, name, description, status, date_entered, date_modified FROM projects ORDER BY date_modified DESC LIMIT 50"
+        r#"
+        SELECT p.id, p.name, p.description, p.status, p.date_entered, p.date_modified,
+               COALESCE(array_agg(t.name ORDER BY t.name) FILTER (WHERE t.name IS NOT NULL), '{}') AS tags
+        FROM projects p
+        LEFT JOIN taggables tg ON tg.taggable_type = 'projects' AND tg.taggable_id = p.id
+        LEFT JOIN tags t ON t.id = tg.tag_id
+        GROUP BY p.id
+        HAVING cardinality($1::text[]) = 0
+            OR COUNT(DISTINCT LOWER(t.name)) FILTER (WHERE LOWER(t.name) = ANY($1)) = cardinality($1::text[])
+        ORDER BY p.date_modified DESC
+        LIMIT 50
+        "#
     )
+    .bind(&tag_filter)
     .fetch_all(&data.db)
     .await;
     
     match projects_query {
         Ok(rows) => {
+            let keys: Vec<String> = rows.iter().map(|row| row.get::<Uuid, _>("id").to_string()).collect();
+            let feedback = project_feedback::load_summaries(&data.db, &keys, None).await.unwrap_or_else(|e| {
+                log::warn!("Could not load project feedback: {e}");
+                HashMap::new()
+            });
+            
             let projects: Vec<serde_json::Value> = rows.into_iter().map(|row| {
+                let id = row.get::<Uuid, _>("id");
+                let summary = feedback.get(&id.to_string()).cloned().unwrap_or_default();
                 json!({
-                    "id": row.get::<Uuid, _>("id"),
+                    "id": id,
                     "name": row.get::<String, _>("name"),
                     "description": row.get::<Option<String>, _>("description"),
                     "status": row.get::<Option<String>, _>("status"),
                     "created_date": row.get::<chrono::DateTime<Utc>, _>("date_entered"),
-                    "modified_date": row.get::<chrono::DateTime<Utc>, _>("date_modified")
+                    "modified_date": row.get::<chrono::DateTime<Utc>, _>("date_modified"),
+                    "tags": row.get::<Vec<String>, _>("tags"),
+                    "average_rating": summary.average_rating,
+                    "vote_count": summary.vote_count,
+                    "star_count": summary.star_count,
+                    "comment_count": summary.comment_count
                 })
             }).collect();
             
@@ -1636,10 +1948,21 @@ async fn create_project(
     .await;
     
     match result {
-        Ok(_) => Ok(HttpResponse::Created().json(json!({
-            "id": id.to_string(),
-            "message": "Project created successfully"
-        }))),
+        Ok(_) => {
+            let tagger = data.tagger.read().unwrap().clone();
+            let tags = project_tags::tag_stored_project(&data.db, &tagger, id, &req.name, req.description.as_deref())
+                .await
+                .unwrap_or_else(|e| {
+                    log::warn!("Could not tag project {id}: {e}");
+                    Vec::new()
+                });
+            data.project_index.invalidate_database();
+            Ok(HttpResponse::Created().json(json!({
+                "id": id.to_string(),
+                "tags": tags,
+                "message": "Project created successfully"
+            })))
+        }
         Err(e) => Ok(HttpResponse::BadRequest().json(json!({
             "error": e.to_string()
         }))),
@@ -1724,10 +2047,15 @@ async fn init_database(pool: &Pool<Postgres>) -> anyhow::Result<()> {
             date_entered TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
             date_modified TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
             created_by VARCHAR(36),
-            modified_user_id VARCHAR(36)
+            modified_user_id VARCHAR(36),
+            project_number VARCHAR(100)
         )
         "#
     ).execute(pool).await?;
+    // Workbook project number, used to merge with the Excel source; added after the table shipped
+    sqlx::query("ALTER TABLE projects ADD COLUMN IF NOT EXISTS project_number VARCHAR(100)")
+        .execute(pool)
+        .await?;
     
     // Create opportunities table
     sqlx::query(
@@ -2061,6 +2389,186 @@ async fn init_database(pool: &Pool<Postgres>) -> anyhow::Result<()> {
         "#
     ).execute(pool).await?;
     
+    // External source ids for synced projects (e.g. DemocracyLab), used for incremental sync
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS project_sources (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            project_id UUID REFERENCES projects(id) ON DELETE CASCADE,
+            source VARCHAR(50) NOT NULL,
+            source_id VARCHAR(100) NOT NULL,
+            source_revision VARCHAR(100),
+            synced_tags TEXT[],
+            last_synced TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE(source, source_id)
+        )
+        "#
+    ).execute(pool).await?;
+    
+    // Registered CSV sync sources (published Google Sheets -> table)
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS sync_sources (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            name VARCHAR(150) NOT NULL,
+            csv_url TEXT NOT NULL,
+            target_table VARCHAR(100) NOT NULL,
+            column_mapping JSONB,
+            schedule VARCHAR(100) NOT NULL,
+            enabled BOOLEAN DEFAULT true,
+            last_run_at TIMESTAMP WITH TIME ZONE,
+            last_status VARCHAR(20),
+            last_summary JSONB,
+            date_entered TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+            date_modified TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+        )
+        "#
+    ).execute(pool).await?;
+    
+    // Rows inserted or updated by each sync source
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS sync_change_log (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            source_id UUID REFERENCES sync_sources(id) ON DELETE CASCADE,
+            table_name VARCHAR(100),
+            record_id VARCHAR(36),
+            action VARCHAR(20),
+            changes JSONB,
+            date_entered TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+        )
+        "#
+    ).execute(pool).await?;
+    
+    // Project feedback, keyed by the recommendation project key (Excel project number or projects.id)
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS project_votes (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            project_key VARCHAR(100) NOT NULL,
+            user_id VARCHAR(255) NOT NULL,
+            rating SMALLINT NOT NULL CHECK (rating BETWEEN 1 AND 5),
+            date_entered TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+            date_modified TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE(project_key, user_id)
+        )
+        "#
+    ).execute(pool).await?;
+    
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS project_stars (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            project_key VARCHAR(100) NOT NULL,
+            user_id VARCHAR(255) NOT NULL,
+            date_entered TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE(project_key, user_id)
+        )
+        "#
+    ).execute(pool).await?;
+    
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS project_comments (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            project_key VARCHAR(100) NOT NULL,
+            user_id VARCHAR(255) NOT NULL,
+            comment TEXT NOT NULL,
+            date_entered TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+            date_modified TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+        )
+        "#
+    ).execute(pool).await?;
+    
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_project_comments_key ON project_comments (project_key)")
+        .execute(pool).await?;
+    
+    // Skill profiles for people-to-project matching; skill_key is the lowercased name
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS member_skills (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            person_id VARCHAR(255) NOT NULL,
+            skill_name VARCHAR(100) NOT NULL,
+            skill_key VARCHAR(100) NOT NULL,
+            category VARCHAR(50),
+            level SMALLINT NOT NULL CHECK (level BETWEEN 1 AND 4),
+            years_experience INTEGER,
+            related TEXT[] NOT NULL DEFAULT '{}',
+            date_modified TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE(person_id, skill_key)
+        )
+        "#
+    ).execute(pool).await?;
+    
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS project_skills (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            project_key VARCHAR(100) NOT NULL,
+            skill_name VARCHAR(100) NOT NULL,
+            skill_key VARCHAR(100) NOT NULL,
+            min_level SMALLINT NOT NULL CHECK (min_level BETWEEN 1 AND 4),
+            required BOOLEAN NOT NULL DEFAULT TRUE,
+            date_modified TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+            UNIQUE(project_key, skill_key)
+        )
+        "#
+    ).execute(pool).await?;
+    
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_member_skills_key ON member_skills (skill_key)")
+        .execute(pool).await?;
+    
+    // AI answers shared across users, keyed by provider, model, prompt and data context
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS ai_response_cache (
+            cache_key TEXT PRIMARY KEY,
+            provider VARCHAR(100) NOT NULL,
+            model VARCHAR(200) NOT NULL,
+            prompt_hash VARCHAR(64) NOT NULL,
+            context_hash VARCHAR(64) NOT NULL,
+            analysis TEXT NOT NULL,
+            token_usage JSONB,
+            hit_count INTEGER NOT NULL DEFAULT 0,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+            expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+            last_hit_at TIMESTAMP WITH TIME ZONE
+        )
+        "#
+    ).execute(pool).await?;
+    
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_ai_response_cache_expires ON ai_response_cache (expires_at)")
+        .execute(pool).await?;
+    
+    // Ledger of every AI provider call, for usage reports and token budgets
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS ai_usage (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            provider VARCHAR(100) NOT NULL,
+            model VARCHAR(200) NOT NULL,
+            user_id VARCHAR(255),
+            endpoint VARCHAR(100) NOT NULL,
+            prompt_tokens INTEGER,
+            completion_tokens INTEGER,
+            total_tokens INTEGER,
+            reserved_tokens INTEGER,
+            estimated BOOLEAN NOT NULL DEFAULT false,
+            latency_ms INTEGER NOT NULL DEFAULT 0,
+            status VARCHAR(20) NOT NULL,
+            error TEXT,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+        )
+        "#
+    ).execute(pool).await?;
+    
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_ai_usage_created ON ai_usage (created_at)")
+        .execute(pool).await?;
+    
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_ai_usage_provider ON ai_usage (provider, created_at)")
+        .execute(pool).await?;
+    
     println!("Database schema initialized successfully!");
     Ok(())
 }
@@ -2285,11 +2793,46 @@ async fn run_api_server(config: Config) -> anyhow::Result<()> {
         log::warn!("Failed to start .env file watcher: {e}");
     }
     
+    let recommendation_mappings: SharedMappings = Arc::new(RwLock::new(recommendations::load_mappings_or_default()));
+    if let Err(e) = start_mappings_watcher(recommendation_mappings.clone()) {
+        log::warn!("Failed to start recommendation mappings watcher: {e}");
+    }
+    
+    let tagger: tagger::SharedTagger = Arc::new(RwLock::new(Arc::new(tagger::Tagger::load()?)));
+    
+    let provider_configs: ai_provider::SharedProviderConfigs = Arc::new(RwLock::new(Arc::new(ai_provider::load_provider_configs())));
+    if let Err(e) = ai_provider::start_watcher(provider_configs.clone()) {
+        log::warn!("Failed to start AI provider list watcher: {e}");
+    }
+    
+    let excel_file_path = shared_config.lock().unwrap().excel_file_path.clone();
+    let project_index = Arc::new(project_index::ProjectIndexCache::load(&excel_file_path, tagger.clone()));
+    
     let state = Arc::new(ApiState {
         db: pool,
         config: shared_config.clone(),
+        recommendation_mappings,
+        project_index,
+        tagger,
+        provider_configs,
+        running_syncs: Default::default(),
     });
     
+    if let Err(e) = project_index::start_watcher(state.clone()) {
+        log::warn!("Failed to start project workbook watcher: {e}");
+    }
+    
+    let retag_state = state.clone();
+    if let Err(e) = project_tags::start_watcher(state.tagger.clone(), move || {
+        let path = retag_state.config.lock().unwrap().excel_file_path.clone();
+        retag_state.project_index.retag(&path);
+    }) {
+        log::warn!("Failed to start tag dictionaries watcher: {e}");
+    }
+    
+    start_democracylab_sync_schedule(state.clone());
+    sync::start_scheduler(state.clone());
+    
     // Create persistent Claude session manager
     let claude_session_manager: ClaudeSessionManager = Arc::new(Mutex::new(ClaudeSession::new()));
     
@@ -2321,6 +2864,21 @@ async fn run_api_server(config: Config) -> anyhow::Result<()> {
                     .route("/tables/mock", web::get().to(get_tables_mock))
                     .route("/projects", web::get().to(get_projects))
                     .route("/projects", web::post().to(create_project))
+                    .route("/projects/tags/generate", web::post().to(project_tags::generate_project_tags))
+                    .route("/projects/{key}/feedback", web::get().to(project_feedback::get_project_feedback))
+                    .route("/projects/{key}/star", web::post().to(project_feedback::star_project))
+                    .route("/projects/{key}/star", web::delete().to(project_feedback::unstar_project))
+                    .route("/projects/{key}/vote", web::put().to(project_feedback::vote_project))
+                    .route("/projects/{key}/vote", web::delete().to(project_feedback::remove_vote))
+                    .route("/projects/{key}/comments", web::get().to(project_feedback::list_comments))
+                    .route("/projects/{key}/comments", web::post().to(project_feedback::add_comment))
+                    .route("/projects/{key}/comments/{comment_id}", web::delete().to(project_feedback::delete_comment))
+                    .route("/projects/{key}/skills", web::get().to(skills_matching::get_project_skills))
+                    .route("/projects/{key}/skills", web::put().to(skills_matching::put_project_skills))
+                    .route("/projects/{key}/matches", web::get().to(skills_matching::get_project_matches))
+                    .route("/people/{id}/skills", web::get().to(skills_matching::get_person_skills))
+                    .route("/people/{id}/skills", web::put().to(skills_matching::put_person_skills))
+                    .route("/people/{id}/matches", web::get().to(skills_matching::get_person_matches))
                     .service(
                         web::scope("/db")
                             .route("/test-connection", web::get().to(db_test_connection))
@@ -2335,18 +2893,30 @@ async fn run_api_server(config: Config) -> anyhow::Result<()> {
                             .route("/excel/sheets", web::post().to(import::get_excel_sheets))
                             .route("/data", web::post().to(import::import_data))
                             .route("/democracylab", web::post().to(import::import_democracylab_projects))
+                            .route("/democracylab/sync", web::post().to(import::sync_democracylab_projects))
+                    )
+                    .service(
+                        web::scope("/ai")
+                            .route("/providers", web::get().to(ai_provider::list_providers))
+                            .route("/analyze", web::post().to(ai_provider::analyze))
+                            .route("/cache", web::get().to(ai_cache::get_cache_stats))
+                            .route("/cache", web::delete().to(ai_cache::invalidate))
+                            .route("/usage", web::get().to(ai_usage::get_usage))
+                            .route("/usage/budget", web::get().to(ai_usage::get_budget))
                     )
                     .service(
                         web::scope("/claude")
                             .route("/usage/cli", web::get().to(get_claude_usage_cli))
                             .route("/usage/website", web::get().to(get_claude_usage_website))
                             .route("/analyze", web::post().to(claude_insights::analyze_with_claude_cli))
+                            .route("/analyze/stream", web::post().to(claude_insights::analyze_with_claude_cli_stream))
                     )
                     .service(
                         web::scope("/gemini")
                             .route("/usage/cli", web::get().to(get_gemini_usage_cli))
                             .route("/usage/website", web::get().to(get_gemini_usage_website))
                             .route("/analyze", web::post().to(gemini_insights::analyze_with_gemini))
+                            .route("/analyze/stream", web::post().to(gemini_insights::analyze_with_gemini_stream))
                     )
                     .service(
                         web::scope("/google")
@@ -2382,9 +2952,23 @@ async fn run_api_server(config: Config) -> anyhow::Result<()> {
                             .route("/csv", web::post().to(fetch_csv))
                             .route("/external", web::post().to(proxy_external_request))
                     )
+                    .service(
+                        web::scope("/sync")
+                            .route("/sources", web::get().to(sync::list_sync_sources))
+                            .route("/sources", web::post().to(sync::create_sync_source))
+                            .route("/sources/{id}", web::get().to(sync::get_sync_source))
+                            .route("/sources/{id}", web::put().to(sync::update_sync_source))
+                            .route("/sources/{id}", web::delete().to(sync::delete_sync_source))
+                            .route("/sources/{id}/run", web::post().to(sync::run_sync_source))
+                            .route("/sources/{id}/log", web::get().to(sync::get_sync_source_log))
+                    )
                     .service(
                         web::scope("/recommendations")
                             .route("", web::post().to(get_recommendations_handler))
+                            .route("/source", web::get().to(get_recommendation_source))
+                            .route("/facets", web::get().to(get_recommendation_facets))
+                            .route("/mappings", web::get().to(get_recommendation_mappings))
+                            .route("/mappings", web::put().to(update_recommendation_mappings))
                     )
             )
     })
@@ -2523,11 +3107,15 @@ async fn get_claude_cli_usage() -> anyhow::Result<serde_json::Value> {
 
 
 // Handlers for Claude usage - get real data from persistent Claude CLI session
-async fn get_claude_usage_cli(session_manager: web::Data<ClaudeSessionManager>) -> Result<HttpResponse> {
+async fn get_claude_usage_cli(session_manager: web::Data<ClaudeSessionManager>, data: web::Data<Arc<ApiState>>) -> Result<HttpResponse> {
+    // Persisted totals from the ai_usage ledger, which survive restarts unlike the session counters
+    let ledger = ai_usage::provider_totals(&data.db, "claude", "month").await.ok();
+
     match get_claude_cli_usage_persistent(session_manager.get_ref().clone()).await {
         Ok(usage_data) => Ok(HttpResponse::Ok().json(json!({
             "success": true,
-            "usage": usage_data
+            "usage": usage_data,
+            "ledger": ledger
         }))),
         Err(e) => {
             // Fall back to one-time request if persistent session fails
@@ -2535,23 +3123,29 @@ async fn get_claude_usage_cli(session_manager: web::Data<ClaudeSessionManager>)
             match get_claude_cli_usage().await {
                 Ok(fallback_data) => Ok(HttpResponse::Ok().json(json!({
                     "success": true,
-                    "usage": fallback_data
+                    "usage": fallback_data,
+                    "ledger": ledger
                 }))),
                 Err(fallback_e) => Ok(HttpResponse::Ok().json(json!({
                     "success": false,
-                    "error": format!("Failed to get Claude CLI usage: {fallback_e}")
+                    "error": format!("Failed to get Claude CLI usage: {fallback_e}"),
+                    "ledger": ledger
                 })))
             }
         }
     }
 }
 
-async fn get_claude_usage_website(session_manager: web::Data<ClaudeSessionManager>) -> Result<HttpResponse> {
+async fn get_claude_usage_website(session_manager: web::Data<ClaudeSessionManager>, data: web::Data<Arc<ApiState>>) -> Result<HttpResponse> {
+    // Persisted totals from the ai_usage ledger, which survive restarts unlike the session counters
+    let ledger = ai_usage::provider_totals(&data.db, "claude", "day").await.ok();
+
     // For website usage, we'll use the same persistent CLI session since that's what's available
     match get_claude_cli_usage_persistent(session_manager.get_ref().clone()).await {
         Ok(usage_data) => Ok(HttpResponse::Ok().json(json!({
             "success": true,
-            "usage": usage_data
+            "usage": usage_data,
+            "ledger": ledger
         }))),
         Err(e) => {
             // Fall back to one-time request if persistent session fails  
@@ -2559,29 +3153,39 @@ async fn get_claude_usage_website(session_manager: web::Data<ClaudeSessionManage
             match get_claude_cli_usage().await {
                 Ok(fallback_data) => Ok(HttpResponse::Ok().json(json!({
                     "success": true,
-                    "usage": fallback_data
+                    "usage": fallback_data,
+                    "ledger": ledger
                 }))),
                 Err(fallback_e) => Ok(HttpResponse::Ok().json(json!({
                     "success": false,
-                    "error": format!("Failed to get Claude usage: {fallback_e}")
+                    "error": format!("Failed to get Claude usage: {fallback_e}"),
+                    "ledger": ledger
                 })))
             }
         }
     }
 }
 
-async fn get_gemini_usage_cli() -> Result<HttpResponse> {
-    Ok(HttpResponse::Ok().json(json!({
-        "success": false,
-        "error": "Gemini CLI not connected or not available"
-    })))
+// Gemini usage comes from the ai_usage ledger: this month for the CLI panel, today for the website panel
+async fn get_gemini_usage_cli(data: web::Data<Arc<ApiState>>) -> Result<HttpResponse> {
+    gemini_usage(&data, "month").await
 }
 
-async fn get_gemini_usage_website() -> Result<HttpResponse> {
-    Ok(HttpResponse::Ok().json(json!({
-        "success": false,
-        "error": "Gemini website API not configured"
-    })))
+async fn get_gemini_usage_website(data: web::Data<Arc<ApiState>>) -> Result<HttpResponse> {
+    gemini_usage(&data, "day").await
+}
+
+async fn gemini_usage(data: &ApiState, period: &str) -> Result<HttpResponse> {
+    match ai_usage::provider_totals(&data.db, "gemini", period).await {
+        Ok(usage) => Ok(HttpResponse::Ok().json(json!({
+            "success": true,
+            "usage": usage
+        }))),
+        Err(e) => Ok(HttpResponse::Ok().json(json!({
+            "success": false,
+            "error": format!("Failed to read Gemini usage: {e}")
+        })))
+    }
 }
 
 #[actix_web::main]
@@ -2605,6 +3209,15 @@ async fn main() -> anyhow::Result<()> {
                         .context("Failed to connect to database for init")?;
                     init_database(&pool).await?;
                 }
+                Commands::SyncDemocracylab => {
+                    let pool = PgPoolOptions::new()
+                        .connect(&config.database_url)
+                        .await
+                        .context("Failed to connect to database for DemocracyLab sync")?;
+                    let tagger = tagger::Tagger::load()?;
+                    let summary = democracylab::sync_projects(&pool, &config.democracylab_api_url(), &tagger).await?;
+                    println!("{}", serde_json::to_string_pretty(&summary)?);
+                }
             }
         }
         Err(_) => {
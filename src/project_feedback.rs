@@ -0,0 +1,400 @@
+// src/project_feedback.rs
+use actix_web::{web, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{Pool, Postgres, Row};
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::recommendations::{Interaction, Project};
+use crate::ApiState;
+
+/// Longest comment accepted, in characters
+const MAX_COMMENT_LENGTH: usize = 5000;
+
+#[derive(Debug, Deserialize)]
+pub struct UserRequest {
+    pub user_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserQuery {
+    pub user_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VoteRequest {
+    pub user_id: String,
+    pub rating: i16,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CommentRequest {
+    pub user_id: String,
+    pub comment: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProjectComment {
+    pub id: Uuid,
+    pub user_id: String,
+    pub comment: String,
+    pub date_entered: DateTime<Utc>,
+}
+
+/// Aggregated feedback for one project, plus the requesting user's own feedback
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct FeedbackSummary {
+    pub average_rating: Option<f64>,
+    pub vote_count: i64,
+    pub star_count: i64,
+    pub comment_count: i64,
+    pub starred: bool,
+    pub user_rating: Option<i16>,
+    pub user_comment: Option<String>,
+}
+
+fn validate_key(project_key: &str) -> Result<(), String> {
+    if project_key.trim().is_empty() || project_key.len() > 100 {
+        return Err("Project key must be 1-100 characters".to_string());
+    }
+    Ok(())
+}
+
+fn validate_user(user_id: &str) -> Result<(), String> {
+    if user_id.trim().is_empty() || user_id.len() > 255 {
+        return Err("user_id must be 1-255 characters".to_string());
+    }
+    Ok(())
+}
+
+fn bad_request(error: String) -> Result<HttpResponse> {
+    Ok(HttpResponse::BadRequest().json(json!({ "success": false, "error": error })))
+}
+
+fn server_error(error: sqlx::Error) -> Result<HttpResponse> {
+    Ok(HttpResponse::InternalServerError().json(json!({ "success": false, "error": error.to_string() })))
+}
+
+/// Feedback for a set of project keys; `user_id` fills in that user's star, vote and latest comment
+pub async fn load_summaries(
+    pool: &Pool<Postgres>,
+    keys: &[String],
+    user_id: Option<&str>,
+) -> Result<HashMap<String, FeedbackSummary>, sqlx::Error> {
+    let mut summaries: HashMap<String, FeedbackSummary> = HashMap::new();
+    if keys.is_empty() {
+        return Ok(summaries);
+    }
+
+    let votes = sqlx::query(
+        r#"
+        SELECT project_key, AVG(rating)::float8 AS average_rating, COUNT(*) AS vote_count,
+               MAX(rating) FILTER (WHERE user_id = $2) AS user_rating
+        FROM project_votes WHERE project_key = ANY($1)
+        GROUP BY project_key
+        "#
+    )
+    .bind(keys)
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    for row in votes {
+        let summary = summaries.entry(row.get("project_key")).or_default();
+        summary.average_rating = row.get("average_rating");
+        summary.vote_count = row.get("vote_count");
+        summary.user_rating = row.get("user_rating");
+    }
+
+    let stars = sqlx::query(
+        r#"
+        SELECT project_key, COUNT(*) AS star_count, BOOL_OR(user_id = $2) AS starred
+        FROM project_stars WHERE project_key = ANY($1)
+        GROUP BY project_key
+        "#
+    )
+    .bind(keys)
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    for row in stars {
+        let summary = summaries.entry(row.get("project_key")).or_default();
+        summary.star_count = row.get("star_count");
+        summary.starred = row.get::<Option<bool>, _>("starred").unwrap_or(false);
+    }
+
+    let comments = sqlx::query(
+        r#"
+        SELECT project_key, COUNT(*) AS comment_count,
+               (ARRAY_AGG(comment ORDER BY date_entered DESC) FILTER (WHERE user_id = $2))[1] AS user_comment
+        FROM project_comments WHERE project_key = ANY($1)
+        GROUP BY project_key
+        "#
+    )
+    .bind(keys)
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    for row in comments {
+        let summary = summaries.entry(row.get("project_key")).or_default();
+        summary.comment_count = row.get("comment_count");
+        summary.user_comment = row.get("user_comment");
+    }
+
+    Ok(summaries)
+}
+
+/// Copy aggregated feedback onto projects, matched by project key
+pub async fn annotate_projects(
+    pool: &Pool<Postgres>,
+    projects: Vec<&mut Project>,
+    user_id: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    let keys: Vec<String> = projects.iter().map(|p| p.key.clone()).filter(|k| !k.is_empty()).collect();
+    let summaries = load_summaries(pool, &keys, user_id).await?;
+
+    for project in projects {
+        if let Some(summary) = summaries.get(&project.key) {
+            project.average_rating = summary.average_rating;
+            project.vote_count = summary.vote_count;
+            project.star_count = summary.star_count;
+            project.comment_count = summary.comment_count;
+            project.starred = summary.starred;
+            project.user_rating = summary.user_rating;
+            project.comment = summary.user_comment.clone().unwrap_or_default();
+        }
+    }
+    Ok(())
+}
+
+/// Every vote and star, merged per user and project, for collaborative filtering
+pub async fn load_interactions(pool: &Pool<Postgres>) -> Result<Vec<Interaction>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT COALESCE(v.user_id, s.user_id) AS user_id,
+               COALESCE(v.project_key, s.project_key) AS project_key,
+               v.rating::float8 AS rating,
+               s.id IS NOT NULL AS starred
+        FROM project_votes v
+        FULL OUTER JOIN project_stars s ON s.user_id = v.user_id AND s.project_key = v.project_key
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .iter()
+        .map(|row| Interaction {
+            user_id: row.get("user_id"),
+            project_key: row.get("project_key"),
+            rating: row.get("rating"),
+            starred: row.get("starred"),
+        })
+        .collect())
+}
+
+pub async fn get_project_feedback(
+    data: web::Data<Arc<ApiState>>,
+    path: web::Path<String>,
+    query: web::Query<UserQuery>,
+) -> Result<HttpResponse> {
+    let project_key = path.into_inner();
+    if let Err(e) = validate_key(&project_key) {
+        return bad_request(e);
+    }
+
+    match load_summaries(&data.db, std::slice::from_ref(&project_key), query.user_id.as_deref()).await {
+        Ok(mut summaries) => Ok(HttpResponse::Ok().json(json!({
+            "success": true,
+            "project_key": project_key,
+            "feedback": summaries.remove(&project_key).unwrap_or_default()
+        }))),
+        Err(e) => server_error(e),
+    }
+}
+
+pub async fn star_project(
+    data: web::Data<Arc<ApiState>>,
+    path: web::Path<String>,
+    req: web::Json<UserRequest>,
+) -> Result<HttpResponse> {
+    let project_key = path.into_inner();
+    if let Err(e) = validate_key(&project_key).and_then(|_| validate_user(&req.user_id)) {
+        return bad_request(e);
+    }
+
+    let result = sqlx::query(
+        r#"
+        INSERT INTO project_stars (project_key, user_id)
+        VALUES ($1, $2)
+        ON CONFLICT (project_key, user_id) DO NOTHING
+        "#
+    )
+    .bind(&project_key)
+    .bind(req.user_id.trim())
+    .execute(&data.db)
+    .await;
+
+    match result {
+        Ok(_) => Ok(HttpResponse::Ok().json(json!({ "success": true, "starred": true }))),
+        Err(e) => server_error(e),
+    }
+}
+
+pub async fn unstar_project(
+    data: web::Data<Arc<ApiState>>,
+    path: web::Path<String>,
+    query: web::Query<UserRequest>,
+) -> Result<HttpResponse> {
+    let project_key = path.into_inner();
+    if let Err(e) = validate_key(&project_key).and_then(|_| validate_user(&query.user_id)) {
+        return bad_request(e);
+    }
+    match sqlx::query("DELETE FROM project_stars WHERE project_key = $1 AND user_id = $2")
+        .bind(&project_key)
+        .bind(query.user_id.trim())
+        .execute(&data.db)
+        .await
+    {
+        Ok(_) => Ok(HttpResponse::Ok().json(json!({ "success": true, "starred": false }))),
+        Err(e) => server_error(e),
+    }
+}
+
+pub async fn vote_project(
+    data: web::Data<Arc<ApiState>>,
+    path: web::Path<String>,
+    req: web::Json<VoteRequest>,
+) -> Result<HttpResponse> {
+    let project_key = path.into_inner();
+    if let Err(e) = validate_key(&project_key).and_then(|_| validate_user(&req.user_id)) {
+        return bad_request(e);
+    }
+    if !(1..=5).contains(&req.rating) {
+        return bad_request("rating must be between 1 and 5".to_string());
+    }
+
+    let result = sqlx::query(
+        r#"
+        INSERT INTO project_votes (project_key, user_id, rating)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (project_key, user_id)
+        DO UPDATE SET rating = EXCLUDED.rating, date_modified = CURRENT_TIMESTAMP
+        "#
+    )
+    .bind(&project_key)
+    .bind(req.user_id.trim())
+    .bind(req.rating)
+    .execute(&data.db)
+    .await;
+
+    match result {
+        Ok(_) => Ok(HttpResponse::Ok().json(json!({ "success": true, "rating": req.rating }))),
+        Err(e) => server_error(e),
+    }
+}
+
+pub async fn remove_vote(
+    data: web::Data<Arc<ApiState>>,
+    path: web::Path<String>,
+    query: web::Query<UserRequest>,
+) -> Result<HttpResponse> {
+    let project_key = path.into_inner();
+    if let Err(e) = validate_key(&project_key).and_then(|_| validate_user(&query.user_id)) {
+        return bad_request(e);
+    }
+    match sqlx::query("DELETE FROM project_votes WHERE project_key = $1 AND user_id = $2")
+        .bind(&project_key)
+        .bind(query.user_id.trim())
+        .execute(&data.db)
+        .await
+    {
+        Ok(_) => Ok(HttpResponse::Ok().json(json!({ "success": true }))),
+        Err(e) => server_error(e),
+    }
+}
+
+pub async fn list_comments(data: web::Data<Arc<ApiState>>, path: web::Path<String>) -> Result<HttpResponse> {
+    let project_key = path.into_inner();
+    if let Err(e) = validate_key(&project_key) {
+        return bad_request(e);
+    }
+    let rows = sqlx::query(
+        "SELECT id, user_id, comment, date_entered FROM project_comments WHERE project_key = $1 ORDER BY date_entered DESC"
+    )
+    .bind(&project_key)
+    .fetch_all(&data.db)
+    .await;
+
+    match rows {
+        Ok(rows) => {
+            let comments: Vec<ProjectComment> = rows
+                .iter()
+                .map(|row| ProjectComment {
+                    id: row.get("id"),
+                    user_id: row.get("user_id"),
+                    comment: row.get("comment"),
+                    date_entered: row.get("date_entered"),
+                })
+                .collect();
+            Ok(HttpResponse::Ok().json(json!({ "success": true, "data": comments })))
+        }
+        Err(e) => server_error(e),
+    }
+}
+
+pub async fn add_comment(
+    data: web::Data<Arc<ApiState>>,
+    path: web::Path<String>,
+    req: web::Json<CommentRequest>,
+) -> Result<HttpResponse> {
+    let project_key = path.into_inner();
+    if let Err(e) = validate_key(&project_key).and_then(|_| validate_user(&req.user_id)) {
+        return bad_request(e);
+    }
+    let comment = req.comment.trim();
+    if comment.is_empty() || comment.chars().count() > MAX_COMMENT_LENGTH {
+        return bad_request(format!("comment must be 1-{MAX_COMMENT_LENGTH} characters"));
+    }
+
+    let result = sqlx::query_scalar::<_, Uuid>(
+        "INSERT INTO project_comments (project_key, user_id, comment) VALUES ($1, $2, $3) RETURNING id"
+    )
+    .bind(&project_key)
+    .bind(req.user_id.trim())
+    .bind(comment)
+    .fetch_one(&data.db)
+    .await;
+
+    match result {
+        Ok(id) => Ok(HttpResponse::Created().json(json!({ "success": true, "id": id }))),
+        Err(e) => server_error(e),
+    }
+}
+
+/// Delete a comment only when `user_id` matches its author. The id is taken from the query
+/// string as sent by the client and is not tied to a verified login, so this prevents mistakes
+/// rather than deliberate deletion of someone else's comment.
+pub async fn delete_comment(
+    data: web::Data<Arc<ApiState>>,
+    path: web::Path<(String, Uuid)>,
+    query: web::Query<UserRequest>,
+) -> Result<HttpResponse> {
+    let (project_key, comment_id) = path.into_inner();
+    if let Err(e) = validate_key(&project_key).and_then(|_| validate_user(&query.user_id)) {
+        return bad_request(e);
+    }
+    match sqlx::query("DELETE FROM project_comments WHERE id = $1 AND project_key = $2 AND user_id = $3")
+        .bind(comment_id)
+        .bind(&project_key)
+        .bind(query.user_id.trim())
+        .execute(&data.db)
+        .await
+    {
+        Ok(r) if r.rows_affected() == 0 => Ok(HttpResponse::NotFound().json(json!({ "success": false, "error": "Comment not found" }))),
+        Ok(_) => Ok(HttpResponse::Ok().json(json!({ "success": true }))),
+        Err(e) => server_error(e),
+    }
+}
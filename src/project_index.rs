@@ -0,0 +1,523 @@
+// src/project_index.rs
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use notify::{Config as NotifyConfig, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use sqlx::{Pool, Postgres, Row};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::mpsc::channel;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::dedup;
+use crate::recommendations::{self, Project, ProjectSet};
+use crate::tagger::{SharedTagger, Tagger};
+use crate::ApiState;
+
+/// Projects parsed from the workbook, with their text index, shared read-only between requests
+#[derive(Debug)]
+pub struct ProjectIndex {
+    pub projects: Arc<ProjectSet>,
+    pub path: String,
+    /// Workbook mtime when it was read; None if the file couldn't be read
+    pub modified: Option<SystemTime>,
+    pub loaded_at: DateTime<Utc>,
+    pub load_time_ms: u128,
+    pub error: Option<String>,
+}
+
+impl ProjectIndex {
+    fn load(path: &str, tagger: &Tagger) -> Self {
+        let started = Instant::now();
+        let modified = file_modified(path);
+        let (projects, error) = match recommendations::load_projects(path) {
+            Ok(mut projects) => {
+                projects.iter_mut().for_each(|p| tagger.tag_project(p));
+                (projects, None)
+            }
+            Err(e) => {
+                log::error!("Failed to load projects from {path}: {e}");
+                (Vec::new(), Some(e.to_string()))
+            }
+        };
+
+        let index = ProjectIndex {
+            projects: Arc::new(ProjectSet::new(projects)),
+            path: path.to_string(),
+            modified,
+            loaded_at: Utc::now(),
+            load_time_ms: started.elapsed().as_millis(),
+            error,
+        };
+        log::info!("Loaded {} projects from {} in {} ms", index.projects.projects.len(), path, index.load_time_ms);
+        index
+    }
+
+    fn is_stale(&self, path: &str) -> bool {
+        self.path != path || self.modified != file_modified(path)
+    }
+
+    pub fn status(&self) -> ProjectIndexStatus {
+        ProjectIndexStatus {
+            path: self.path.clone(),
+            rows: self.projects.projects.len(),
+            loaded_at: self.loaded_at,
+            load_time_ms: self.load_time_ms,
+            file_modified: self.modified.map(DateTime::<Utc>::from),
+            error: self.error.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProjectIndexStatus {
+    pub path: String,
+    pub rows: usize,
+    pub loaded_at: DateTime<Utc>,
+    pub load_time_ms: u128,
+    pub file_modified: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+}
+
+fn file_modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// How long the projects table is served from memory when no write in this process
+/// invalidated it; covers writes from other processes such as the sync-democracylab command
+const DATABASE_REFRESH: Duration = Duration::from_secs(300);
+
+/// Last combined set and the source sets it was built from
+type CombinedSet = (Vec<Arc<ProjectSet>>, Arc<ProjectSet>);
+
+/// Holds the current index; a reload builds a new index and swaps the Arc, so readers
+/// always see either the old or the new index in full
+#[derive(Debug)]
+pub struct ProjectIndexCache {
+    current: RwLock<Arc<ProjectIndex>>,
+    reloading: Mutex<()>,
+    tagger: SharedTagger,
+    /// Projects table as last read, with when it was read
+    database: RwLock<Option<(Arc<ProjectSet>, Instant)>>,
+    /// Bumped by every invalidation so a read that started earlier isn't cached
+    database_generation: AtomicU64,
+    combined: Mutex<Option<CombinedSet>>,
+}
+
+impl ProjectIndexCache {
+    pub fn load(path: &str, tagger: SharedTagger) -> Self {
+        let index = ProjectIndex::load(path, &tagger.read().unwrap());
+        ProjectIndexCache {
+            current: RwLock::new(Arc::new(index)),
+            reloading: Mutex::new(()),
+            tagger,
+            database: RwLock::new(None),
+            database_generation: AtomicU64::new(0),
+            combined: Mutex::new(None),
+        }
+    }
+
+    /// Drop the cached projects table; call after writing projects or their tags
+    pub fn invalidate_database(&self) {
+        self.database_generation.fetch_add(1, Ordering::SeqCst);
+        *self.database.write().unwrap() = None;
+    }
+
+    fn cached_database(&self) -> Option<Arc<ProjectSet>> {
+        self.database
+            .read()
+            .unwrap()
+            .as_ref()
+            .filter(|(_, loaded_at)| loaded_at.elapsed() < DATABASE_REFRESH)
+            .map(|(projects, _)| projects.clone())
+    }
+
+    fn store_database(&self, projects: Arc<ProjectSet>, generation: u64) {
+        let mut database = self.database.write().unwrap();
+        if self.database_generation.load(Ordering::SeqCst) == generation {
+            *database = Some((projects, Instant::now()));
+        }
+    }
+
+    /// The combined set for these source sets, reusing the last one when none of them changed
+    fn combined(&self, sets: &[Arc<ProjectSet>]) -> Arc<ProjectSet> {
+        let mut combined = self.combined.lock().unwrap();
+        if let Some((inputs, projects)) = combined.as_ref() {
+            if inputs.len() == sets.len() && inputs.iter().zip(sets).all(|(a, b)| Arc::ptr_eq(a, b)) {
+                return projects.clone();
+            }
+        }
+        let projects = Arc::new(ProjectSet::new(combine(sets)));
+        *combined = Some((sets.to_vec(), projects.clone()));
+        projects
+    }
+
+    pub fn snapshot(&self) -> Arc<ProjectIndex> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Return the index for `path`, re-reading the workbook first if it moved or changed.
+    /// Blocks while parsing, so call it from a blocking context.
+    pub fn refresh_if_stale(&self, path: &str) -> Arc<ProjectIndex> {
+        let snapshot = self.snapshot();
+        if !snapshot.is_stale(path) {
+            return snapshot;
+        }
+
+        // Only one reload at a time; whoever waited re-checks before parsing again
+        let _guard = self.reloading.lock().unwrap();
+        let snapshot = self.snapshot();
+        if !snapshot.is_stale(path) {
+            return snapshot;
+        }
+
+        let index = Arc::new(ProjectIndex::load(path, &self.tagger()));
+        *self.current.write().unwrap() = index.clone();
+        index
+    }
+
+    /// Re-tag everything after the tag dictionaries changed: re-read the workbook and
+    /// drop the cached projects table. Blocks while parsing.
+    pub fn retag(&self, path: &str) {
+        let _guard = self.reloading.lock().unwrap();
+        let index = Arc::new(ProjectIndex::load(path, &self.tagger()));
+        *self.current.write().unwrap() = index;
+        self.invalidate_database();
+    }
+
+    fn tagger(&self) -> Arc<Tagger> {
+        self.tagger.read().unwrap().clone()
+    }
+}
+
+/// Reload the index as soon as the workbook changes on disk rather than on the next request
+pub fn start_watcher(state: Arc<ApiState>) -> anyhow::Result<()> {
+    let path = state.config.lock().unwrap().excel_file_path.clone();
+    let file = Path::new(&path);
+    let Some(dir) = file.parent().map(|d| if d.as_os_str().is_empty() { Path::new(".") } else { d }).filter(|d| d.exists()) else {
+        log::warn!("Project workbook directory for {path} not found; it will be reloaded on request only");
+        return Ok(());
+    };
+    let file_name = file.file_name().map(|name| name.to_os_string());
+
+    let (tx, rx) = channel();
+    let mut watcher = RecommendedWatcher::new(tx, NotifyConfig::default())?;
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    log::info!("Started watching {path} for changes");
+
+    // notify delivers events over a blocking channel, so receive them on their own thread
+    std::thread::spawn(move || {
+        let _watcher = watcher;
+        for event in rx {
+            match event {
+                Ok(Event { kind: EventKind::Modify(_) | EventKind::Create(_), paths, .. })
+                    if paths.iter().any(|p| p.file_name() == file_name.as_deref()) => {
+                    // Give the writer a moment to finish before parsing
+                    std::thread::sleep(std::time::Duration::from_millis(500));
+                    let path = state.config.lock().unwrap().excel_file_path.clone();
+                    state.project_index.refresh_if_stale(&path);
+                }
+                Ok(_) => {} // Ignore other events
+                Err(e) => log::error!("File watcher error: {e}"),
+            }
+        }
+    });
+
+    Ok(())
+}
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Somewhere recommendations can draw projects from
+pub trait ProjectSource: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn load(&self) -> BoxFuture<'_, anyhow::Result<Arc<ProjectSet>>>;
+}
+
+/// The project workbook at EXCEL_FILE_PATH, served from the cached index
+pub struct ExcelSource {
+    cache: Arc<ProjectIndexCache>,
+    path: String,
+}
+
+impl ProjectSource for ExcelSource {
+    fn name(&self) -> &'static str {
+        "excel"
+    }
+
+    fn load(&self) -> BoxFuture<'_, anyhow::Result<Arc<ProjectSet>>> {
+        Box::pin(async move {
+            let cache = self.cache.clone();
+            let path = self.path.clone();
+            // Only re-reads the workbook when it changed since it was last loaded
+            let index = tokio::task::spawn_blocking(move || cache.refresh_if_stale(&path)).await?;
+            match &index.error {
+                Some(error) => anyhow::bail!("{error}"),
+                None => Ok(index.projects.clone()),
+            }
+        })
+    }
+}
+
+/// The projects table, including projects created through the API or imported
+/// from Excel, JSON and DemocracyLab. The built set is cached until a write invalidates it.
+pub struct PostgresSource {
+    pool: Pool<Postgres>,
+    cache: Arc<ProjectIndexCache>,
+}
+
+impl ProjectSource for PostgresSource {
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    fn load(&self) -> BoxFuture<'_, anyhow::Result<Arc<ProjectSet>>> {
+        Box::pin(async move {
+            if let Some(projects) = self.cache.cached_database() {
+                return Ok(projects);
+            }
+            let generation = self.cache.database_generation.load(Ordering::SeqCst);
+
+            let rows = sqlx::query(
+                r#"
+                SELECT p.id::text AS id, p.name, p.description, p.status, p.project_number,
+                       COALESCE(array_agg(t.name ORDER BY t.name) FILTER (WHERE t.name IS NOT NULL), '{}') AS tags
+                FROM projects p
+                LEFT JOIN taggables tg ON tg.taggable_type = 'projects' AND tg.taggable_id = p.id
+                LEFT JOIN tags t ON t.id = tg.tag_id
+                WHERE p.name IS NOT NULL AND p.name <> ''
+                GROUP BY p.id
+                ORDER BY p.date_entered
+                "#
+            )
+            .fetch_all(&self.pool)
+            .await
+            .context("Failed to read projects table")?;
+
+            let tagger = self.cache.tagger();
+            let projects = rows
+                .iter()
+                .enumerate()
+                .map(|(i, row)| {
+                    project_from_row(
+                        i,
+                        row.get("id"),
+                        row.get("name"),
+                        row.get::<Option<String>, _>("description").unwrap_or_default(),
+                        row.get::<Option<String>, _>("status").unwrap_or_default(),
+                        row.get::<Option<String>, _>("project_number").unwrap_or_default(),
+                        row.get("tags"),
+                    )
+                })
+                .map(|mut project| {
+                    tagger.tag_project(&mut project);
+                    project
+                })
+                .collect();
+            let projects = Arc::new(ProjectSet::new(projects));
+            self.cache.store_database(projects.clone(), generation);
+            Ok(projects)
+        })
+    }
+}
+
+/// Rebuild a Project from a projects row. The importers fold workbook columns into the
+/// description as "Label: value" paragraphs, so those are read back into their fields.
+pub(crate) fn project_from_row(index: usize, id: String, name: String, description: String, status: String, project_number: String, tags: Vec<String>) -> Project {
+    let mut fields: HashMap<&str, String> = HashMap::new();
+    let mut text = Vec::new();
+    for paragraph in description.split("\n\n").map(str::trim).filter(|p| !p.is_empty()) {
+        let labelled = paragraph.split_once(": ").and_then(|(label, value)| {
+            let field = match label {
+                "Department" => "department",
+                "Region" => "region",
+                "Country" => "country",
+                "Framework" => "framework",
+                "NAICS Sector" => "naics_sector",
+                "Profile URL" | "Project URL" => "project_profile_url",
+                _ => return None,
+            };
+            Some((field, value.trim().to_string()))
+        });
+        match labelled {
+            Some((field, value)) => {
+                fields.insert(field, value);
+            }
+            None => text.push(paragraph),
+        }
+    }
+    let mut field = |name: &str| fields.remove(name).unwrap_or_default();
+
+    Project {
+        id: (index + 1) as f64,
+        key: id,
+        source: "postgres".to_string(),
+        project_name: name,
+        project_description: text.join("\n\n"),
+        country: field("country"),
+        naics_sector: field("naics_sector"),
+        committed: 0.0,
+        department: field("department"),
+        project_type: status,
+        region: field("region"),
+        fiscal_year: String::new(),
+        project_number,
+        framework: field("framework"),
+        project_profile_url: field("project_profile_url"),
+        tags,
+        starred: false,
+        comment: String::new(),
+        average_rating: None,
+        vote_count: 0,
+        star_count: 0,
+        comment_count: 0,
+        user_rating: None,
+    }
+}
+
+/// The sources named in RECOMMENDATION_SOURCES, in priority order
+pub fn configured_sources(state: &ApiState) -> Vec<Box<dyn ProjectSource>> {
+    let config = state.config.lock().unwrap();
+    let mut sources: Vec<Box<dyn ProjectSource>> = Vec::new();
+    for name in config.recommendation_sources() {
+        match name.as_str() {
+            "excel" => sources.push(Box::new(ExcelSource {
+                cache: state.project_index.clone(),
+                path: config.excel_file_path.clone(),
+            })),
+            "postgres" => sources.push(Box::new(PostgresSource {
+                pool: state.db.clone(),
+                cache: state.project_index.clone(),
+            })),
+            other => log::warn!("Ignoring unknown recommendation source '{other}'"),
+        }
+    }
+    sources
+}
+
+/// Load every source and combine them. A source that fails is skipped as long as another
+/// succeeds; when several sources are combined, the earlier source wins on duplicates.
+pub async fn load_projects(cache: &ProjectIndexCache, sources: &[Box<dyn ProjectSource>]) -> anyhow::Result<Arc<ProjectSet>> {
+    let mut loaded = Vec::new();
+    let mut errors = Vec::new();
+    for source in sources {
+        match source.load().await {
+            Ok(projects) => loaded.push(projects),
+            Err(e) => {
+                log::error!("Recommendation source {} failed: {e}", source.name());
+                errors.push(format!("{}: {e}", source.name()));
+            }
+        }
+    }
+
+    match loaded.len() {
+        0 if errors.is_empty() => anyhow::bail!("No recommendation sources are configured"),
+        0 => anyhow::bail!("{}", errors.join("; ")),
+        1 => Ok(loaded.remove(0)),
+        _ => Ok(cache.combined(&loaded)),
+    }
+}
+
+/// Merge project lists, treating projects with the same project number as one project. Without
+/// a shared number, projects with the same normalized name are merged only when at least one of
+/// them has no project number, so distinct numbered projects that share a name stay apart.
+/// Tags from the duplicates are kept.
+fn combine(sets: &[Arc<ProjectSet>]) -> Vec<Project> {
+    let mut combined: Vec<Project> = Vec::new();
+    let mut by_number: HashMap<String, usize> = HashMap::new();
+    let mut by_name: HashMap<String, Vec<usize>> = HashMap::new();
+
+    for project in sets.iter().flat_map(|set| set.projects.iter()) {
+        let number = project.project_number.trim().to_lowercase();
+        let name = dedup::normalize_value(&project.project_name);
+
+        let existing = (!number.is_empty())
+            .then(|| by_number.get(&number).copied())
+            .flatten()
+            .or_else(|| {
+                by_name.get(&name)?.iter().copied().find(|&i| {
+                    number.is_empty() || combined[i].project_number.trim().is_empty()
+                })
+            });
+
+        match existing {
+            Some(i) => {
+                let kept = &mut combined[i];
+                for tag in &project.tags {
+                    if !kept.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+                        kept.tags.push(tag.clone());
+                    }
+                }
+                // A project matched by name takes the number the other source knew it by
+                if kept.project_number.trim().is_empty() && !number.is_empty() {
+                    kept.project_number = project.project_number.clone();
+                    by_number.insert(number, i);
+                }
+            }
+            None => {
+                let i = combined.len();
+                if !number.is_empty() {
+                    by_number.insert(number, i);
+                }
+                by_name.entry(name).or_default().push(i);
+                combined.push(project.clone());
+            }
+        }
+    }
+
+    // Keep ids unique across sources for clients that key on them; the first source keeps its ids
+    let mut next_id = combined.iter().map(|p| p.id).fold(0.0, f64::max);
+    let mut used = std::collections::HashSet::new();
+    for project in combined.iter_mut() {
+        if !used.insert(project.id.to_bits()) {
+            next_id += 1.0;
+            project.id = next_id;
+            used.insert(next_id.to_bits());
+        }
+    }
+    combined
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(projects: &[(&str, &str, &str)]) -> Arc<ProjectSet> {
+        let projects = projects
+            .iter()
+            .enumerate()
+            .map(|(i, (key, name, number))| project_from_row(i, key.to_string(), name.to_string(), String::new(), "Active".into(), number.to_string(), vec![format!("tag-{key}")]))
+            .collect();
+        Arc::new(ProjectSet::new(projects))
+    }
+
+    #[test]
+    fn combine_merges_on_number_and_on_name_only_without_conflicting_numbers() {
+        let excel = set(&[("e1", "Solar Farm", "P-1"), ("e2", "Water Works", "P-2"), ("e3", "Road Repair", "")]);
+        let postgres = set(&[
+            // Same number under another name
+            ("d1", "Solar Farm Phase I", "p-1"),
+            // Same name, different number: a separate project
+            ("d2", "Water Works", "P-9"),
+            // Same name, one side without a number
+            ("d3", "Road  Repair", "P-3"),
+        ]);
+
+        let combined = combine(&[excel, postgres]);
+        let keys: Vec<&str> = combined.iter().map(|p| p.key.as_str()).collect();
+        assert_eq!(keys, vec!["e1", "e2", "e3", "d2"]);
+
+        assert_eq!(combined[0].tags, vec!["tag-e1", "tag-d1"]);
+        assert_eq!(combined[1].tags, vec!["tag-e2"]);
+        assert_eq!(combined[2].tags, vec!["tag-e3", "tag-d3"]);
+        assert_eq!(combined[2].project_number, "P-3");
+
+        // Both sources number their rows from 1, so the second source's ids are reassigned
+        let ids: std::collections::HashSet<u64> = combined.iter().map(|p| p.id.to_bits()).collect();
+        assert_eq!(ids.len(), combined.len());
+    }
+}
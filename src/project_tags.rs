@@ -0,0 +1,117 @@
+// src/project_tags.rs
+// Storing generated tags on project rows, and reloading the tag dictionaries
+use actix_web::{web, HttpResponse, Result};
+use notify::{Config as NotifyConfig, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde_json::json;
+use sqlx::{Pool, Postgres, Row};
+use std::path::Path;
+use std::sync::mpsc::channel;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::project_index::project_from_row;
+use crate::tagger::{SharedTagger, Tagger, TAG_DICTIONARIES_PATH};
+use crate::ApiState;
+
+/// Generate tags for a stored projects row and attach them through tags/taggables.
+/// The description is read the same way the Postgres recommendation source reads it.
+pub async fn tag_stored_project(
+    pool: &Pool<Postgres>,
+    tagger: &Tagger,
+    project_id: Uuid,
+    name: &str,
+    description: Option<&str>,
+) -> Result<Vec<String>, sqlx::Error> {
+    let mut project = project_from_row(
+        0,
+        project_id.to_string(),
+        name.to_string(),
+        description.unwrap_or_default().to_string(),
+        String::new(),
+        String::new(),
+        Vec::new(),
+    );
+    tagger.tag_project(&mut project);
+    crate::import::tag_record(pool, "projects", project_id, &project.tags).await?;
+    Ok(project.tags)
+}
+
+/// Regenerate tags for every project already in the database
+pub async fn generate_project_tags(state: web::Data<Arc<ApiState>>) -> Result<HttpResponse> {
+    let rows = match sqlx::query("SELECT id, name, description FROM projects WHERE name IS NOT NULL AND name <> ''")
+        .fetch_all(&state.db)
+        .await
+    {
+        Ok(rows) => rows,
+        Err(e) => {
+            return Ok(HttpResponse::InternalServerError().json(json!({
+                "success": false,
+                "error": format!("Failed to read projects: {e}")
+            })));
+        }
+    };
+
+    let tagger = state.tagger.read().unwrap().clone();
+    let mut tagged = 0;
+    let mut errors = Vec::new();
+    for row in &rows {
+        let id: Uuid = row.get("id");
+        let name: String = row.get("name");
+        let description: Option<String> = row.get("description");
+        match tag_stored_project(&state.db, &tagger, id, &name, description.as_deref()).await {
+            Ok(tags) if !tags.is_empty() => tagged += 1,
+            Ok(_) => {}
+            Err(e) => errors.push(format!("{id}: {e}")),
+        }
+    }
+
+    state.project_index.invalidate_database();
+    Ok(HttpResponse::Ok().json(json!({
+        "success": errors.is_empty(),
+        "projects_processed": rows.len(),
+        "projects_tagged": tagged,
+        "errors": errors
+    })))
+}
+
+/// Reload the tag dictionaries when the file changes, then run `on_reload` so anything
+/// tagged with the old dictionaries can be rebuilt
+pub fn start_watcher(tagger: SharedTagger, on_reload: impl Fn() + Send + 'static) -> anyhow::Result<()> {
+    let path = Path::new(TAG_DICTIONARIES_PATH);
+    let Some(dir) = path.parent().filter(|dir| dir.exists()) else {
+        log::warn!("No config directory found; tag dictionaries will not hot reload");
+        return Ok(());
+    };
+    let file_name = path.file_name().map(|name| name.to_os_string());
+
+    let (tx, rx) = channel();
+    let mut watcher = RecommendedWatcher::new(tx, NotifyConfig::default())?;
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    log::info!("Started watching {TAG_DICTIONARIES_PATH} for changes");
+
+    // notify delivers events over a blocking channel, so receive them on their own thread
+    std::thread::spawn(move || {
+        let _watcher = watcher;
+        for event in rx {
+            match event {
+                Ok(Event { kind: EventKind::Modify(_) | EventKind::Create(_), paths, .. })
+                    if paths.iter().any(|p| p.file_name() == file_name.as_deref()) => {
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+
+                    match Tagger::load() {
+                        Ok(reloaded) => {
+                            *tagger.write().unwrap() = Arc::new(reloaded);
+                            log::info!("Tag dictionaries reloaded");
+                            on_reload();
+                        }
+                        Err(e) => log::error!("Failed to reload tag dictionaries, keeping the previous ones: {e:#}"),
+                    }
+                }
+                Ok(_) => {} // Ignore other events
+                Err(e) => log::error!("File watcher error: {e}"),
+            }
+        }
+    });
+
+    Ok(())
+}
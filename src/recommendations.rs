@@ -1,10 +1,16 @@
 use serde::{Deserialize, Serialize};
 use calamine::{open_workbook, Reader, Xlsx};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Project {
     pub id: f64,
+    /// Stable identifier within the source: the project number (or row) for Excel, the UUID for Postgres
+    #[serde(default)]
+    pub key: String,
+    /// Where the project was loaded from, e.g. "excel" or "postgres"
+    #[serde(default)]
+    pub source: String,
     pub project_name: String,
     pub project_description: String,
     pub country: String,
@@ -20,32 +26,424 @@ pub struct Project {
     pub tags: Vec<String>,
     pub starred: bool,
     pub comment: String,
+    /// Team feedback from project_votes, project_stars and project_comments
+    #[serde(default)]
+    pub average_rating: Option<f64>,
+    #[serde(default)]
+    pub vote_count: i64,
+    #[serde(default)]
+    pub star_count: i64,
+    #[serde(default)]
+    pub comment_count: i64,
+    /// The requesting user's own vote
+    #[serde(default)]
+    pub user_rating: Option<i16>,
 }
 
-#[derive(Deserialize, Debug)]
+/// Interests either as a plain list (each weighted 1.0) or as `{interest: weight}`,
+/// e.g. the 1-5 star ratings the survey stores in PartnerTools_preferences
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Preferences {
+    List(Vec<String>),
+    Weighted(HashMap<String, f64>),
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Preferences::List(Vec::new())
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
 pub struct RecommendationRequest {
-    pub preferences: Vec<String>,
-}
-
-fn get_preference_to_filter_mappings() -> HashMap<String, serde_json::Value> {
-    let mut mappings = HashMap::new();
-    mappings.insert("Agriculture".to_string(), serde_json::json!({ "naicsSectors": ["Agriculture"], "departments": ["Technical Assistance"] }));
-    mappings.insert("Education".to_string(), serde_json::json!({ "naicsSectors": ["Educational Services"], "departments": ["Technical Assistance"] }));
-    mappings.insert("Healthcare Access".to_string(), serde_json::json!({ "naicsSectors": ["Health Care"], "departments": ["Equity Investments"] }));
-    mappings.insert("Financial Inclusion".to_string(), serde_json::json!({ "naicsSectors": ["Finance and Insurance"], "departments": ["Investment Funds", "Finance"] }));
-    mappings.insert("Infrastructure Development".to_string(), serde_json::json!({ "naicsSectors": ["Utilities"], "departments": ["Finance"] }));
-    mappings.insert("Technology Innovation".to_string(), serde_json::json!({ "naicsSectors": ["Information"], "departments": ["Investment Funds"] }));
-    mappings.insert("Small Business Support".to_string(), serde_json::json!({ "naicsSectors": ["Finance and Insurance"], "departments": ["Investment Funds"] }));
-    mappings.insert("Rural Development".to_string(), serde_json::json!({ "departments": ["Technical Assistance"] }));
-    mappings.insert("Environmental Sustainability".to_string(), serde_json::json!({ "naicsSectors": ["Utilities"], "departments": ["Finance"] }));
-    mappings.insert("Renewable Energy".to_string(), serde_json::json!({ "naicsSectors": ["Utilities"], "departments": ["Finance"] }));
-    mappings.insert("Water & Sanitation".to_string(), serde_json::json!({ "naicsSectors": ["Utilities"], "departments": ["Finance"] }));
-    mappings.insert("Digital Inclusion".to_string(), serde_json::json!({ "naicsSectors": ["Information", "Educational Services"], "departments": ["Technical Assistance"] }));
-    mappings.insert("Economic Growth".to_string(), serde_json::json!({ "naicsSectors": ["Finance and Insurance"], "departments": ["Investment Funds"] }));
-    mappings.insert("Food Security".to_string(), serde_json::json!({ "naicsSectors": ["Agriculture"], "departments": ["Technical Assistance"] }));
+    #[serde(default)]
+    pub preferences: Preferences,
+    /// Skill names matched against project text
+    #[serde(default)]
+    pub skills: Vec<String>,
+    /// Survey category scores (1-5) such as `{"environment": 4.5}`
+    #[serde(default)]
+    pub policy: HashMap<String, f64>,
+    /// Free-text interests or bio, matched against project text by TF-IDF similarity
+    #[serde(default)]
+    pub text: Option<String>,
+    /// Score given to a perfect text match; defaults to TEXT_WEIGHT
+    #[serde(default)]
+    pub text_weight: Option<f64>,
+    /// Hard filters applied before scoring
+    #[serde(flatten)]
+    pub filters: ProjectFilters,
+    /// When set, each result's starred, comment and user_rating reflect this user's feedback
+    #[serde(default)]
+    pub user_id: Option<String>,
+    /// Share of the score (0.0-1.0) taken from collaborative filtering over team votes and
+    /// stars; 0 (the default) ranks by content only and 1 by collaborative filtering only
+    #[serde(default)]
+    pub collaborative_weight: Option<f64>,
+    /// Trade-off (0.0-1.0) between relevance and spreading results across sector, region
+    /// and department; 0 (the default) ranks by relevance only
+    #[serde(default)]
+    pub diversity: Option<f64>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub offset: Option<usize>,
+}
+
+/// Hard filters on project attributes. List filters match any listed value
+/// (case-insensitive); an empty list or missing bound does not filter.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct ProjectFilters {
+    #[serde(default)]
+    pub countries: Vec<String>,
+    #[serde(default)]
+    pub regions: Vec<String>,
+    #[serde(default)]
+    pub project_types: Vec<String>,
+    #[serde(default)]
+    pub frameworks: Vec<String>,
+    /// Inclusive fiscal year range, e.g. 2020..=2024
+    #[serde(default)]
+    pub fiscal_year_min: Option<i32>,
+    #[serde(default)]
+    pub fiscal_year_max: Option<i32>,
+    /// Inclusive committed amount range in dollars
+    #[serde(default)]
+    pub committed_min: Option<f64>,
+    #[serde(default)]
+    pub committed_max: Option<f64>,
+    /// Only projects carrying every one of these tags (case-insensitive)
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// A filter dimension that has facet counts
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Facet {
+    Country,
+    Region,
+    ProjectType,
+    Framework,
+    FiscalYear,
+    Committed,
+}
+
+fn matches_any(values: &[String], actual: &str) -> bool {
+    values.is_empty() || values.iter().any(|v| v.trim().eq_ignore_ascii_case(actual.trim()))
+}
+
+/// The first four-digit year in a fiscal year cell such as "2023" or "FY 2023"
+pub fn fiscal_year(value: &str) -> Option<i32> {
+    value
+        .split(|c: char| !c.is_ascii_digit())
+        .find(|digits| digits.len() == 4)
+        .and_then(|digits| digits.parse().ok())
+}
+
+impl ProjectFilters {
+    pub fn matches(&self, project: &Project) -> bool {
+        self.matches_except(project, None)
+    }
+
+    /// Whether the project passes every filter other than `skip`, so a facet's counts
+    /// show what selecting another value of that facet would return
+    fn matches_except(&self, project: &Project, skip: Option<Facet>) -> bool {
+        let check = |facet: Facet, passes: &dyn Fn() -> bool| skip == Some(facet) || passes();
+
+        check(Facet::Country, &|| matches_any(&self.countries, &project.country))
+            && check(Facet::Region, &|| matches_any(&self.regions, &project.region))
+            && check(Facet::ProjectType, &|| matches_any(&self.project_types, &project.project_type))
+            && check(Facet::Framework, &|| matches_any(&self.frameworks, &project.framework))
+            && check(Facet::FiscalYear, &|| {
+                if self.fiscal_year_min.is_none() && self.fiscal_year_max.is_none() {
+                    return true;
+                }
+                fiscal_year(&project.fiscal_year).is_some_and(|year| {
+                    self.fiscal_year_min.is_none_or(|min| year >= min) && self.fiscal_year_max.is_none_or(|max| year <= max)
+                })
+            })
+            && check(Facet::Committed, &|| {
+                self.committed_min.is_none_or(|min| project.committed >= min)
+                    && self.committed_max.is_none_or(|max| project.committed <= max)
+            })
+            && self.tags.iter().all(|tag| project.tags.iter().any(|t| t.trim().eq_ignore_ascii_case(tag.trim())))
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct FacetValue {
+    pub value: String,
+    pub count: usize,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct NumericRange {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+/// Available filter values with project counts, for building filter chips.
+/// Each facet is counted with the other facets' filters applied but not its own.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct ProjectFacets {
+    pub countries: Vec<FacetValue>,
+    pub regions: Vec<FacetValue>,
+    pub project_types: Vec<FacetValue>,
+    pub frameworks: Vec<FacetValue>,
+    pub fiscal_years: Vec<FacetValue>,
+    pub committed: NumericRange,
+}
+
+pub fn facets(projects: &[Project], filters: &ProjectFilters) -> ProjectFacets {
+    let count = |facet: Facet, value: &dyn Fn(&Project) -> String| -> Vec<FacetValue> {
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for project in projects.iter().filter(|p| filters.matches_except(p, Some(facet))) {
+            let value = value(project).trim().to_string();
+            if !value.is_empty() {
+                *counts.entry(value).or_default() += 1;
+            }
+        }
+        let mut values: Vec<FacetValue> = counts.into_iter().map(|(value, count)| FacetValue { value, count }).collect();
+        values.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+        values
+    };
+
+    let mut fiscal_years = count(Facet::FiscalYear, &|p| fiscal_year(&p.fiscal_year).map(|y| y.to_string()).unwrap_or_default());
+    fiscal_years.sort_by(|a, b| b.value.cmp(&a.value));
+
+    let committed = projects
+        .iter()
+        .filter(|p| filters.matches_except(p, Some(Facet::Committed)) && p.committed > 0.0)
+        .fold(NumericRange::default(), |range, p| NumericRange {
+            min: Some(range.min.map_or(p.committed, |m| m.min(p.committed))),
+            max: Some(range.max.map_or(p.committed, |m| m.max(p.committed))),
+        });
+
+    ProjectFacets {
+        countries: count(Facet::Country, &|p| p.country.clone()),
+        regions: count(Facet::Region, &|p| p.region.clone()),
+        project_types: count(Facet::ProjectType, &|p| p.project_type.clone()),
+        frameworks: count(Facet::Framework, &|p| p.framework.clone()),
+        fiscal_years,
+        committed,
+    }
+}
+
+impl RecommendationRequest {
+    /// Weight of each interest, combining explicit preferences with survey policy scores.
+    /// Interests with a zero or negative weight are dropped.
+    pub fn preference_weights(&self) -> Vec<(String, f64)> {
+        let mut weights: HashMap<String, f64> = match &self.preferences {
+            Preferences::List(names) => names.iter().map(|n| (n.clone(), 1.0)).collect(),
+            Preferences::Weighted(map) => map.clone(),
+        };
+
+        for (category, score) in &self.policy {
+            let boost = score.clamp(0.0, 5.0) / 5.0 * POLICY_WEIGHT;
+            for interest in policy_interests(category) {
+                *weights.entry(interest.to_string()).or_insert(0.0) += boost;
+            }
+        }
+
+        let mut weights: Vec<(String, f64)> = weights.into_iter().filter(|(_, w)| *w > 0.0).collect();
+        weights.sort_by(|a, b| a.0.cmp(&b.0));
+        weights
+    }
+}
+
+/// Number of results returned when the request doesn't set a limit
+pub const DEFAULT_LIMIT: usize = 5;
+/// Upper bound on the page size a client can request
+pub const MAX_LIMIT: usize = 100;
+
+/// Score added when a project's NAICS sector matches a preference
+const SECTOR_WEIGHT: f64 = 2.0;
+/// Score added when a project's department matches a preference
+const DEPARTMENT_WEIGHT: f64 = 1.0;
+/// Score added when a project's country matches a preference
+const COUNTRY_WEIGHT: f64 = 1.0;
+/// Score added when a project's region matches a preference
+const REGION_WEIGHT: f64 = 0.5;
+/// Score added when a project's framework matches a preference
+const FRAMEWORK_WEIGHT: f64 = 0.5;
+/// Score added for each preference keyword mentioned in the project text
+const KEYWORD_WEIGHT: f64 = 0.5;
+/// Score added for each requested skill mentioned in the project text
+const SKILL_WEIGHT: f64 = 0.5;
+/// Score contributed by a text similarity of 1.0, unless the request sets text_weight
+pub const TEXT_WEIGHT: f64 = 5.0;
+/// Cosine similarity below which a text match is treated as noise
+const MIN_TEXT_SIMILARITY: f64 = 0.05;
+/// Interest weight contributed by a 5/5 survey policy score
+const POLICY_WEIGHT: f64 = 1.0;
+
+/// Interests boosted by each survey category (see analyzeSurveyResponses in js/survey.js)
+fn policy_interests(category: &str) -> &'static [&'static str] {
+    match category.to_lowercase().as_str() {
+        "technology" => &["Technology Innovation", "Digital Inclusion"],
+        "environment" => &["Environmental Sustainability", "Renewable Energy", "Water & Sanitation"],
+        "economy" => &["Economic Growth", "Small Business Support", "Financial Inclusion"],
+        "social" => &["Healthcare Access", "Education", "Food Security"],
+        "governance" => &["Infrastructure Development"],
+        _ => &[],
+    }
+}
+
+/// Why a project was recommended, and how much that match added to its score
+#[derive(Serialize, Debug, Clone)]
+pub struct MatchReason {
+    /// What matched: "sector", "department", "country", "region", "framework", "keyword", "skill",
+    /// "text" or "collaborative"
+    pub kind: String,
+    /// The preference or skill that produced the match
+    pub source: String,
+    pub message: String,
+    pub contribution: f64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ScoredProject {
+    #[serde(flatten)]
+    pub project: Project,
+    pub score: f64,
+    /// Score contributions, largest first; they sum to `score`
+    pub reasons: Vec<MatchReason>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RecommendationResponse {
+    pub results: Vec<ScoredProject>,
+    /// Number of matching projects before paging
+    pub total: usize,
+    pub limit: usize,
+    pub offset: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collaborative: Option<CollaborativeInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub facets: Option<ProjectFacets>,
+}
+
+/// How collaborative filtering was applied to a response
+#[derive(Serialize, Debug, Clone)]
+pub struct CollaborativeInfo {
+    pub weight: f64,
+    pub applied: bool,
+    /// Projects the user has rated or starred
+    pub user_items: usize,
+    /// Why the response fell back to content-only ranking
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fallback: Option<String>,
+}
+
+/// Location of the editable preference-to-filter mappings
+pub const MAPPINGS_PATH: &str = "config/recommendation_mappings.json";
+
+/// Project attributes a preference maps to; any match adds to a project's score
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PreferenceMapping {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub naics_sectors: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub departments: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub countries: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub regions: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub frameworks: Vec<String>,
+    /// Words or phrases looked for in the project name, description, type and framework
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+}
+
+impl PreferenceMapping {
+    fn is_empty(&self) -> bool {
+        self.naics_sectors.is_empty()
+            && self.departments.is_empty()
+            && self.countries.is_empty()
+            && self.regions.is_empty()
+            && self.frameworks.is_empty()
+            && self.keywords.is_empty()
+    }
+}
+
+/// Preference name -> mapping, kept sorted so the file round-trips cleanly
+pub type PreferenceMappings = BTreeMap<String, PreferenceMapping>;
+
+fn mapping(naics_sectors: &[&str], departments: &[&str]) -> PreferenceMapping {
+    PreferenceMapping {
+        naics_sectors: naics_sectors.iter().map(|s| s.to_string()).collect(),
+        departments: departments.iter().map(|s| s.to_string()).collect(),
+        ..Default::default()
+    }
+}
+
+/// Built-in mappings used when config/recommendation_mappings.json is missing or invalid
+pub fn default_mappings() -> PreferenceMappings {
+    let mut mappings = PreferenceMappings::new();
+    mappings.insert("Agriculture".to_string(), mapping(&["Agriculture"], &["Technical Assistance"]));
+    mappings.insert("Education".to_string(), mapping(&["Educational Services"], &["Technical Assistance"]));
+    mappings.insert("Healthcare Access".to_string(), mapping(&["Health Care"], &["Equity Investments"]));
+    mappings.insert("Financial Inclusion".to_string(), mapping(&["Finance and Insurance"], &["Investment Funds", "Finance"]));
+    mappings.insert("Infrastructure Development".to_string(), mapping(&["Construction", "Transportation and Warehousing"], &[]));
+    mappings.insert("Technology Innovation".to_string(), mapping(&["Information"], &["Investment Funds"]));
+    mappings.insert("Small Business Support".to_string(), mapping(&["Finance and Insurance"], &["Investment Funds"]));
+    mappings.insert("Rural Development".to_string(), mapping(&[], &["Technical Assistance"]));
+    mappings.insert("Environmental Sustainability".to_string(), mapping(&["Utilities"], &["Finance"]));
+    mappings.insert("Renewable Energy".to_string(), mapping(&["Utilities"], &["Finance"]));
+    mappings.insert("Water & Sanitation".to_string(), mapping(&["Utilities"], &["Finance"]));
+    mappings.insert("Digital Inclusion".to_string(), mapping(&["Information", "Educational Services"], &["Technical Assistance"]));
+    mappings.insert("Economic Growth".to_string(), mapping(&["Finance and Insurance"], &["Investment Funds"]));
+    mappings.insert("Food Security".to_string(), mapping(&["Agriculture"], &["Technical Assistance"]));
     mappings
 }
 
+/// Read mappings from a JSON file
+pub fn load_mappings(path: &str) -> Result<PreferenceMappings, anyhow::Error> {
+    let content = std::fs::read_to_string(path)?;
+    let mappings: PreferenceMappings = serde_json::from_str(&content)?;
+    validate_mappings(&mappings)?;
+    Ok(mappings)
+}
+
+/// Read mappings from the mappings file, falling back to the built-in defaults
+pub fn load_mappings_or_default() -> PreferenceMappings {
+    match load_mappings(MAPPINGS_PATH) {
+        Ok(mappings) => mappings,
+        Err(e) => {
+            log::warn!("Using built-in recommendation mappings ({MAPPINGS_PATH}: {e})");
+            default_mappings()
+        }
+    }
+}
+
+/// Reject blank preference names and mappings that could never match a project
+pub fn validate_mappings(mappings: &PreferenceMappings) -> Result<(), anyhow::Error> {
+    for (name, mapping) in mappings {
+        if name.trim().is_empty() {
+            anyhow::bail!("Preference names cannot be empty");
+        }
+        if mapping.is_empty() {
+            anyhow::bail!("Preference '{name}' must map to at least one sector, department, country, region, framework or keyword");
+        }
+    }
+    Ok(())
+}
+
+/// Write mappings to a JSON file, replacing it atomically
+pub fn save_mappings(path: &str, mappings: &PreferenceMappings) -> Result<(), anyhow::Error> {
+    validate_mappings(mappings)?;
+    if let Some(dir) = std::path::Path::new(path).parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let temp_path = format!("{path}.tmp");
+    std::fs::write(&temp_path, serde_json::to_string_pretty(mappings)? + "\n")?;
+    std::fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
 fn find_column_index(headers: &[String], possible_names: &[&str]) -> Option<usize> {
     for name in possible_names {
         if let Some(index) = headers.iter().position(|h| h.to_lowercase().contains(&name.to_lowercase())) {
@@ -55,7 +453,8 @@ fn find_column_index(headers: &[String], possible_names: &[&str]) -> Option<usiz
     None
 }
 
-pub fn get_recommendations(preferences: &[String], excel_file_path: &str) -> Result<Vec<Project>, anyhow::Error> {
+/// Read every project with a name from the first sheet of the workbook
+pub fn load_projects(excel_file_path: &str) -> Result<Vec<Project>, anyhow::Error> {
     let mut excel: Xlsx<_> = open_workbook(excel_file_path)?;
     let mut projects = Vec::new();
 
@@ -97,8 +496,11 @@ pub fn get_recommendations(preferences: &[String], excel_file_path: &str) -> Res
                 continue;
             }
             
+            let project_number = get_string(project_number_idx);
             let project = Project {
                 id: (i + 1) as f64,
+                key: if project_number.is_empty() { format!("row-{}", i + 1) } else { project_number.clone() },
+                source: "excel".to_string(),
                 project_name,
                 project_description: get_string(project_description_idx),
                 country: get_string(country_idx),
@@ -108,37 +510,821 @@ pub fn get_recommendations(preferences: &[String], excel_file_path: &str) -> Res
                 project_type: get_string(project_type_idx),
                 region: get_string(region_idx),
                 fiscal_year: get_string(fiscal_year_idx),
-                project_number: get_string(project_number_idx),
+                project_number,
                 framework: get_string(framework_idx),
                 project_profile_url: get_string(project_profile_url_idx),
                 tags: vec![],
                 starred: false,
                 comment: String::new(),
+                average_rating: None,
+                vote_count: 0,
+                star_count: 0,
+                comment_count: 0,
+                user_rating: None,
             };
             projects.push(project);
         }
     }
 
-    let mappings = get_preference_to_filter_mappings();
-    let mut recommended_projects = Vec::new();
+    Ok(projects)
+}
 
-    for project in projects {
-        for preference in preferences {
-            if let Some(mapping) = mappings.get(preference) {
-                let naics_sectors = mapping.get("naicsSectors").and_then(|v| v.as_array()).map(|a| a.iter().map(|s| s.as_str().unwrap().to_string()).collect::<Vec<String>>()).unwrap_or_default();
-                let departments = mapping.get("departments").and_then(|v| v.as_array()).map(|a| a.iter().map(|s| s.as_str().unwrap().to_string()).collect::<Vec<String>>()).unwrap_or_default();
+fn project_text(project: &Project) -> String {
+    format!(
+        "{} {} {} {}",
+        project.project_name, project.project_description, project.project_type, project.framework
+    )
+    .to_lowercase()
+}
+
+/// Whole-word (or whole-phrase) match, so "R" doesn't match every project
+pub fn mentions(text: &str, term: &str) -> bool {
+    let term = term.trim().to_lowercase();
+    if term.is_empty() {
+        return false;
+    }
+    text.match_indices(&term).any(|(i, _)| {
+        let before = text[..i].chars().next_back();
+        let after = text[i + term.len()..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    })
+}
+
+/// Score every project against the request; a project's score accumulates across all
+/// matching preferences (each matched attribute or keyword counted separately, scaled by
+/// the preference's weight), each requested skill its text mentions, and the TF-IDF
+/// similarity of its text to the request's free text
+pub fn score_projects(set: &ProjectSet, request: &RecommendationRequest, mappings: &PreferenceMappings) -> Vec<ScoredProject> {
+    let weights = request.preference_weights();
+    let text_query = request.text.as_deref().and_then(|text| set.text_index.query(text));
+    let text_weight = request.text_weight.unwrap_or(TEXT_WEIGHT).max(0.0);
+    let mut scored = Vec::new();
+
+    for (position, project) in set.projects.iter().enumerate() {
+        if !request.filters.matches(project) {
+            continue;
+        }
+        let mut reasons = Vec::new();
+        let text = project_text(project);
+
+        for (preference, weight) in &weights {
+            let Some(mapping) = mappings.get(preference) else {
+                continue;
+            };
 
-                if (!naics_sectors.is_empty() && naics_sectors.contains(&project.naics_sector)) ||
-                   (!departments.is_empty() && departments.contains(&project.department)) {
-                    recommended_projects.push(project.clone());
-                    break; // Avoid duplicate projects
+            let attributes: [(&str, &str, &[String], &str, f64); 5] = [
+                ("sector", "NAICS sector", &mapping.naics_sectors, &project.naics_sector, SECTOR_WEIGHT),
+                ("department", "department", &mapping.departments, &project.department, DEPARTMENT_WEIGHT),
+                ("country", "country", &mapping.countries, &project.country, COUNTRY_WEIGHT),
+                ("region", "region", &mapping.regions, &project.region, REGION_WEIGHT),
+                ("framework", "framework", &mapping.frameworks, &project.framework, FRAMEWORK_WEIGHT),
+            ];
+            for (kind, label, values, actual, base) in attributes {
+                if !actual.is_empty() && values.iter().any(|v| v.eq_ignore_ascii_case(actual)) {
+                    reasons.push(MatchReason {
+                        kind: kind.to_string(),
+                        source: preference.clone(),
+                        message: format!("matched preference {preference} via {label} {actual}"),
+                        contribution: base * weight,
+                    });
                 }
             }
+
+            for keyword in mapping.keywords.iter().filter(|k| mentions(&text, k)) {
+                reasons.push(MatchReason {
+                    kind: "keyword".to_string(),
+                    source: preference.clone(),
+                    message: format!("matched preference {preference} via keyword '{}'", keyword.trim()),
+                    contribution: KEYWORD_WEIGHT * weight,
+                });
+            }
         }
+
+        if !request.skills.is_empty() {
+            for skill in request.skills.iter().filter(|skill| mentions(&text, skill)) {
+                reasons.push(MatchReason {
+                    kind: "skill".to_string(),
+                    source: skill.clone(),
+                    message: format!("skill '{}' mentioned in project", skill.trim()),
+                    contribution: SKILL_WEIGHT,
+                });
+            }
+        }
+
+        if let Some(query) = &text_query {
+            let (similarity, terms) = set.text_index.similarity(position, query);
+            if similarity >= MIN_TEXT_SIMILARITY && text_weight > 0.0 {
+                reasons.push(MatchReason {
+                    kind: "text".to_string(),
+                    source: "text".to_string(),
+                    message: format!("text similarity {:.2} (shared terms: {})", similarity, terms.join(", ")),
+                    contribution: similarity * text_weight,
+                });
+            }
+        }
+
+        let score: f64 = reasons.iter().map(|r| r.contribution).sum();
+        if score > 0.0 {
+            reasons.sort_by(|a, b| b.contribution.total_cmp(&a.contribution));
+            scored.push(ScoredProject { project: project.clone(), score, reasons });
+        }
+    }
+
+    sort_by_relevance(&mut scored);
+    scored
+}
+
+/// Highest score first; ties go to the larger commitment, then the more recent fiscal year
+pub fn sort_by_relevance(scored: &mut [ScoredProject]) {
+    scored.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| b.project.committed.total_cmp(&a.project.committed))
+            .then_with(|| b.project.fiscal_year.cmp(&a.project.fiscal_year))
+    });
+}
+
+/// Share of sector, region and department two projects have in common (0.0-1.0)
+fn attribute_similarity(a: &Project, b: &Project) -> f64 {
+    let same = |x: &str, y: &str| !x.trim().is_empty() && x.trim().eq_ignore_ascii_case(y.trim());
+    let shared = [
+        same(&a.naics_sector, &b.naics_sector),
+        same(&a.region, &b.region),
+        same(&a.department, &b.department),
+    ];
+    shared.iter().filter(|s| **s).count() as f64 / shared.len() as f64
+}
+
+/// Re-rank with maximal marginal relevance: each pick maximises
+/// `(1 - diversity) * relevance - diversity * similarity to the projects already picked`,
+/// with relevance scaled to the best score. Only the first `offset + limit` positions are
+/// re-ranked; greedy picks don't depend on how many follow, so pages stay consistent.
+pub fn diversify(mut scored: Vec<ScoredProject>, diversity: f64, limit: Option<usize>, offset: Option<usize>) -> Vec<ScoredProject> {
+    let diversity = diversity.clamp(0.0, 1.0);
+    let picks = offset.unwrap_or(0) + limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let best = scored.iter().map(|s| s.score).fold(0.0, f64::max);
+    if diversity <= 0.0 || best <= 0.0 || scored.len() < 2 {
+        return scored;
+    }
+
+    let mut ranked = Vec::with_capacity(scored.len());
+    // Highest similarity of each remaining project to anything already picked
+    let mut redundancy = vec![0.0; scored.len()];
+    while ranked.len() < picks && !scored.is_empty() {
+        let mmr = |i: usize| (1.0 - diversity) * scored[i].score / best - diversity * redundancy[i];
+        let next = (0..scored.len()).fold(0, |best_i, i| if mmr(i) > mmr(best_i) { i } else { best_i });
+        let picked = scored.remove(next);
+        redundancy.remove(next);
+        for (item, r) in scored.iter().zip(redundancy.iter_mut()) {
+            *r = f64::max(*r, attribute_similarity(&picked.project, &item.project));
+        }
+        ranked.push(picked);
+    }
+    ranked.extend(scored);
+    ranked
+}
+
+/// Apply limit/offset to a ranked list
+pub fn paginate(scored: Vec<ScoredProject>, limit: Option<usize>, offset: Option<usize>) -> RecommendationResponse {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = offset.unwrap_or(0);
+    let total = scored.len();
+    let results = scored.into_iter().skip(offset).take(limit).collect();
+
+    RecommendationResponse { results, total, limit, offset, collaborative: None, facets: None }
+}
+
+/// Projects together with the text index built over them; rebuilt whenever the projects change
+#[derive(Debug, Default)]
+pub struct ProjectSet {
+    pub projects: Vec<Project>,
+    pub text_index: TextIndex,
+}
+
+impl ProjectSet {
+    pub fn new(projects: Vec<Project>) -> Self {
+        let text_index = TextIndex::build(&projects);
+        ProjectSet { projects, text_index }
+    }
+}
+
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "has", "have", "in", "into", "is", "it",
+    "its", "of", "on", "or", "that", "the", "their", "this", "to", "was", "were", "will", "with", "which",
+    "i", "me", "my", "we", "our", "you", "your", "am", "interested", "project", "projects",
+];
+
+/// Lowercase words of two or more characters, without stop words; a trailing plural "s" is dropped
+fn tokenize(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.len() >= 2 && !STOP_WORDS.contains(w))
+        .map(|w| match w.strip_suffix('s') {
+            Some(stem) if stem.len() >= 3 && !stem.ends_with('s') => stem.to_string(),
+            _ => w.to_string(),
+        })
+        .collect()
+}
+
+/// Unit-length TF-IDF vector from term counts
+fn weigh(counts: HashMap<String, usize>, idf: &HashMap<String, f64>) -> HashMap<String, f64> {
+    let mut vector: HashMap<String, f64> = counts
+        .into_iter()
+        .filter_map(|(term, count)| idf.get(&term).map(|w| (term, (1.0 + (count as f64).ln()) * w)))
+        .collect();
+    let norm = vector.values().map(|w| w * w).sum::<f64>().sqrt();
+    if norm > 0.0 {
+        vector.values_mut().for_each(|w| *w /= norm);
     }
+    vector
+}
 
-    // Limit the recommendations to 5 as mentioned in the commit
-    recommended_projects.truncate(5);
+fn term_counts(text: &str) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for token in tokenize(text) {
+        *counts.entry(token).or_insert(0) += 1;
+    }
+    counts
+}
 
-    Ok(recommended_projects)
-}
\ No newline at end of file
+/// TF-IDF index over project name, description and tags, compared by cosine similarity
+#[derive(Debug, Default)]
+pub struct TextIndex {
+    idf: HashMap<String, f64>,
+    documents: Vec<HashMap<String, f64>>,
+}
+
+impl TextIndex {
+    pub fn build(projects: &[Project]) -> Self {
+        // The name is repeated so it counts for more than a word buried in the description
+        let counts: Vec<HashMap<String, usize>> = projects
+            .iter()
+            .map(|p| term_counts(&format!("{0} {0} {1} {2}", p.project_name, p.project_description, p.tags.join(" "))))
+            .collect();
+
+        let mut document_frequency: HashMap<&str, usize> = HashMap::new();
+        for doc in &counts {
+            for term in doc.keys() {
+                *document_frequency.entry(term).or_insert(0) += 1;
+            }
+        }
+        let total = counts.len() as f64;
+        let idf: HashMap<String, f64> = document_frequency
+            .into_iter()
+            .map(|(term, df)| (term.to_string(), ((total + 1.0) / (df as f64 + 1.0)).ln() + 1.0))
+            .collect();
+
+        let documents = counts.into_iter().map(|c| weigh(c, &idf)).collect();
+        TextIndex { idf, documents }
+    }
+
+    /// Vector for free text; None when none of its words appear in any project
+    pub fn query(&self, text: &str) -> Option<HashMap<String, f64>> {
+        let vector = weigh(term_counts(text), &self.idf);
+        (!vector.is_empty()).then_some(vector)
+    }
+
+    /// Cosine similarity between a project and a query, with the shared terms that contribute most
+    pub fn similarity(&self, position: usize, query: &HashMap<String, f64>) -> (f64, Vec<String>) {
+        let Some(document) = self.documents.get(position) else {
+            return (0.0, Vec::new());
+        };
+        let mut shared: Vec<(&String, f64)> = query
+            .iter()
+            .filter_map(|(term, w)| document.get(term).map(|d| (term, w * d)))
+            .collect();
+        shared.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        let similarity = shared.iter().map(|(_, w)| w).sum();
+        (similarity, shared.into_iter().take(3).map(|(term, _)| term.clone()).collect())
+    }
+}
+
+/// Value given to a star when no explicit vote exists
+const STAR_VALUE: f64 = 4.0;
+
+/// One user's feedback on one project: a 1-5 vote, or STAR_VALUE for a star
+#[derive(Debug, Clone)]
+pub struct Interaction {
+    pub user_id: String,
+    pub project_key: String,
+    pub rating: Option<f64>,
+    pub starred: bool,
+}
+
+impl Interaction {
+    fn value(&self) -> f64 {
+        match (self.rating, self.starred) {
+            (Some(rating), true) => rating.max(STAR_VALUE),
+            (Some(rating), false) => rating,
+            (None, _) => STAR_VALUE,
+        }
+    }
+}
+
+/// Predicted interest in a project, with the user's projects that led to it
+#[derive(Debug, Clone)]
+pub struct CollaborativeScore {
+    /// 0.0-1.0
+    pub score: f64,
+    pub because: Vec<String>,
+}
+
+/// Width of the 1-5 rating scale; a prediction this far above the user's mean scores 1.0
+const RATING_SPREAD: f64 = 4.0;
+
+/// Item-item collaborative filtering: each rating is centered on its user's mean so generous and
+/// harsh raters compare fairly, projects are compared by cosine similarity of those centered
+/// ratings, and a project the user hasn't rated is predicted from how far above their own mean
+/// they rated similar projects. Projects predicted at or below the user's mean are left out.
+/// Returns predictions keyed by project key.
+pub fn collaborative_scores(interactions: &[Interaction], user_id: &str) -> HashMap<String, CollaborativeScore> {
+    let mut users: HashMap<&str, HashMap<&str, f64>> = HashMap::new();
+    for interaction in interactions {
+        users
+            .entry(interaction.user_id.as_str())
+            .or_default()
+            .insert(interaction.project_key.as_str(), interaction.value());
+    }
+
+    let mut items: HashMap<&str, HashMap<&str, f64>> = HashMap::new();
+    for (user, ratings) in &users {
+        let mean = ratings.values().sum::<f64>() / ratings.len() as f64;
+        for (item, value) in ratings {
+            items.entry(*item).or_default().insert(*user, value - mean);
+        }
+    }
+
+    let norms: HashMap<&str, f64> = items
+        .iter()
+        .map(|(item, users)| (*item, users.values().map(|v| v * v).sum::<f64>().sqrt()))
+        .collect();
+    let cosine = |a: &str, b: &str| -> f64 {
+        let (users_a, users_b) = (&items[a], &items[b]);
+        let dot: f64 = users_a.iter().filter_map(|(u, va)| users_b.get(u).map(|vb| va * vb)).sum();
+        let denominator = norms[a] * norms[b];
+        if denominator > 0.0 { dot / denominator } else { 0.0 }
+    };
+
+    let rated: Vec<(&str, f64)> = items
+        .iter()
+        .filter_map(|(item, users)| users.get(user_id).map(|v| (*item, *v)))
+        .collect();
+
+    let mut predictions = HashMap::new();
+    for candidate in items.keys().filter(|item| !rated.iter().any(|(r, _)| r == *item)) {
+        let mut neighbours: Vec<(&str, f64, f64)> = rated
+            .iter()
+            .map(|(item, centered)| (*item, cosine(item, candidate), *centered))
+            .filter(|(_, similarity, _)| *similarity > 0.0)
+            .collect();
+        if neighbours.is_empty() {
+            continue;
+        }
+
+        let similarity_total: f64 = neighbours.iter().map(|(_, s, _)| s).sum();
+        let predicted = neighbours.iter().map(|(_, s, c)| s * c).sum::<f64>() / similarity_total;
+        if predicted <= 0.0 {
+            continue;
+        }
+        // Shrink predictions backed by little similarity towards zero
+        let confidence = similarity_total / (similarity_total + 1.0);
+
+        neighbours.sort_by(|a, b| (b.1 * b.2).total_cmp(&(a.1 * a.2)));
+        predictions.insert(
+            candidate.to_string(),
+            CollaborativeScore {
+                score: (predicted / RATING_SPREAD).min(1.0) * confidence,
+                because: neighbours.iter().filter(|(_, _, c)| *c > 0.0).take(3).map(|(item, _, _)| item.to_string()).collect(),
+            },
+        );
+    }
+    predictions
+}
+
+/// Blend content scores with collaborative predictions: content scores are scaled to 0-1 by
+/// the best content score, then `weight` of the final score comes from collaborative filtering.
+/// Projects only collaborative filtering found are added.
+pub fn blend_collaborative(
+    scored: Vec<ScoredProject>,
+    projects: &[Project],
+    predictions: &HashMap<String, CollaborativeScore>,
+    weight: f64,
+) -> Vec<ScoredProject> {
+    let weight = weight.clamp(0.0, 1.0);
+    let best_content = scored.iter().map(|s| s.score).fold(0.0, f64::max);
+    let content_scale = if best_content > 0.0 { (1.0 - weight) / best_content } else { 0.0 };
+    let names: HashMap<&str, &str> = projects.iter().map(|p| (p.key.as_str(), p.project_name.as_str())).collect();
+
+    let mut blended: Vec<ScoredProject> = Vec::with_capacity(scored.len());
+    let mut seen = std::collections::HashSet::new();
+    for mut item in scored {
+        item.reasons.iter_mut().for_each(|r| r.contribution *= content_scale);
+        seen.insert(item.project.key.clone());
+        blended.push(item);
+    }
+    for project in projects.iter().filter(|p| predictions.contains_key(&p.key)) {
+        if seen.insert(project.key.clone()) {
+            blended.push(ScoredProject { project: project.clone(), score: 0.0, reasons: Vec::new() });
+        }
+    }
+
+    for item in blended.iter_mut() {
+        if let Some(prediction) = predictions.get(&item.project.key) {
+            let because: Vec<&str> = prediction.because.iter().map(|k| names.get(k.as_str()).copied().unwrap_or(k)).collect();
+            item.reasons.push(MatchReason {
+                kind: "collaborative".to_string(),
+                source: "team".to_string(),
+                message: format!("people who liked {} also liked this", because.join(", ")),
+                contribution: prediction.score * weight,
+            });
+        }
+        item.reasons.retain(|r| r.contribution > 0.0);
+        item.reasons.sort_by(|a, b| b.contribution.total_cmp(&a.contribution));
+        item.score = item.reasons.iter().map(|r| r.contribution).sum();
+    }
+
+    blended.retain(|item| item.score > 0.0);
+    sort_by_relevance(&mut blended);
+    blended
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(key: &str) -> Project {
+        Project {
+            id: 0.0,
+            key: key.to_string(),
+            source: "test".to_string(),
+            project_name: key.to_string(),
+            project_description: String::new(),
+            country: String::new(),
+            naics_sector: String::new(),
+            committed: 0.0,
+            department: String::new(),
+            project_type: String::new(),
+            region: String::new(),
+            fiscal_year: String::new(),
+            project_number: key.to_string(),
+            framework: String::new(),
+            project_profile_url: String::new(),
+            tags: Vec::new(),
+            starred: false,
+            comment: String::new(),
+            average_rating: None,
+            vote_count: 0,
+            star_count: 0,
+            comment_count: 0,
+            user_rating: None,
+        }
+    }
+
+    fn scored(key: &str, score: f64) -> ScoredProject {
+        ScoredProject {
+            project: project(key),
+            score,
+            reasons: vec![MatchReason { kind: "sector".into(), source: "test".into(), message: String::new(), contribution: score }],
+        }
+    }
+
+    fn energy_mappings() -> PreferenceMappings {
+        PreferenceMappings::from([(
+            "Energy".to_string(),
+            PreferenceMapping {
+                naics_sectors: vec!["Utilities".into()],
+                departments: vec!["Finance".into()],
+                keywords: vec!["solar".into()],
+                ..Default::default()
+            },
+        )])
+    }
+
+    fn request(preferences: &[&str]) -> RecommendationRequest {
+        RecommendationRequest {
+            preferences: Preferences::List(preferences.iter().map(|p| p.to_string()).collect()),
+            ..Default::default()
+        }
+    }
+
+    fn keys(scored: &[ScoredProject]) -> Vec<&str> {
+        scored.iter().map(|s| s.project.key.as_str()).collect()
+    }
+
+    fn votes(ratings: &[(&str, &str, f64)]) -> Vec<Interaction> {
+        ratings
+            .iter()
+            .map(|(user, project, rating)| Interaction {
+                user_id: user.to_string(),
+                project_key: project.to_string(),
+                rating: Some(*rating),
+                starred: false,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn projects_like_a_low_rated_one_are_not_recommended() {
+        // "me" disliked x and liked y; the others rated z like x and w like y
+        let interactions = votes(&[
+            ("me", "x", 1.0), ("me", "y", 5.0),
+            ("a", "x", 1.0), ("a", "y", 5.0), ("a", "z", 1.0), ("a", "w", 5.0),
+            ("b", "x", 2.0), ("b", "y", 5.0), ("b", "z", 1.0), ("b", "w", 4.0),
+        ]);
+        let predictions = collaborative_scores(&interactions, "me");
+
+        assert!(!predictions.contains_key("z"), "{predictions:?}");
+        let w = &predictions["w"];
+        assert!(w.score > 0.0 && w.score <= 1.0);
+        assert_eq!(w.because, vec!["y".to_string()]);
+        // Rated projects are never predicted
+        assert!(!predictions.contains_key("x") && !predictions.contains_key("y"));
+    }
+
+    #[test]
+    fn a_generous_rater_does_not_lift_everything() {
+        // Every project is rated 5 by "a", so "a" says nothing about which one "me" would prefer
+        let interactions = votes(&[("me", "x", 5.0), ("a", "x", 5.0), ("a", "z", 5.0)]);
+        assert!(collaborative_scores(&interactions, "me").is_empty());
+    }
+
+    #[test]
+    fn users_without_feedback_get_no_predictions() {
+        let interactions = votes(&[("a", "x", 5.0), ("a", "y", 1.0), ("b", "x", 4.0), ("b", "y", 2.0)]);
+        assert!(collaborative_scores(&interactions, "me").is_empty());
+        assert!(collaborative_scores(&[], "me").is_empty());
+    }
+
+    #[test]
+    fn blend_weight_splits_content_and_collaborative_scores() {
+        let projects = vec![project("a"), project("b")];
+        let predictions = HashMap::from([("b".to_string(), CollaborativeScore { score: 0.8, because: vec!["a".into()] })]);
+
+        let blended = blend_collaborative(vec![scored("a", 2.0)], &projects, &predictions, 0.25);
+        let score = |key: &str| blended.iter().find(|s| s.project.key == key).map(|s| s.score);
+        assert!((score("a").unwrap() - 0.75).abs() < 1e-9);
+        assert!((score("b").unwrap() - 0.2).abs() < 1e-9);
+        assert_eq!(blended[0].project.key, "a");
+
+        // Weight 0 is content only: projects only collaborative filtering found are dropped
+        let content_only = blend_collaborative(vec![scored("a", 2.0)], &projects, &predictions, 0.0);
+        assert_eq!(content_only.len(), 1);
+        assert!((content_only[0].score - 1.0).abs() < 1e-9);
+
+        // Weight 1 (and anything above) is collaborative only
+        let collaborative_only = blend_collaborative(vec![scored("a", 2.0)], &projects, &predictions, 1.5);
+        assert_eq!(collaborative_only.len(), 1);
+        assert_eq!(collaborative_only[0].project.key, "b");
+        assert!((collaborative_only[0].score - 0.8).abs() < 1e-9);
+    }
+
+    #[test]
+    fn projects_are_ranked_by_accumulated_score() {
+        let mut both = project("both");
+        both.naics_sector = "utilities".into();
+        both.department = "Finance".into();
+        let mut sector = project("sector");
+        sector.naics_sector = "Utilities".into();
+        let mut bigger = project("bigger");
+        bigger.naics_sector = "Utilities".into();
+        bigger.committed = 5_000_000.0;
+        let mut unrelated = project("unrelated");
+        unrelated.naics_sector = "Agriculture".into();
+        let set = ProjectSet::new(vec![sector, unrelated, bigger, both]);
+
+        let scored = score_projects(&set, &request(&["Energy"]), &energy_mappings());
+        // Ties go to the larger commitment; projects matching nothing are left out
+        assert_eq!(keys(&scored), vec!["both", "bigger", "sector"]);
+        assert_eq!(scored[0].score, SECTOR_WEIGHT + DEPARTMENT_WEIGHT);
+        assert_eq!(scored[1].score, SECTOR_WEIGHT);
+
+        assert!(score_projects(&set, &request(&["Unmapped"]), &energy_mappings()).is_empty());
+    }
+
+    #[test]
+    fn pages_are_bounded() {
+        let scored: Vec<ScoredProject> = (0..7).map(|i| scored(&format!("p{i}"), 10.0 - i as f64)).collect();
+
+        let first = paginate(scored.clone(), None, None);
+        assert_eq!((first.total, first.limit, first.offset), (7, DEFAULT_LIMIT, 0));
+        assert_eq!(keys(&first.results), vec!["p0", "p1", "p2", "p3", "p4"]);
+
+        let last = paginate(scored.clone(), Some(5), Some(5));
+        assert_eq!(keys(&last.results), vec!["p5", "p6"]);
+
+        let past_the_end = paginate(scored.clone(), Some(5), Some(10));
+        assert!(past_the_end.results.is_empty());
+        assert_eq!(past_the_end.total, 7);
+
+        assert_eq!(paginate(scored.clone(), Some(0), None).limit, 1);
+        assert_eq!(paginate(scored, Some(1_000), None).limit, MAX_LIMIT);
+    }
+
+    #[test]
+    fn preference_weights_combine_lists_weights_and_policy() {
+        assert_eq!(request(&["Energy", "Education"]).preference_weights(), vec![("Education".to_string(), 1.0), ("Energy".to_string(), 1.0)]);
+
+        let weighted = RecommendationRequest {
+            preferences: Preferences::Weighted(HashMap::from([
+                ("Renewable Energy".to_string(), 0.5),
+                ("Education".to_string(), 0.0),
+                ("Healthcare Access".to_string(), -1.0),
+            ])),
+            policy: HashMap::from([("Environment".to_string(), 2.5), ("governance".to_string(), 9.0), ("unknown".to_string(), 5.0)]),
+            ..Default::default()
+        };
+        // Policy scores are clamped to 0..=5 and added to every interest of their category;
+        // interests left without a positive weight are dropped
+        assert_eq!(
+            weighted.preference_weights(),
+            vec![
+                ("Environmental Sustainability".to_string(), 0.5),
+                ("Infrastructure Development".to_string(), 1.0),
+                ("Renewable Energy".to_string(), 1.0),
+                ("Water & Sanitation".to_string(), 0.5),
+            ]
+        );
+    }
+
+    #[test]
+    fn preference_weight_scales_the_match() {
+        let mut matched = project("matched");
+        matched.naics_sector = "Utilities".into();
+        let set = ProjectSet::new(vec![matched]);
+        let request = RecommendationRequest {
+            preferences: Preferences::Weighted(HashMap::from([("Energy".to_string(), 0.5)])),
+            ..Default::default()
+        };
+
+        let scored = score_projects(&set, &request, &energy_mappings());
+        assert_eq!(scored[0].score, SECTOR_WEIGHT * 0.5);
+    }
+
+    #[test]
+    fn reasons_explain_every_contribution_largest_first() {
+        let mut matched = project("matched");
+        matched.department = "Finance".into();
+        matched.naics_sector = "Utilities".into();
+        matched.project_description = "Solar mini-grids, monitored with Python dashboards".into();
+        let set = ProjectSet::new(vec![matched]);
+        let request = RecommendationRequest { skills: vec!["python".into(), "rust".into()], ..request(&["Energy"]) };
+
+        let scored = score_projects(&set, &request, &energy_mappings());
+        let reasons = &scored[0].reasons;
+        let kinds: Vec<&str> = reasons.iter().map(|r| r.kind.as_str()).collect();
+        assert_eq!(kinds, vec!["sector", "department", "keyword", "skill"]);
+        assert_eq!(reasons.iter().map(|r| r.contribution).sum::<f64>(), scored[0].score);
+        assert_eq!(reasons[0].source, "Energy");
+        assert_eq!(reasons[0].message, "matched preference Energy via NAICS sector Utilities");
+        assert_eq!(reasons[2].message, "matched preference Energy via keyword 'solar'");
+        assert_eq!(reasons[3].message, "skill 'python' mentioned in project");
+    }
+
+    fn described(key: &str, name: &str, description: &str) -> Project {
+        Project { project_name: name.into(), project_description: description.into(), ..project(key) }
+    }
+
+    #[test]
+    fn tokens_drop_stop_words_short_words_and_plurals() {
+        assert_eq!(tokenize("The Solar panels and R grids for a class bus"), vec!["solar", "panel", "grid", "class", "bus"]);
+    }
+
+    #[test]
+    fn text_index_ranks_rare_and_name_terms_higher() {
+        let index = TextIndex::build(&[
+            described("pumps", "Solar pumps", "Water for farms"),
+            described("treatment", "Treatment plant", "Clean water for cities"),
+            described("sanitation", "Sanitation", "Water and toilets"),
+            described("mention", "Rural roads", "Some treatment of runoff water"),
+        ]);
+        assert!(index.query("the and of").is_none());
+        assert!(index.query("blockchain").is_none());
+
+        let query = index.query("water treatment").unwrap();
+        let similarity: Vec<f64> = (0..4).map(|position| index.similarity(position, &query).0).collect();
+        // Both terms beat "water" alone, and "treatment" in the name beats it in the description
+        assert!(similarity[1] > similarity[3]);
+        assert!(similarity[3] > similarity[0] && similarity[3] > similarity[2]);
+
+        let (_, terms) = index.similarity(1, &query);
+        assert_eq!(terms, vec!["treatment", "water"]);
+        assert_eq!(index.similarity(9, &query), (0.0, Vec::new()));
+    }
+
+    #[test]
+    fn free_text_adds_a_text_reason() {
+        let set = ProjectSet::new(vec![
+            described("treatment", "Treatment plant", "Clean water for cities"),
+            described("roads", "Rural roads", "Paving"),
+        ]);
+        let request = RecommendationRequest { text: Some("I care about water treatment".into()), ..Default::default() };
+
+        let scored = score_projects(&set, &request, &PreferenceMappings::new());
+        assert_eq!(keys(&scored), vec!["treatment"]);
+        let reason = &scored[0].reasons[0];
+        assert_eq!(reason.kind, "text");
+        assert!(reason.message.ends_with("(shared terms: treatment, water)"), "{}", reason.message);
+        let query = set.text_index.query("water treatment").unwrap();
+        assert!((scored[0].score - set.text_index.similarity(0, &query).0 * TEXT_WEIGHT).abs() < 1e-9);
+
+        let muted = RecommendationRequest { text_weight: Some(0.0), ..request };
+        assert!(score_projects(&set, &muted, &PreferenceMappings::new()).is_empty());
+    }
+
+    fn located(key: &str, country: &str, region: &str, fiscal_year: &str, committed: f64) -> Project {
+        Project {
+            country: country.into(),
+            region: region.into(),
+            fiscal_year: fiscal_year.into(),
+            committed,
+            ..project(key)
+        }
+    }
+
+    fn counts(values: &[FacetValue]) -> Vec<(&str, usize)> {
+        values.iter().map(|v| (v.value.as_str(), v.count)).collect()
+    }
+
+    #[test]
+    fn fiscal_year_takes_the_first_four_digit_year() {
+        assert_eq!(fiscal_year("FY 2023"), Some(2023));
+        assert_eq!(fiscal_year("2021-2022"), Some(2021));
+        assert_eq!(fiscal_year("FY23"), None);
+    }
+
+    #[test]
+    fn filters_require_every_dimension() {
+        let mut tagged = located("tagged", "Kenya", "East Africa", "FY 2023", 300.0);
+        tagged.tags = vec!["Solar ".into(), "Water".into()];
+        let filters = ProjectFilters {
+            countries: vec![" kenya".into(), "Ghana".into()],
+            fiscal_year_min: Some(2023),
+            committed_max: Some(300.0),
+            tags: vec!["solar".into(), "WATER".into()],
+            ..Default::default()
+        };
+        assert!(filters.matches(&tagged));
+        assert!(ProjectFilters::default().matches(&project("anything")));
+
+        let mut untagged = tagged.clone();
+        untagged.tags.pop();
+        assert!(!filters.matches(&untagged));
+        assert!(!filters.matches(&Project { country: "Peru".into(), ..tagged.clone() }));
+        assert!(!filters.matches(&Project { fiscal_year: "2022".into(), ..tagged.clone() }));
+        assert!(!filters.matches(&Project { fiscal_year: String::new(), ..tagged.clone() }));
+        assert!(!filters.matches(&Project { committed: 301.0, ..tagged }));
+    }
+
+    #[test]
+    fn facets_ignore_their_own_filter() {
+        let projects = vec![
+            located("p1", "Kenya", "East Africa", "FY 2022", 100.0),
+            located("p2", "Kenya", "East Africa", "FY 2023", 300.0),
+            located("p3", "Ghana", "West Africa", "2023", 0.0),
+            located("p4", "Peru", "South America", "", 50.0),
+        ];
+        let filters = ProjectFilters { countries: vec!["kenya".into()], fiscal_year_min: Some(2023), ..Default::default() };
+
+        let filtered = facets(&projects, &filters);
+        // Countries are counted within the fiscal year filter only, fiscal years within the country filter only
+        assert_eq!(counts(&filtered.countries), vec![("Ghana", 1), ("Kenya", 1)]);
+        assert_eq!(counts(&filtered.fiscal_years), vec![("2023", 1), ("2022", 1)]);
+        assert_eq!(counts(&filtered.regions), vec![("East Africa", 1)]);
+        assert_eq!((filtered.committed.min, filtered.committed.max), (Some(300.0), Some(300.0)));
+
+        let unfiltered = facets(&projects, &ProjectFilters::default());
+        assert_eq!(counts(&unfiltered.countries), vec![("Kenya", 2), ("Ghana", 1), ("Peru", 1)]);
+        assert_eq!(counts(&unfiltered.fiscal_years), vec![("2023", 2), ("2022", 1)]);
+        // Projects without a commitment don't widen the range
+        assert_eq!((unfiltered.committed.min, unfiltered.committed.max), (Some(50.0), Some(300.0)));
+    }
+
+    fn scored_in(key: &str, score: f64, sector: &str, region: &str) -> ScoredProject {
+        let mut item = scored(key, score);
+        item.project.naics_sector = sector.into();
+        item.project.region = region.into();
+        item
+    }
+
+    #[test]
+    fn diversify_trades_relevance_for_variety() {
+        let ranked = vec![
+            scored_in("a", 3.0, "Utilities", "East Africa"),
+            scored_in("b", 2.9, "utilities", "East Africa"),
+            scored_in("c", 2.0, "Agriculture", "West Africa"),
+            scored_in("d", 1.0, "Utilities", "East Africa"),
+        ];
+
+        // "b" is nearly as relevant as "a" but shares its sector and region, so "c" comes second;
+        // positions past the requested page keep their relevance order
+        assert_eq!(keys(&diversify(ranked.clone(), 0.5, Some(2), None)), vec!["a", "c", "b", "d"]);
+        assert_eq!(keys(&diversify(ranked.clone(), 0.0, Some(2), None)), vec!["a", "b", "c", "d"]);
+        assert_eq!(keys(&diversify(ranked.clone(), 1.0, Some(4), None)), vec!["a", "c", "b", "d"]);
+
+        // A later page sees the same ordering as one page covering both
+        let second_page = paginate(diversify(ranked.clone(), 0.5, Some(1), Some(1)), Some(1), Some(1));
+        assert_eq!(keys(&second_page.results), vec!["c"]);
+        assert_eq!(keys(&diversify(ranked, 0.5, Some(1), None))[0], "a");
+    }
+}
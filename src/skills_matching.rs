@@ -0,0 +1,615 @@
+// src/skills_matching.rs
+use actix_web::{web, HttpResponse, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{Pool, Postgres, Row};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use crate::ApiState;
+
+/// Proficiency levels used by the survey's skills form, lowest first
+const LEVELS: [&str; 4] = ["beginner", "intermediate", "advanced", "expert"];
+
+/// Share of a requirement's weight earned through a related technology rather than the skill itself
+const RELATED_CREDIT: f64 = 0.5;
+const REQUIRED_WEIGHT: f64 = 2.0;
+const OPTIONAL_WEIGHT: f64 = 1.0;
+
+const DEFAULT_MATCH_LIMIT: usize = 10;
+const MAX_MATCH_LIMIT: usize = 100;
+
+fn parse_level(level: &str) -> Option<i16> {
+    LEVELS
+        .iter()
+        .position(|l| l.eq_ignore_ascii_case(level.trim()))
+        .map(|i| i as i16 + 1)
+}
+
+fn level_name(level: i16) -> &'static str {
+    LEVELS[(level.clamp(1, 4) - 1) as usize]
+}
+
+fn skill_key(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+/// One skill as the survey's skills form submits it
+#[derive(Debug, Deserialize)]
+pub struct SkillInput {
+    pub name: String,
+    pub category: Option<String>,
+    pub level: String,
+    #[serde(default)]
+    pub experience: Option<i32>,
+    #[serde(default)]
+    pub related: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PersonSkillsRequest {
+    pub skills: Vec<SkillInput>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RequirementInput {
+    pub name: String,
+    /// Minimum proficiency; defaults to intermediate
+    #[serde(default)]
+    pub level: Option<String>,
+    #[serde(default = "default_required")]
+    pub required: bool,
+}
+
+fn default_required() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProjectSkillsRequest {
+    pub skills: Vec<RequirementInput>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MatchQuery {
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MemberSkill {
+    pub name: String,
+    #[serde(skip)]
+    pub key: String,
+    pub category: Option<String>,
+    #[serde(serialize_with = "serialize_level")]
+    pub level: i16,
+    pub years_experience: Option<i32>,
+    pub related: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SkillRequirement {
+    pub name: String,
+    #[serde(skip)]
+    pub key: String,
+    #[serde(rename = "level", serialize_with = "serialize_level")]
+    pub min_level: i16,
+    pub required: bool,
+}
+
+fn serialize_level<S: serde::Serializer>(level: &i16, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(level_name(*level))
+}
+
+/// How one requirement was (or wasn't) met
+#[derive(Debug, Clone, Serialize)]
+pub struct SkillMatchReason {
+    /// "exact", "related" or "missing"
+    pub kind: String,
+    pub skill: String,
+    pub message: String,
+    pub contribution: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SkillMatch {
+    /// 0.0-1.0: the weighted share of requirements met, scaled down where proficiency falls short
+    pub score: f64,
+    pub matched: usize,
+    pub requirements: usize,
+    /// Required skills the person lacks entirely
+    pub missing_required: Vec<String>,
+    pub reasons: Vec<SkillMatchReason>,
+    /// Levels above the minimum across matched skills, used to break score ties
+    #[serde(skip)]
+    surplus: i16,
+}
+
+/// Score a person's skills against a project's requirements. Each requirement counts
+/// double when required; a skill below the minimum level earns level/minimum of its weight,
+/// and a related technology earns RELATED_CREDIT of that.
+pub fn score_match(requirements: &[SkillRequirement], skills: &[MemberSkill]) -> SkillMatch {
+    let total_weight: f64 = requirements
+        .iter()
+        .map(|r| if r.required { REQUIRED_WEIGHT } else { OPTIONAL_WEIGHT })
+        .sum();
+    let mut result = SkillMatch {
+        score: 0.0,
+        matched: 0,
+        requirements: requirements.len(),
+        missing_required: Vec::new(),
+        reasons: Vec::new(),
+        surplus: 0,
+    };
+    if total_weight <= 0.0 {
+        return result;
+    }
+
+    for requirement in requirements {
+        let weight = if requirement.required { REQUIRED_WEIGHT } else { OPTIONAL_WEIGHT } / total_weight;
+        let fit = |level: i16| (level as f64 / requirement.min_level as f64).min(1.0);
+        let wanted = format!("{} ({})", requirement.name, level_name(requirement.min_level));
+
+        if let Some(skill) = skills.iter().find(|s| s.key == requirement.key) {
+            let contribution = weight * fit(skill.level);
+            let message = if skill.level >= requirement.min_level {
+                format!("has {} at {} level, needs {}", skill.name, level_name(skill.level), level_name(requirement.min_level))
+            } else {
+                format!("has {} at {} level, below the {} needed", skill.name, level_name(skill.level), level_name(requirement.min_level))
+            };
+            result.matched += 1;
+            result.surplus += (skill.level - requirement.min_level).max(0);
+            result.score += contribution;
+            result.reasons.push(SkillMatchReason { kind: "exact".to_string(), skill: requirement.name.clone(), message, contribution });
+            continue;
+        }
+
+        let related = skills
+            .iter()
+            .filter(|s| s.related.iter().any(|r| skill_key(r) == requirement.key))
+            .max_by_key(|s| s.level);
+        if let Some(skill) = related {
+            let contribution = weight * RELATED_CREDIT * fit(skill.level);
+            result.matched += 1;
+            result.score += contribution;
+            result.reasons.push(SkillMatchReason {
+                kind: "related".to_string(),
+                skill: requirement.name.clone(),
+                message: format!("lists {} as related to {} ({})", requirement.name, skill.name, level_name(skill.level)),
+                contribution,
+            });
+        } else {
+            if requirement.required {
+                result.missing_required.push(requirement.name.clone());
+            }
+            result.reasons.push(SkillMatchReason {
+                kind: "missing".to_string(),
+                skill: requirement.name.clone(),
+                message: format!("no experience with {wanted}{}", if requirement.required { ", which is required" } else { "" }),
+                contribution: 0.0,
+            });
+        }
+    }
+
+    result.reasons.sort_by(|a, b| b.contribution.total_cmp(&a.contribution));
+    result
+}
+
+/// Highest score first, then the most proficiency above the minimums
+fn sort_matches<T>(matches: &mut [(T, SkillMatch)]) {
+    matches.sort_by(|(_, a), (_, b)| b.score.total_cmp(&a.score).then_with(|| b.surplus.cmp(&a.surplus)));
+}
+
+fn validate_id(id: &str, label: &str, max: usize) -> Result<(), String> {
+    if id.trim().is_empty() || id.len() > max {
+        return Err(format!("{label} must be 1-{max} characters"));
+    }
+    Ok(())
+}
+
+fn bad_request(error: String) -> Result<HttpResponse> {
+    Ok(HttpResponse::BadRequest().json(json!({ "success": false, "error": error })))
+}
+
+fn server_error(error: sqlx::Error) -> Result<HttpResponse> {
+    Ok(HttpResponse::InternalServerError().json(json!({ "success": false, "error": error.to_string() })))
+}
+
+fn member_skill_from_row(row: &sqlx::postgres::PgRow) -> MemberSkill {
+    MemberSkill {
+        name: row.get("skill_name"),
+        key: row.get("skill_key"),
+        category: row.get("category"),
+        level: row.get("level"),
+        years_experience: row.get("years_experience"),
+        related: row.get("related"),
+    }
+}
+
+fn requirement_from_row(row: &sqlx::postgres::PgRow) -> SkillRequirement {
+    SkillRequirement {
+        name: row.get("skill_name"),
+        key: row.get("skill_key"),
+        min_level: row.get("min_level"),
+        required: row.get("required"),
+    }
+}
+
+/// Skills of every person, or just one when `person_id` is given
+async fn load_member_skills(pool: &Pool<Postgres>, person_id: Option<&str>) -> Result<BTreeMap<String, Vec<MemberSkill>>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT person_id, skill_name, skill_key, category, level, years_experience, related
+        FROM member_skills
+        WHERE $1::text IS NULL OR person_id = $1
+        ORDER BY person_id, level DESC, skill_name
+        "#
+    )
+    .bind(person_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut people: BTreeMap<String, Vec<MemberSkill>> = BTreeMap::new();
+    for row in &rows {
+        people.entry(row.get("person_id")).or_default().push(member_skill_from_row(row));
+    }
+    Ok(people)
+}
+
+/// Requirements of every project, or just one when `project_key` is given
+async fn load_requirements(pool: &Pool<Postgres>, project_key: Option<&str>) -> Result<BTreeMap<String, Vec<SkillRequirement>>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT project_key, skill_name, skill_key, min_level, required
+        FROM project_skills
+        WHERE $1::text IS NULL OR project_key = $1
+        ORDER BY project_key, required DESC, skill_name
+        "#
+    )
+    .bind(project_key)
+    .fetch_all(pool)
+    .await?;
+
+    let mut projects: BTreeMap<String, Vec<SkillRequirement>> = BTreeMap::new();
+    for row in &rows {
+        projects.entry(row.get("project_key")).or_default().push(requirement_from_row(row));
+    }
+    Ok(projects)
+}
+
+pub async fn get_person_skills(data: web::Data<Arc<ApiState>>, path: web::Path<String>) -> Result<HttpResponse> {
+    let person_id = path.into_inner().trim().to_string();
+    match load_member_skills(&data.db, Some(&person_id)).await {
+        Ok(mut people) => Ok(HttpResponse::Ok().json(json!({
+            "success": true,
+            "person_id": person_id,
+            "skills": people.remove(&person_id).unwrap_or_default()
+        }))),
+        Err(e) => server_error(e),
+    }
+}
+
+/// Replace a person's skill profile
+pub async fn put_person_skills(
+    data: web::Data<Arc<ApiState>>,
+    path: web::Path<String>,
+    req: web::Json<PersonSkillsRequest>,
+) -> Result<HttpResponse> {
+    let person_id = path.into_inner();
+    if let Err(e) = validate_id(&person_id, "Person id", 255) {
+        return bad_request(e);
+    }
+    let mut skills = Vec::new();
+    for skill in &req.skills {
+        if let Err(e) = validate_id(&skill.name, "Skill name", 100) {
+            return bad_request(e);
+        }
+        let Some(level) = parse_level(&skill.level) else {
+            return bad_request(format!("{}: level must be one of {}", skill.name, LEVELS.join(", ")));
+        };
+        skills.push((skill, level));
+    }
+
+    let result: Result<(), sqlx::Error> = async {
+        let mut tx = data.db.begin().await?;
+        sqlx::query("DELETE FROM member_skills WHERE person_id = $1")
+            .bind(person_id.trim())
+            .execute(&mut *tx)
+            .await?;
+        for (skill, level) in &skills {
+            sqlx::query(
+                r#"
+                INSERT INTO member_skills (person_id, skill_name, skill_key, category, level, years_experience, related)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+                ON CONFLICT (person_id, skill_key)
+                DO UPDATE SET level = EXCLUDED.level, category = EXCLUDED.category,
+                              years_experience = EXCLUDED.years_experience, related = EXCLUDED.related,
+                              date_modified = CURRENT_TIMESTAMP
+                "#
+            )
+            .bind(person_id.trim())
+            .bind(skill.name.trim())
+            .bind(skill_key(&skill.name))
+            .bind(&skill.category)
+            .bind(level)
+            .bind(skill.experience)
+            .bind(&skill.related)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+    .await;
+
+    match result {
+        Ok(()) => Ok(HttpResponse::Ok().json(json!({ "success": true, "skills": skills.len() }))),
+        Err(e) => server_error(e),
+    }
+}
+
+pub async fn get_project_skills(data: web::Data<Arc<ApiState>>, path: web::Path<String>) -> Result<HttpResponse> {
+    let project_key = path.into_inner();
+    match load_requirements(&data.db, Some(&project_key)).await {
+        Ok(mut projects) => Ok(HttpResponse::Ok().json(json!({
+            "success": true,
+            "project_key": project_key,
+            "skills": projects.remove(&project_key).unwrap_or_default()
+        }))),
+        Err(e) => server_error(e),
+    }
+}
+
+/// Replace a project's skill requirements
+pub async fn put_project_skills(
+    data: web::Data<Arc<ApiState>>,
+    path: web::Path<String>,
+    req: web::Json<ProjectSkillsRequest>,
+) -> Result<HttpResponse> {
+    let project_key = path.into_inner();
+    if let Err(e) = validate_id(&project_key, "Project key", 100) {
+        return bad_request(e);
+    }
+    let mut requirements = Vec::new();
+    for requirement in &req.skills {
+        if let Err(e) = validate_id(&requirement.name, "Skill name", 100) {
+            return bad_request(e);
+        }
+        let level = match requirement.level.as_deref() {
+            None => 2,
+            Some(level) => match parse_level(level) {
+                Some(level) => level,
+                None => return bad_request(format!("{}: level must be one of {}", requirement.name, LEVELS.join(", "))),
+            },
+        };
+        requirements.push((requirement, level));
+    }
+
+    let result: Result<(), sqlx::Error> = async {
+        let mut tx = data.db.begin().await?;
+        sqlx::query("DELETE FROM project_skills WHERE project_key = $1")
+            .bind(&project_key)
+            .execute(&mut *tx)
+            .await?;
+        for (requirement, level) in &requirements {
+            sqlx::query(
+                r#"
+                INSERT INTO project_skills (project_key, skill_name, skill_key, min_level, required)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (project_key, skill_key)
+                DO UPDATE SET min_level = EXCLUDED.min_level, required = EXCLUDED.required,
+                              date_modified = CURRENT_TIMESTAMP
+                "#
+            )
+            .bind(&project_key)
+            .bind(requirement.name.trim())
+            .bind(skill_key(&requirement.name))
+            .bind(level)
+            .bind(requirement.required)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+    .await;
+
+    match result {
+        Ok(()) => Ok(HttpResponse::Ok().json(json!({ "success": true, "skills": requirements.len() }))),
+        Err(e) => server_error(e),
+    }
+}
+
+/// People ranked by how well their skills cover the project's requirements
+pub async fn get_project_matches(
+    data: web::Data<Arc<ApiState>>,
+    path: web::Path<String>,
+    query: web::Query<MatchQuery>,
+) -> Result<HttpResponse> {
+    let project_key = path.into_inner();
+    let requirements = match load_requirements(&data.db, Some(&project_key)).await {
+        Ok(mut projects) => projects.remove(&project_key).unwrap_or_default(),
+        Err(e) => return server_error(e),
+    };
+    if requirements.is_empty() {
+        return Ok(HttpResponse::NotFound().json(json!({
+            "success": false,
+            "error": format!("Project {project_key} has no skill requirements; add them with PUT /api/projects/{project_key}/skills")
+        })));
+    }
+    let people = match load_member_skills(&data.db, None).await {
+        Ok(people) => people,
+        Err(e) => return server_error(e),
+    };
+
+    let mut matches: Vec<(String, SkillMatch)> = people
+        .into_iter()
+        .map(|(person_id, skills)| {
+            let result = score_match(&requirements, &skills);
+            (person_id, result)
+        })
+        .filter(|(_, m)| m.score > 0.0)
+        .collect();
+    sort_matches(&mut matches);
+
+    let total = matches.len();
+    let limit = query.limit.unwrap_or(DEFAULT_MATCH_LIMIT).clamp(1, MAX_MATCH_LIMIT);
+    let results: Vec<serde_json::Value> = matches
+        .into_iter()
+        .take(limit)
+        .map(|(person_id, m)| json!({ "person_id": person_id, "match": m }))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "project_key": project_key,
+        "requirements": requirements,
+        "total": total,
+        "results": results
+    })))
+}
+
+/// Projects ranked by how well the person's skills cover each project's requirements
+pub async fn get_person_matches(
+    data: web::Data<Arc<ApiState>>,
+    path: web::Path<String>,
+    query: web::Query<MatchQuery>,
+) -> Result<HttpResponse> {
+    let person_id = path.into_inner().trim().to_string();
+    let skills = match load_member_skills(&data.db, Some(&person_id)).await {
+        Ok(mut people) => people.remove(&person_id).unwrap_or_default(),
+        Err(e) => return server_error(e),
+    };
+    if skills.is_empty() {
+        return Ok(HttpResponse::NotFound().json(json!({
+            "success": false,
+            "error": format!("{person_id} has no skills yet; add them with PUT /api/people/{person_id}/skills")
+        })));
+    }
+    let projects = match load_requirements(&data.db, None).await {
+        Ok(projects) => projects,
+        Err(e) => return server_error(e),
+    };
+
+    let mut matches: Vec<(String, SkillMatch)> = projects
+        .iter()
+        .map(|(project_key, requirements)| (project_key.clone(), score_match(requirements, &skills)))
+        .filter(|(_, m)| m.score > 0.0)
+        .collect();
+    sort_matches(&mut matches);
+
+    let total = matches.len();
+    let limit = query.limit.unwrap_or(DEFAULT_MATCH_LIMIT).clamp(1, MAX_MATCH_LIMIT);
+    matches.truncate(limit);
+    let names = project_names(&data, matches.iter().map(|(key, _)| key.as_str()).collect()).await;
+
+    let results: Vec<serde_json::Value> = matches
+        .into_iter()
+        .map(|(project_key, m)| json!({
+            "project_key": project_key,
+            "project_name": names.get(&project_key),
+            "match": m
+        }))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "person_id": person_id,
+        "total": total,
+        "results": results
+    })))
+}
+
+/// Names for project keys, from the projects table or the cached workbook
+async fn project_names(data: &ApiState, keys: Vec<&str>) -> HashMap<String, String> {
+    let index = data.project_index.snapshot();
+    let mut names: HashMap<String, String> = index
+        .projects
+        .projects
+        .iter()
+        .filter(|p| keys.contains(&p.key.as_str()))
+        .map(|p| (p.key.clone(), p.project_name.clone()))
+        .collect();
+
+    let rows = sqlx::query("SELECT id::text AS id, name FROM projects WHERE id::text = ANY($1)")
+        .bind(&keys)
+        .fetch_all(&data.db)
+        .await
+        .unwrap_or_else(|e| {
+            log::warn!("Could not load project names: {e}");
+            Vec::new()
+        });
+    // A NULL name leaves the key unnamed rather than failing the whole response
+    for row in &rows {
+        if let Some(name) = row.get::<Option<String>, _>("name") {
+            names.insert(row.get("id"), name);
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn skill(name: &str, level: &str, related: &[&str]) -> MemberSkill {
+        MemberSkill {
+            name: name.to_string(),
+            key: skill_key(name),
+            category: None,
+            level: parse_level(level).unwrap(),
+            years_experience: None,
+            related: related.iter().map(|r| r.to_string()).collect(),
+        }
+    }
+
+    fn requirement(name: &str, level: &str, required: bool) -> SkillRequirement {
+        SkillRequirement { name: name.to_string(), key: skill_key(name), min_level: parse_level(level).unwrap(), required }
+    }
+
+    #[test]
+    fn exact_skills_at_the_minimum_level_score_fully() {
+        let requirements = [requirement("Rust", "intermediate", true), requirement("SQL", "beginner", false)];
+        let result = score_match(&requirements, &[skill("rust", "expert", &[]), skill("sql", "beginner", &[])]);
+
+        assert!((result.score - 1.0).abs() < 1e-9);
+        assert_eq!((result.matched, result.requirements), (2, 2));
+        assert!(result.missing_required.is_empty());
+        assert_eq!(result.surplus, 2);
+        assert_eq!(result.reasons[0].message, "has rust at expert level, needs intermediate");
+    }
+
+    #[test]
+    fn required_skills_count_double_and_shortfalls_scale_down() {
+        let requirements = [requirement("Rust", "advanced", true), requirement("Docker", "intermediate", false)];
+
+        // Beginner against advanced earns a third of the required skill's two-thirds share
+        let result = score_match(&requirements, &[skill("Rust", "beginner", &[])]);
+        assert!((result.score - 2.0 / 9.0).abs() < 1e-9);
+        assert_eq!(result.reasons[0].message, "has Rust at beginner level, below the advanced needed");
+        assert_eq!(result.reasons[1].kind, "missing");
+        assert_eq!(result.reasons[1].message, "no experience with Docker (intermediate)");
+
+        let result = score_match(&requirements, &[skill("Docker", "expert", &[])]);
+        assert!((result.score - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(result.missing_required, vec!["Rust"]);
+        assert_eq!(result.reasons[1].message, "no experience with Rust (advanced), which is required");
+    }
+
+    #[test]
+    fn related_technologies_earn_partial_credit() {
+        let requirements = [requirement("PostgreSQL", "intermediate", true)];
+        let skills = [skill("MySQL", "beginner", &["postgresql"]), skill("SQL Server", "advanced", &[" PostgreSQL "])];
+
+        let result = score_match(&requirements, &skills);
+        // The most proficient related skill is used, at RELATED_CREDIT of its weight
+        assert!((result.score - RELATED_CREDIT).abs() < 1e-9);
+        assert_eq!(result.matched, 1);
+        assert_eq!(result.reasons[0].kind, "related");
+        assert_eq!(result.reasons[0].message, "lists PostgreSQL as related to SQL Server (advanced)");
+    }
+
+    #[test]
+    fn no_requirements_score_zero() {
+        let result = score_match(&[], &[skill("Rust", "expert", &[])]);
+        assert_eq!((result.score, result.matched, result.requirements), (0.0, 0, 0));
+    }
+}
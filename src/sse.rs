@@ -0,0 +1,36 @@
+// src/sse.rs
+use actix_web::{web, HttpResponse};
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+/// Events buffered before a slow client holds up the producer
+const CHANNEL_CAPACITY: usize = 64;
+
+/// Sends named events to an open text/event-stream response
+#[derive(Clone)]
+pub struct EventSender(mpsc::Sender<web::Bytes>);
+
+impl EventSender {
+    /// Fails once the client has closed the stream
+    pub async fn send(&self, event: &str, data: &impl Serialize) -> anyhow::Result<()> {
+        let data = serde_json::to_string(data)?;
+        self.0
+            .send(web::Bytes::from(format!("event: {event}\ndata: {data}\n\n")))
+            .await
+            .map_err(|_| anyhow::anyhow!("Client disconnected from the event stream"))
+    }
+}
+
+/// An event-stream response and the sender that feeds it; the stream ends when every sender is dropped
+pub fn channel() -> (EventSender, HttpResponse) {
+    let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+    let body = futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|bytes| (Ok::<_, actix_web::Error>(bytes), rx))
+    });
+    let response = HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .insert_header(("X-Accel-Buffering", "no"))
+        .streaming(body);
+    (EventSender(tx), response)
+}
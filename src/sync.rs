@@ -0,0 +1,696 @@
+// src/sync.rs
+use actix_web::{web, HttpResponse, Result};
+use anyhow::Context;
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::{Pool, Postgres, Row};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+use crate::csv;
+use crate::dedup::{self, DuplicateIndex, NearDuplicate};
+use crate::import::{self, UpsertOutcome};
+use crate::tagger::Tagger;
+use crate::validation::{CellViolation, TableValidator};
+use crate::ApiState;
+
+/// Only published Google Sheets are fetched, matching the /api/proxy/csv restriction
+pub fn is_google_sheets_url(url: &str) -> bool {
+    url::Url::parse(url)
+        .map(|u| u.scheme() == "https" && u.host_str() == Some("docs.google.com") && u.path().starts_with("/spreadsheets"))
+        .unwrap_or(false)
+}
+
+/// Five-field cron schedule (minute hour day-of-month month day-of-week), evaluated in UTC
+#[derive(Debug, Clone)]
+pub struct CronSchedule {
+    minutes: Vec<u32>,
+    hours: Vec<u32>,
+    days_of_month: Vec<u32>,
+    months: Vec<u32>,
+    days_of_week: Vec<u32>,
+    /// Both day fields were given without "*", so a day matching either one is due
+    either_day: bool,
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let expanded = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            other => other,
+        };
+
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(format!("Expected 5 cron fields, got {}: '{}'", fields.len(), expression));
+        }
+
+        Ok(CronSchedule {
+            minutes: parse_cron_field(fields[0], 0, 59)?,
+            hours: parse_cron_field(fields[1], 0, 23)?,
+            days_of_month: parse_cron_field(fields[2], 1, 31)?,
+            months: parse_cron_field(fields[3], 1, 12)?,
+            // Accept 7 as Sunday, as most cron implementations do
+            days_of_week: parse_cron_field(fields[4], 0, 7)?
+                .into_iter()
+                .map(|d| d % 7)
+                .collect(),
+            either_day: !fields[2].starts_with('*') && !fields[4].starts_with('*'),
+        })
+    }
+
+    pub fn matches(&self, time: &DateTime<Utc>) -> bool {
+        let day_of_month = self.days_of_month.contains(&time.day());
+        let day_of_week = self.days_of_week.contains(&time.weekday().num_days_from_sunday());
+        // As in standard cron, "0 0 1 * 1" runs on the 1st and on every Monday
+        let day = if self.either_day { day_of_month || day_of_week } else { day_of_month && day_of_week };
+
+        day && self.minutes.contains(&time.minute())
+            && self.hours.contains(&time.hour())
+            && self.months.contains(&time.month())
+    }
+}
+
+/// Parse one cron field: "*", "5", "1,15", "9-17", "*/10" or "0-30/5"
+fn parse_cron_field(field: &str, min: u32, max: u32) -> Result<Vec<u32>, String> {
+    let mut values = Vec::new();
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step.parse().map_err(|_| format!("Invalid cron step '{step}'"))?;
+                if step == 0 {
+                    return Err("Cron step must be greater than 0".to_string());
+                }
+                (range, step)
+            }
+            None => (part, 1),
+        };
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (
+                start.parse().map_err(|_| format!("Invalid cron value '{start}'"))?,
+                end.parse().map_err(|_| format!("Invalid cron value '{end}'"))?,
+            )
+        } else {
+            let value: u32 = range.parse().map_err(|_| format!("Invalid cron value '{range}'"))?;
+            (value, if step > 1 { max } else { value })
+        };
+
+        if start < min || end > max || start > end {
+            return Err(format!("Cron range {start}-{end} is outside {min}-{max}"));
+        }
+        values.extend((start..=end).step_by(step as usize));
+    }
+
+    values.sort_unstable();
+    values.dedup();
+    Ok(values)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncSource {
+    pub id: Uuid,
+    pub name: String,
+    pub csv_url: String,
+    pub target_table: String,
+    /// CSV header -> table column; when empty, headers are matched to columns by name
+    pub column_mapping: HashMap<String, String>,
+    pub schedule: String,
+    pub enabled: bool,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub last_status: Option<String>,
+    pub last_summary: Option<serde_json::Value>,
+}
+
+impl SyncSource {
+    fn from_row(row: &sqlx::postgres::PgRow) -> Result<Self, sqlx::Error> {
+        let mapping: Option<serde_json::Value> = row.try_get("column_mapping")?;
+        Ok(SyncSource {
+            id: row.try_get("id")?,
+            name: row.try_get("name")?,
+            csv_url: row.try_get("csv_url")?,
+            target_table: row.try_get("target_table")?,
+            column_mapping: mapping
+                .and_then(|m| serde_json::from_value(m).ok())
+                .unwrap_or_default(),
+            // A missing schedule fails to parse, so the scheduler skips the source with a warning
+            schedule: row.try_get::<Option<String>, _>("schedule")?.unwrap_or_default(),
+            enabled: row.try_get::<Option<bool>, _>("enabled")?.unwrap_or(true),
+            last_run_at: row.try_get::<Option<DateTime<Utc>>, _>("last_run_at")?,
+            last_status: row.try_get::<Option<String>, _>("last_status")?,
+            last_summary: row.try_get::<Option<serde_json::Value>, _>("last_summary")?,
+        })
+    }
+}
+
+const SOURCE_COLUMNS: &str =
+    "id, name, csv_url, target_table, column_mapping, schedule, enabled, last_run_at, last_status, last_summary";
+
+#[derive(Debug, Deserialize)]
+pub struct SyncSourceRequest {
+    pub name: String,
+    pub csv_url: String,
+    pub target_table: String,
+    #[serde(default)]
+    pub column_mapping: HashMap<String, String>,
+    pub schedule: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl SyncSourceRequest {
+    fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("name is required".to_string());
+        }
+        if !is_google_sheets_url(&self.csv_url) {
+            return Err("csv_url must be a published Google Sheets URL".to_string());
+        }
+        CronSchedule::parse(&self.schedule)?;
+        Ok(())
+    }
+
+    /// The target table must exist and have an id column to match rows against
+    async fn check_target_table(&self, pool: &Pool<Postgres>) -> Result<(), String> {
+        let columns = import::load_table_columns(pool, &self.target_table)
+            .await
+            .map_err(|e| format!("Failed to read columns for {}: {e}", self.target_table))?;
+        if columns.is_empty() {
+            return Err(format!("target_table '{}' not found in database", self.target_table));
+        }
+        if !columns.iter().any(|c| c.name == "id") {
+            return Err(format!("target_table '{}' has no id column", self.target_table));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SyncRunSummary {
+    pub rows_fetched: usize,
+    pub inserted: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub near_duplicates: Vec<NearDuplicate>,
+    pub violations: Vec<CellViolation>,
+    pub errors: Vec<String>,
+}
+
+pub async fn list_sources(pool: &Pool<Postgres>) -> Result<Vec<SyncSource>, sqlx::Error> {
+    let rows = sqlx::query(&format!("SELECT {SOURCE_COLUMNS} FROM sync_sources ORDER BY name"))
+        .fetch_all(pool)
+        .await?;
+    rows.iter().map(SyncSource::from_row).collect()
+}
+
+pub async fn get_source(pool: &Pool<Postgres>, id: Uuid) -> Result<Option<SyncSource>, sqlx::Error> {
+    let row = sqlx::query(&format!("SELECT {SOURCE_COLUMNS} FROM sync_sources WHERE id = $1"))
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+    row.as_ref().map(SyncSource::from_row).transpose()
+}
+
+/// Fetch a source's CSV and upsert every row into its target table, logging each change.
+/// Projects written to the projects table are tagged with `tagger`.
+pub async fn run_source(pool: &Pool<Postgres>, source: &SyncSource, tagger: &Tagger) -> anyhow::Result<SyncRunSummary> {
+    if !is_google_sheets_url(&source.csv_url) {
+        anyhow::bail!("Only Google Sheets URLs are allowed");
+    }
+
+    let response = reqwest::Client::new()
+        .get(&source.csv_url)
+        .timeout(std::time::Duration::from_secs(30))
+        .send()
+        .await
+        .context("Failed to fetch CSV")?;
+    if !response.status().is_success() {
+        anyhow::bail!("HTTP {}: the spreadsheet may not be publicly accessible", response.status());
+    }
+    let text = response.text().await.context("Failed to read CSV body")?;
+
+    let mut rows = csv::parse_csv(&text).into_iter();
+    let headers = rows.next().context("The spreadsheet appears to be empty")?;
+
+    let columns = import::load_table_columns(pool, &source.target_table).await?;
+    if columns.is_empty() {
+        anyhow::bail!("Target table '{}' not found in database", source.target_table);
+    }
+    let column_names: Vec<String> = columns.iter().map(|c| c.name.clone()).collect();
+    let mut duplicate_index = DuplicateIndex::load(pool, &source.target_table, dedup::load_rule(&source.target_table)?, &column_names).await?;
+
+    let validator = TableValidator::load(&source.target_table)?;
+    let mut summary = SyncRunSummary::default();
+    let actor = "sheets-sync";
+
+    for (index, row) in rows.enumerate() {
+        summary.rows_fetched += 1;
+
+        let record: HashMap<String, serde_json::Value> = headers
+            .iter()
+            .zip(row.iter())
+            .filter_map(|(header, value)| {
+                let key = if source.column_mapping.is_empty() {
+                    Some(header.clone())
+                } else {
+                    source.column_mapping.get(header.trim()).cloned()
+                };
+                key.map(|k| (k, serde_json::Value::String(value.clone())))
+            })
+            .collect();
+
+        let change = match import::upsert_generic_record(pool, &source.target_table, &columns, &mut duplicate_index, &record, &validator, actor).await {
+            Ok(UpsertOutcome::Inserted { id, values }) => {
+                summary.inserted += 1;
+                Some((id, "inserted", values))
+            }
+            Ok(UpsertOutcome::Updated { id, values }) => {
+                summary.updated += 1;
+                Some((id, "updated", values))
+            }
+            Ok(UpsertOutcome::Unchanged) => {
+                summary.unchanged += 1;
+                None
+            }
+            Ok(UpsertOutcome::NearDuplicate(mut near)) => {
+                near.row = index + 2; // +1 for the header row, +1 for 1-based rows
+                summary.near_duplicates.push(near);
+                None
+            }
+            Ok(UpsertOutcome::Invalid(row_violations)) => {
+                summary.errors.push(format!("Row {}: failed validation", index + 2));
+                summary.violations.extend(row_violations.into_iter().map(|v| CellViolation { row: index + 2, ..v }));
+                None
+            }
+            Err(e) => {
+                summary.errors.push(format!("Row {}: {}", index + 2, e));
+                None
+            }
+        };
+
+        if let (Some((id, _, values)), "projects") = (&change, source.target_table.as_str()) {
+            if let (Ok(project_id), Some(Some(name))) = (Uuid::parse_str(id), values.get("name")) {
+                let description = values.get("description").cloned().flatten();
+                if let Err(e) = crate::project_tags::tag_stored_project(pool, tagger, project_id, name, description.as_deref()).await {
+                    summary.errors.push(format!("Row {}: saved but not tagged: {}", index + 2, e));
+                }
+            }
+        }
+
+        // The row is already saved, so a lost log entry is reported without stopping the run
+        if let Some((id, action, values)) = change {
+            if let Err(e) = log_change(pool, source, &id, action, &values).await {
+                log::error!("Failed to log sync change for '{}' row {}: {e}", source.name, index + 2);
+                summary.errors.push(format!("Row {}: {} but not logged: {}", index + 2, action, e));
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+async fn log_change(
+    pool: &Pool<Postgres>,
+    source: &SyncSource,
+    record_id: &str,
+    action: &str,
+    values: &HashMap<String, Option<String>>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO sync_change_log (source_id, table_name, record_id, action, changes)
+        VALUES ($1, $2, $3, $4, $5)
+        "#
+    )
+    .bind(source.id)
+    .bind(&source.target_table)
+    .bind(record_id)
+    .bind(action)
+    .bind(json!(values))
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Run a source and store the outcome on the source row
+async fn run_and_record(pool: &Pool<Postgres>, source: &SyncSource, tagger: &Tagger) -> Result<serde_json::Value, sqlx::Error> {
+    println!("Running sync source '{}' -> {}", source.name, source.target_table);
+    let (status, summary) = match run_source(pool, source, tagger).await {
+        Ok(summary) if summary.errors.is_empty() => ("success", json!(summary)),
+        Ok(summary) => ("partial", json!(summary)),
+        Err(e) => ("failed", json!({ "error": e.to_string() })),
+    };
+
+    sqlx::query(
+        "UPDATE sync_sources SET last_run_at = CURRENT_TIMESTAMP, last_status = $2, last_summary = $3 WHERE id = $1"
+    )
+    .bind(source.id)
+    .bind(status)
+    .bind(&summary)
+    .execute(pool)
+    .await?;
+
+    Ok(json!({ "status": status, "summary": summary }))
+}
+
+/// Ids of the sources currently running, shared by the scheduler and the run endpoint
+pub type RunningSources = Arc<Mutex<HashSet<Uuid>>>;
+
+/// Marks a source as running until dropped, so a run that fails, panics or is cancelled
+/// doesn't leave the source stuck
+struct RunningGuard {
+    running: RunningSources,
+    id: Uuid,
+}
+
+impl RunningGuard {
+    /// None when the source is already running
+    fn claim(running: &RunningSources, id: Uuid) -> Option<Self> {
+        running.lock().unwrap().insert(id).then(|| RunningGuard { running: running.clone(), id })
+    }
+}
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        self.running.lock().unwrap().remove(&self.id);
+    }
+}
+
+fn start_of_minute(time: DateTime<Utc>) -> DateTime<Utc> {
+    time.with_second(0).and_then(|t| t.with_nanosecond(0)).unwrap_or(time)
+}
+
+/// Check every minute for enabled sources whose cron schedule is due, running each in its own task.
+/// Every minute since the previous check is considered, so a late tick doesn't skip a run, and a
+/// source still running from an earlier minute is not started again.
+pub fn start_scheduler(state: Arc<ApiState>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(60));
+        let mut last_checked = start_of_minute(Utc::now()) - chrono::Duration::minutes(1);
+        loop {
+            interval.tick().await;
+            let now = start_of_minute(Utc::now());
+            let pending: Vec<DateTime<Utc>> = std::iter::successors(Some(now), |t| Some(*t - chrono::Duration::minutes(1)))
+                .take_while(|t| *t > last_checked)
+                .collect();
+            last_checked = now;
+
+            let sources = match list_sources(&state.db).await {
+                Ok(sources) => sources,
+                Err(e) => {
+                    log::debug!("Sync scheduler could not list sources: {e}");
+                    continue;
+                }
+            };
+
+            for source in sources.into_iter().filter(|s| s.enabled) {
+                // The latest pending minute the schedule matches, newest first
+                let due_at = match CronSchedule::parse(&source.schedule) {
+                    Ok(schedule) => pending.iter().find(|t| schedule.matches(t)).copied(),
+                    Err(e) => {
+                        log::warn!("Sync source '{}' has an invalid schedule: {e}", source.name);
+                        None
+                    }
+                };
+                let Some(due_at) = due_at else { continue };
+                if source.last_run_at.is_some_and(|t| t >= due_at) {
+                    continue;
+                }
+                let Some(guard) = RunningGuard::claim(&state.running_syncs, source.id) else {
+                    log::info!("Sync source '{}' is still running; skipping its {due_at} run", source.name);
+                    continue;
+                };
+
+                let state = state.clone();
+                tokio::spawn(async move {
+                    let _guard = guard;
+                    let tagger = state.tagger.read().unwrap().clone();
+                    if let Err(e) = run_and_record(&state.db, &source, &tagger).await {
+                        log::error!("Failed to record sync run for '{}': {e}", source.name);
+                    }
+                    if source.target_table == "projects" {
+                        state.project_index.invalidate_database();
+                    }
+                });
+            }
+        }
+    });
+}
+
+// List registered sync sources
+pub async fn list_sync_sources(data: web::Data<Arc<ApiState>>) -> Result<HttpResponse> {
+    match list_sources(&data.db).await {
+        Ok(sources) => Ok(HttpResponse::Ok().json(json!({ "success": true, "data": sources }))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({ "success": false, "error": e.to_string() }))),
+    }
+}
+
+// Get a single sync source
+pub async fn get_sync_source(data: web::Data<Arc<ApiState>>, path: web::Path<Uuid>) -> Result<HttpResponse> {
+    match get_source(&data.db, path.into_inner()).await {
+        Ok(Some(source)) => Ok(HttpResponse::Ok().json(json!({ "success": true, "data": source }))),
+        Ok(None) => Ok(HttpResponse::NotFound().json(json!({ "success": false, "error": "Sync source not found" }))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({ "success": false, "error": e.to_string() }))),
+    }
+}
+
+// Register a new sync source
+pub async fn create_sync_source(
+    data: web::Data<Arc<ApiState>>,
+    req: web::Json<SyncSourceRequest>,
+) -> Result<HttpResponse> {
+    if let Err(e) = req.validate() {
+        return Ok(HttpResponse::BadRequest().json(json!({ "success": false, "error": e })));
+    }
+    if let Err(e) = req.check_target_table(&data.db).await {
+        return Ok(HttpResponse::BadRequest().json(json!({ "success": false, "error": e })));
+    }
+
+    let result = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        INSERT INTO sync_sources (name, csv_url, target_table, column_mapping, schedule, enabled)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id
+        "#
+    )
+    .bind(&req.name)
+    .bind(&req.csv_url)
+    .bind(&req.target_table)
+    .bind(json!(req.column_mapping))
+    .bind(&req.schedule)
+    .bind(req.enabled)
+    .fetch_one(&data.db)
+    .await;
+
+    match result {
+        Ok(id) => Ok(HttpResponse::Created().json(json!({ "success": true, "id": id }))),
+        Err(e) => Ok(HttpResponse::BadRequest().json(json!({ "success": false, "error": e.to_string() }))),
+    }
+}
+
+// Update a sync source
+pub async fn update_sync_source(
+    data: web::Data<Arc<ApiState>>,
+    path: web::Path<Uuid>,
+    req: web::Json<SyncSourceRequest>,
+) -> Result<HttpResponse> {
+    if let Err(e) = req.validate() {
+        return Ok(HttpResponse::BadRequest().json(json!({ "success": false, "error": e })));
+    }
+    if let Err(e) = req.check_target_table(&data.db).await {
+        return Ok(HttpResponse::BadRequest().json(json!({ "success": false, "error": e })));
+    }
+
+    let result = sqlx::query(
+        r#"
+        UPDATE sync_sources
+        SET name = $2, csv_url = $3, target_table = $4, column_mapping = $5, schedule = $6,
+            enabled = $7, date_modified = CURRENT_TIMESTAMP
+        WHERE id = $1
+        "#
+    )
+    .bind(path.into_inner())
+    .bind(&req.name)
+    .bind(&req.csv_url)
+    .bind(&req.target_table)
+    .bind(json!(req.column_mapping))
+    .bind(&req.schedule)
+    .bind(req.enabled)
+    .execute(&data.db)
+    .await;
+
+    match result {
+        Ok(r) if r.rows_affected() == 0 => Ok(HttpResponse::NotFound().json(json!({ "success": false, "error": "Sync source not found" }))),
+        Ok(_) => Ok(HttpResponse::Ok().json(json!({ "success": true }))),
+        Err(e) => Ok(HttpResponse::BadRequest().json(json!({ "success": false, "error": e.to_string() }))),
+    }
+}
+
+// Delete a sync source and its change log
+pub async fn delete_sync_source(data: web::Data<Arc<ApiState>>, path: web::Path<Uuid>) -> Result<HttpResponse> {
+    match sqlx::query("DELETE FROM sync_sources WHERE id = $1")
+        .bind(path.into_inner())
+        .execute(&data.db)
+        .await
+    {
+        Ok(r) if r.rows_affected() == 0 => Ok(HttpResponse::NotFound().json(json!({ "success": false, "error": "Sync source not found" }))),
+        Ok(_) => Ok(HttpResponse::Ok().json(json!({ "success": true }))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({ "success": false, "error": e.to_string() }))),
+    }
+}
+
+// Run a sync source immediately, unless it is already running
+pub async fn run_sync_source(data: web::Data<Arc<ApiState>>, path: web::Path<Uuid>) -> Result<HttpResponse> {
+    let source = match get_source(&data.db, path.into_inner()).await {
+        Ok(Some(source)) => source,
+        Ok(None) => return Ok(HttpResponse::NotFound().json(json!({ "success": false, "error": "Sync source not found" }))),
+        Err(e) => return Ok(HttpResponse::InternalServerError().json(json!({ "success": false, "error": e.to_string() }))),
+    };
+
+    let Some(_guard) = RunningGuard::claim(&data.running_syncs, source.id) else {
+        return Ok(HttpResponse::Conflict().json(json!({
+            "success": false,
+            "error": format!("Sync source '{}' is already running", source.name)
+        })));
+    };
+
+    let tagger = data.tagger.read().unwrap().clone();
+    let result = run_and_record(&data.db, &source, &tagger).await;
+    if source.target_table == "projects" {
+        data.project_index.invalidate_database();
+    }
+    match result {
+        Ok(result) => Ok(HttpResponse::Ok().json(json!({
+            "success": result["status"] != "failed",
+            "data": result
+        }))),
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({ "success": false, "error": e.to_string() }))),
+    }
+}
+
+// Recent changes applied by a sync source
+pub async fn get_sync_source_log(
+    data: web::Data<Arc<ApiState>>,
+    path: web::Path<Uuid>,
+    query: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse> {
+    let limit: i64 = query.get("limit").and_then(|l| l.parse().ok()).unwrap_or(100).clamp(1, 1000);
+
+    let rows = sqlx::query(
+        r#"
+        SELECT record_id, action, changes, date_entered
+        FROM sync_change_log
+        WHERE source_id = $1
+        ORDER BY date_entered DESC
+        LIMIT $2
+        "#
+    )
+    .bind(path.into_inner())
+    .bind(limit)
+    .fetch_all(&data.db)
+    .await;
+
+    match rows {
+        Ok(rows) => {
+            let entries: Vec<serde_json::Value> = rows
+                .iter()
+                .map(|row| json!({
+                    "record_id": row.get::<String, _>("record_id"),
+                    "action": row.get::<String, _>("action"),
+                    "changes": row.get::<Option<serde_json::Value>, _>("changes"),
+                    "date": row.get::<DateTime<Utc>, _>("date_entered"),
+                }))
+                .collect();
+            Ok(HttpResponse::Ok().json(json!({ "success": true, "data": entries })))
+        }
+        Err(e) => Ok(HttpResponse::InternalServerError().json(json!({ "success": false, "error": e.to_string() }))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, m, d, h, min, 0).unwrap()
+    }
+
+    #[test]
+    fn parse_cron_field_forms() {
+        assert_eq!(parse_cron_field("*", 0, 5), Ok(vec![0, 1, 2, 3, 4, 5]));
+        assert_eq!(parse_cron_field("5", 0, 59), Ok(vec![5]));
+        assert_eq!(parse_cron_field("15,1,15", 1, 31), Ok(vec![1, 15]));
+        assert_eq!(parse_cron_field("9-12", 0, 23), Ok(vec![9, 10, 11, 12]));
+        assert_eq!(parse_cron_field("*/20", 0, 59), Ok(vec![0, 20, 40]));
+        assert_eq!(parse_cron_field("0-30/10", 0, 59), Ok(vec![0, 10, 20, 30]));
+        assert_eq!(parse_cron_field("50/5", 0, 59), Ok(vec![50, 55]));
+    }
+
+    #[test]
+    fn parse_cron_field_rejects_bad_input() {
+        assert!(parse_cron_field("60", 0, 59).is_err());
+        assert!(parse_cron_field("0", 1, 31).is_err());
+        assert!(parse_cron_field("10-5", 0, 59).is_err());
+        assert!(parse_cron_field("*/0", 0, 59).is_err());
+        assert!(parse_cron_field("a", 0, 59).is_err());
+        assert!(CronSchedule::parse("0 0 * *").is_err());
+    }
+
+    #[test]
+    fn matches_time_fields() {
+        let schedule = CronSchedule::parse("*/15 9-17 * * *").unwrap();
+        assert!(schedule.matches(&at(2024, 3, 4, 9, 45)));
+        assert!(!schedule.matches(&at(2024, 3, 4, 9, 46)));
+        assert!(!schedule.matches(&at(2024, 3, 4, 18, 0)));
+
+        let daily = CronSchedule::parse("@daily").unwrap();
+        assert!(daily.matches(&at(2024, 3, 4, 0, 0)));
+        assert!(!daily.matches(&at(2024, 3, 4, 0, 1)));
+    }
+
+    #[test]
+    fn matches_either_day_when_both_are_restricted() {
+        // 2024-03-01 is a Friday, 2024-03-04 a Monday, 2024-03-05 a Tuesday
+        let schedule = CronSchedule::parse("0 0 1 * 1").unwrap();
+        assert!(schedule.matches(&at(2024, 3, 1, 0, 0)));
+        assert!(schedule.matches(&at(2024, 3, 4, 0, 0)));
+        assert!(!schedule.matches(&at(2024, 3, 5, 0, 0)));
+    }
+
+    #[test]
+    fn matches_both_days_when_one_is_a_wildcard() {
+        let weekdays = CronSchedule::parse("0 0 * * 1-5").unwrap();
+        assert!(weekdays.matches(&at(2024, 3, 4, 0, 0)));
+        assert!(!weekdays.matches(&at(2024, 3, 2, 0, 0)));
+
+        let sundays = CronSchedule::parse("0 0 */2 * 7").unwrap();
+        assert!(sundays.matches(&at(2024, 3, 3, 0, 0)));
+        assert!(!sundays.matches(&at(2024, 3, 10, 0, 0)));
+        assert!(!sundays.matches(&at(2024, 3, 5, 0, 0)));
+    }
+
+    #[test]
+    fn a_running_source_cannot_be_claimed_again_until_released() {
+        let running = RunningSources::default();
+        let id = Uuid::new_v4();
+
+        let guard = RunningGuard::claim(&running, id).unwrap();
+        assert!(RunningGuard::claim(&running, id).is_none());
+        assert!(RunningGuard::claim(&running, Uuid::new_v4()).is_some());
+        drop(guard);
+        assert!(RunningGuard::claim(&running, id).is_some());
+    }
+}
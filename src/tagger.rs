@@ -0,0 +1,154 @@
+// src/tagger.rs
+// Dictionary tagging of projects. This file only depends on crate::recommendations, so the
+// recommendation quality test can include it and tag projects exactly as the server does.
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+
+use crate::recommendations::{mentions, Project};
+
+/// The server's tagger; the watcher swaps in a new one when the dictionaries file changes
+pub type SharedTagger = Arc<RwLock<Arc<Tagger>>>;
+
+/// Location of the editable tag dictionaries
+pub const TAG_DICTIONARIES_PATH: &str = "config/tag_dictionaries.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TagDictionaries {
+    /// Tag -> words or phrases that add it when found in a project's name or description
+    #[serde(default)]
+    pub keywords: BTreeMap<String, Vec<String>>,
+    /// Project fields whose value becomes a tag as-is, e.g. "naics_sector" or "region"
+    #[serde(default)]
+    pub attributes: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Tagger {
+    dictionaries: TagDictionaries,
+}
+
+impl Tagger {
+    /// Load the dictionaries from the dictionaries file
+    pub fn load() -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(TAG_DICTIONARIES_PATH)
+            .map_err(|e| anyhow::anyhow!("Failed to read {TAG_DICTIONARIES_PATH}: {e}"))?;
+        let dictionaries = serde_json::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("Failed to parse {TAG_DICTIONARIES_PATH}: {e}"))?;
+        Ok(Tagger { dictionaries })
+    }
+
+    /// Tags for a record: configured attribute values (lowercased) plus keyword tags found in `text`
+    pub fn tags(&self, attributes: &HashMap<&str, &str>, text: &str) -> Vec<String> {
+        let mut tags: Vec<String> = self
+            .dictionaries
+            .attributes
+            .iter()
+            .filter_map(|a| attributes.get(a.as_str()))
+            .map(|v| v.trim().to_lowercase())
+            .filter(|v| !v.is_empty())
+            .collect();
+
+        let text = text.to_lowercase();
+        for (tag, words) in &self.dictionaries.keywords {
+            if words.iter().any(|w| mentions(&text, w)) {
+                tags.push(tag.clone());
+            }
+        }
+
+        tags.sort();
+        tags.dedup();
+        tags
+    }
+
+    /// Add generated tags to a loaded project, keeping the tags it already has
+    pub fn tag_project(&self, project: &mut Project) {
+        let attributes = HashMap::from([
+            ("naics_sector", project.naics_sector.as_str()),
+            ("department", project.department.as_str()),
+            ("region", project.region.as_str()),
+            ("framework", project.framework.as_str()),
+            ("country", project.country.as_str()),
+            ("project_type", project.project_type.as_str()),
+        ]);
+        let text = format!("{} {}", project.project_name, project.project_description);
+        for tag in self.tags(&attributes, &text) {
+            if !project.tags.iter().any(|t| t.eq_ignore_ascii_case(&tag)) {
+                project.tags.push(tag);
+            }
+        }
+    }
+}
+
+/// Tags a previous sync attached that the source no longer lists (compared case-insensitively)
+pub fn dropped_tags(synced: &[String], current: &[String]) -> Vec<String> {
+    synced
+        .iter()
+        .filter(|old| !current.iter().any(|t| t.eq_ignore_ascii_case(old)))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tagger() -> Tagger {
+        Tagger {
+            dictionaries: TagDictionaries {
+                keywords: BTreeMap::from([
+                    ("solar".to_string(), vec!["solar".to_string(), "photovoltaic".to_string()]),
+                    ("ai".to_string(), vec!["AI".to_string(), "machine learning".to_string()]),
+                ]),
+                attributes: vec!["region".to_string(), "naics_sector".to_string()],
+            },
+        }
+    }
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn keywords_match_whole_words_and_phrases() {
+        let tagger = tagger();
+        let none = HashMap::new();
+
+        assert_eq!(tagger.tags(&none, "A PHOTOVOLTAIC plant"), strings(&["solar"]));
+        assert_eq!(tagger.tags(&none, "Machine Learning for crops, solar-powered"), strings(&["ai", "solar"]));
+        // "AI" inside another word is not a mention
+        assert!(tagger.tags(&none, "Training for Haiti and Spain").is_empty());
+        assert!(tagger.tags(&none, "machine learner").is_empty());
+    }
+
+    #[test]
+    fn configured_attributes_become_lowercased_tags() {
+        let tagger = tagger();
+        let attributes = HashMap::from([("region", " Latin America "), ("naics_sector", ""), ("department", "Energy")]);
+
+        assert_eq!(tagger.tags(&attributes, "solar and solar"), strings(&["latin america", "solar"]));
+    }
+
+    #[test]
+    fn tag_project_keeps_existing_tags() {
+        let mut project: Project = serde_json::from_value(serde_json::json!({
+            "id": 1.0, "project_name": "Solar microgrid", "project_description": "", "country": "",
+            "naics_sector": "Utilities", "committed": 0.0, "department": "", "project_type": "",
+            "region": "", "fiscal_year": "", "project_number": "", "framework": "",
+            "project_profile_url": "", "tags": ["Solar", "community"], "starred": false, "comment": ""
+        }))
+        .unwrap();
+        tagger().tag_project(&mut project);
+
+        assert_eq!(project.tags, strings(&["Solar", "community", "utilities"]));
+    }
+
+    #[test]
+    fn dropped_tags_are_synced_tags_no_longer_listed() {
+        let synced = strings(&["Civic Tech", "health", "education"]);
+
+        assert_eq!(dropped_tags(&synced, &strings(&["civic tech", "Education"])), strings(&["health"]));
+        assert_eq!(dropped_tags(&synced, &[]), synced);
+        assert!(dropped_tags(&[], &strings(&["health"])).is_empty());
+    }
+}
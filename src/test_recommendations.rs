@@ -1,7 +1,18 @@
 // Test the recommendations functionality
 use std::path::Path;
+#[allow(dead_code)]
 mod recommendations;
 
+fn rank(
+    projects: Vec<recommendations::Project>,
+    request: &recommendations::RecommendationRequest,
+    mappings: &recommendations::PreferenceMappings,
+) -> recommendations::RecommendationResponse {
+    let set = recommendations::ProjectSet::new(projects);
+    let scored = recommendations::score_projects(&set, request, mappings);
+    recommendations::paginate(scored, request.limit, request.offset)
+}
+
 fn main() {
     println!("Testing Recommendations Feature");
     println!("================================");
@@ -25,20 +36,31 @@ fn main() {
     
     println!("🔍 Testing with preferences: {:?}", test_preferences);
     
-    match recommendations::get_recommendations(&test_preferences, excel_path) {
-        Ok(projects) => {
-            println!("✅ Successfully loaded {} projects", projects.len());
+    let request = recommendations::RecommendationRequest {
+        preferences: recommendations::Preferences::List(test_preferences),
+        ..Default::default()
+    };
+    
+    let mappings = recommendations::load_mappings_or_default();
+    
+    match recommendations::load_projects(excel_path).map(|projects| rank(projects, &request, &mappings)) {
+        Ok(response) => {
+            println!("✅ {} matching projects, showing {}", response.total, response.results.len());
             
-            for (i, project) in projects.iter().enumerate() {
-                println!("\n📋 Project {}: {}", i + 1, project.project_name);
+            for (i, scored) in response.results.iter().enumerate() {
+                let project = &scored.project;
+                println!("\n📋 Project {}: {} (score {:.1})", i + 1, project.project_name, scored.score);
                 println!("   Description: {}", project.project_description);
                 println!("   Department: {}", project.department);
                 println!("   NAICS Sector: {}", project.naics_sector);
                 println!("   Committed: ${}", project.committed);
                 println!("   Country: {}", project.country);
+                for reason in &scored.reasons {
+                    println!("   + {:.1} {}", reason.contribution, reason.message);
+                }
             }
             
-            if projects.is_empty() {
+            if response.results.is_empty() {
                 println!("⚠️  No matching projects found for the given preferences");
                 println!("💡 Try different preferences or check Excel file content");
             }
@@ -47,4 +69,24 @@ fn main() {
             println!("❌ Error loading recommendations: {}", e);
         }
     }
+    
+    // Free-text matching only
+    let text = "solar power and clean energy for rural communities";
+    println!("\n🔍 Testing with free text: {:?}", text);
+    
+    let request = recommendations::RecommendationRequest {
+        text: Some(text.to_string()),
+        ..Default::default()
+    };
+    
+    if let Ok(projects) = recommendations::load_projects(excel_path) {
+        let response = rank(projects, &request, &mappings);
+        println!("✅ {} matching projects, showing {}", response.total, response.results.len());
+        for (i, scored) in response.results.iter().enumerate() {
+            println!("\n📋 Project {}: {} (score {:.2})", i + 1, scored.project.project_name, scored.score);
+            for reason in &scored.reasons {
+                println!("   + {:.2} {}", reason.contribution, reason.message);
+            }
+        }
+    }
 }
\ No newline at end of file
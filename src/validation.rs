@@ -0,0 +1,334 @@
+// src/validation.rs
+use anyhow::Context;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Location of the per-table field validation rules
+pub const VALIDATION_RULES_PATH: &str = "config/validation_rules.json";
+
+/// Value formats that can be checked without a custom pattern
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldFormat {
+    Url,
+    Email,
+    /// Amounts such as "1200000", "1,200,000" or "$1,200,000.50"
+    Currency,
+    /// A four digit year, optionally written as "FY2024" or "2023-2024"
+    Year,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FieldRule {
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub max_length: Option<usize>,
+    #[serde(default)]
+    pub min: Option<f64>,
+    #[serde(default)]
+    pub max: Option<f64>,
+    #[serde(default)]
+    pub pattern: Option<String>,
+    /// Allowed values, compared case-insensitively
+    #[serde(default)]
+    pub allowed: Option<Vec<String>>,
+    #[serde(default)]
+    pub format: Option<FieldFormat>,
+}
+
+/// A single rule violation for one cell of an import
+#[derive(Debug, Clone, Serialize)]
+pub struct CellViolation {
+    pub row: usize,
+    pub column: String,
+    pub value: Option<String>,
+    pub rule: String,
+    pub message: String,
+}
+
+/// Compiled validation rules for one table
+#[derive(Debug)]
+pub struct TableValidator {
+    rules: Vec<(String, FieldRule, Option<Regex>)>,
+}
+
+impl TableValidator {
+    /// Load the rules for a table from the rules file; a table without an entry has no rules
+    pub fn load(table_name: &str) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(VALIDATION_RULES_PATH)
+            .with_context(|| format!("Failed to read {VALIDATION_RULES_PATH}"))?;
+        let mut tables: HashMap<String, HashMap<String, FieldRule>> = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {VALIDATION_RULES_PATH}"))?;
+
+        Ok(Self::new(tables.remove(table_name).unwrap_or_default()))
+    }
+
+    pub fn new(rules: HashMap<String, FieldRule>) -> Self {
+        let mut compiled: Vec<(String, FieldRule, Option<Regex>)> = rules
+            .into_iter()
+            .map(|(field, rule)| {
+                let pattern = rule.pattern.as_deref().and_then(|p| {
+                    Regex::new(p)
+                        .map_err(|e| log::warn!("Invalid validation pattern for {field}: {e}"))
+                        .ok()
+                });
+                (field, rule, pattern)
+            })
+            .collect();
+        compiled.sort_by(|a, b| a.0.cmp(&b.0));
+        TableValidator { rules: compiled }
+    }
+
+    /// Validate one record; `values` holds field name -> text value (None when empty)
+    pub fn validate(&self, row: usize, values: &HashMap<String, Option<String>>) -> Vec<CellViolation> {
+        let mut violations = Vec::new();
+
+        for (field, rule, pattern) in &self.rules {
+            let value = values.get(field).cloned().flatten().filter(|v| !v.trim().is_empty());
+            let mut violation = |rule_name: &str, message: String| {
+                violations.push(CellViolation {
+                    row,
+                    column: field.clone(),
+                    value: value.clone(),
+                    rule: rule_name.to_string(),
+                    message,
+                });
+            };
+
+            let Some(text) = value.as_deref().map(str::trim) else {
+                if rule.required {
+                    violation("required", format!("{field} is required"));
+                }
+                continue;
+            };
+
+            if let Some(max) = rule.max_length {
+                let length = text.chars().count();
+                if length > max {
+                    violation("max_length", format!("{field} is {length} characters; the maximum is {max}"));
+                }
+            }
+
+            if let Some(allowed) = &rule.allowed {
+                if !allowed.iter().any(|a| a.eq_ignore_ascii_case(text)) {
+                    violation("allowed", format!("{field} must be one of: {}", allowed.join(", ")));
+                }
+            }
+
+            if let Some(regex) = pattern {
+                if !regex.is_match(text) {
+                    violation("pattern", format!("{field} does not match {}", regex.as_str()));
+                }
+            }
+
+            let number = match rule.format {
+                Some(FieldFormat::Currency) => match parse_currency(text) {
+                    Some(n) => Some(n),
+                    None => {
+                        violation("currency", format!("{field} '{text}' is not a valid amount"));
+                        None
+                    }
+                },
+                _ if rule.min.is_some() || rule.max.is_some() => match parse_currency(text) {
+                    Some(n) => Some(n),
+                    None => {
+                        violation("number", format!("{field} '{text}' is not a number"));
+                        None
+                    }
+                },
+                _ => None,
+            };
+
+            if let Some(n) = number {
+                let out_of_range = match (rule.min, rule.max) {
+                    (Some(min), Some(max)) if n < min || n > max => Some(format!("between {min} and {max}")),
+                    (Some(min), None) if n < min => Some(format!("at least {min}")),
+                    (None, Some(max)) if n > max => Some(format!("at most {max}")),
+                    _ => None,
+                };
+                if let Some(range) = out_of_range {
+                    violation("range", format!("{field} must be {range}"));
+                }
+            }
+
+            match rule.format {
+                Some(FieldFormat::Url) if !is_valid_url(text) => {
+                    violation("url", format!("{field} '{text}' is not a valid http(s) URL"));
+                }
+                Some(FieldFormat::Email) if !is_valid_email(text) => {
+                    violation("email", format!("{field} '{text}' is not a valid email address"));
+                }
+                Some(FieldFormat::Year) if !is_valid_year(text) => {
+                    violation("year", format!("{field} '{text}' is not a valid year"));
+                }
+                _ => {}
+            }
+        }
+
+        violations
+    }
+}
+
+/// Parse amounts written as "1200000", "1,200,000", "$1,200,000.50" or "(500)"
+pub fn parse_currency(raw: &str) -> Option<f64> {
+    let trimmed = raw.trim();
+    let (negative, body) = match trimmed.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+        Some(inner) => (true, inner),
+        None => (false, trimmed),
+    };
+    let cleaned: String = body.chars().filter(|c| !matches!(c, '$' | ',' | ' ')).collect();
+    cleaned
+        .parse::<f64>()
+        .ok()
+        .filter(|n| n.is_finite())
+        .map(|n| if negative { -n } else { n })
+}
+
+fn is_valid_url(value: &str) -> bool {
+    url::Url::parse(value)
+        .map(|u| matches!(u.scheme(), "http" | "https") && u.host_str().is_some())
+        .unwrap_or(false)
+}
+
+fn is_valid_email(value: &str) -> bool {
+    match value.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && !value.contains(char::is_whitespace)
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !domain.contains('@')
+        }
+        None => false,
+    }
+}
+
+fn is_valid_year(value: &str) -> bool {
+    let digits = value.trim_start_matches("FY").trim_start_matches("fy").trim();
+    let valid = |y: &str| y.len() == 4 && y.parse::<u32>().is_ok_and(|y| (1900..=2100).contains(&y));
+    match digits.split_once('-') {
+        Some((start, end)) => valid(start.trim()) && valid(end.trim()),
+        None => valid(digits) || digits.parse::<f64>().is_ok_and(|y| y.fract() == 0.0 && valid(&(y as u32).to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validator(field: &str, rule: FieldRule) -> TableValidator {
+        TableValidator::new(HashMap::from([(field.to_string(), rule)]))
+    }
+
+    /// The rule names a single value violates
+    fn broken(validator: &TableValidator, field: &str, value: Option<&str>) -> Vec<String> {
+        let values = HashMap::from([(field.to_string(), value.map(String::from))]);
+        validator.validate(1, &values).into_iter().map(|v| v.rule).collect()
+    }
+
+    fn format(format: FieldFormat) -> FieldRule {
+        FieldRule { format: Some(format), ..Default::default() }
+    }
+
+    #[test]
+    fn required_rejects_missing_and_blank_values() {
+        let v = validator("name", FieldRule { required: true, ..Default::default() });
+        assert_eq!(broken(&v, "name", None), vec!["required"]);
+        assert_eq!(broken(&v, "name", Some("   ")), vec!["required"]);
+        assert!(broken(&v, "name", Some("Acme")).is_empty());
+        assert_eq!(broken(&v, "other", Some("Acme")), vec!["required"]);
+
+        // Optional fields skip every other check when empty
+        let optional = validator("website", format(FieldFormat::Url));
+        assert!(broken(&optional, "website", None).is_empty());
+    }
+
+    #[test]
+    fn max_length_counts_characters() {
+        let v = validator("name", FieldRule { max_length: Some(5), ..Default::default() });
+        assert!(broken(&v, "name", Some("héllo")).is_empty());
+        assert!(broken(&v, "name", Some("  abc  ")).is_empty());
+        assert_eq!(broken(&v, "name", Some("abcdef")), vec!["max_length"]);
+    }
+
+    #[test]
+    fn allowed_values_ignore_case() {
+        let v = validator("status", FieldRule { allowed: Some(vec!["Active".into(), "Planning".into()]), ..Default::default() });
+        assert!(broken(&v, "status", Some("active")).is_empty());
+        assert!(broken(&v, "status", Some("PLANNING ")).is_empty());
+        assert_eq!(broken(&v, "status", Some("Done")), vec!["allowed"]);
+    }
+
+    #[test]
+    fn range_checks_numbers() {
+        let v = validator("score", FieldRule { min: Some(1.0), max: Some(5.0), ..Default::default() });
+        assert!(broken(&v, "score", Some("1")).is_empty());
+        assert!(broken(&v, "score", Some("5")).is_empty());
+        assert_eq!(broken(&v, "score", Some("0.5")), vec!["range"]);
+        assert_eq!(broken(&v, "score", Some("6")), vec!["range"]);
+        assert_eq!(broken(&v, "score", Some("high")), vec!["number"]);
+
+        let at_least = validator("committed", FieldRule { min: Some(0.0), format: Some(FieldFormat::Currency), ..Default::default() });
+        assert!(broken(&at_least, "committed", Some("$1,000")).is_empty());
+        assert_eq!(broken(&at_least, "committed", Some("(500)")), vec!["range"]);
+    }
+
+    #[test]
+    fn currency_amounts() {
+        assert_eq!(parse_currency("1200000"), Some(1_200_000.0));
+        assert_eq!(parse_currency(" 1,200,000 "), Some(1_200_000.0));
+        assert_eq!(parse_currency("$1,200,000.50"), Some(1_200_000.5));
+        assert_eq!(parse_currency("(500)"), Some(-500.0));
+        assert_eq!(parse_currency("-42"), Some(-42.0));
+        assert_eq!(parse_currency("12 million"), None);
+        assert_eq!(parse_currency(""), None);
+        assert_eq!(parse_currency("inf"), None);
+
+        let v = validator("committed", format(FieldFormat::Currency));
+        assert!(broken(&v, "committed", Some("$2,500")).is_empty());
+        assert_eq!(broken(&v, "committed", Some("about $2k")), vec!["currency"]);
+    }
+
+    #[test]
+    fn url_format_needs_http_and_a_host() {
+        let v = validator("website", format(FieldFormat::Url));
+        assert!(broken(&v, "website", Some("https://example.org/path?q=1")).is_empty());
+        assert!(broken(&v, "website", Some("http://localhost:8080")).is_empty());
+        for bad in ["example.org", "ftp://example.org", "mailto:a@example.org", "https://"] {
+            assert_eq!(broken(&v, "website", Some(bad)), vec!["url"], "{bad}");
+        }
+    }
+
+    #[test]
+    fn email_format() {
+        let v = validator("email", format(FieldFormat::Email));
+        assert!(broken(&v, "email", Some("ana@example.org")).is_empty());
+        assert!(broken(&v, "email", Some("first.last+tag@mail.example.co")).is_empty());
+        for bad in ["ana", "@example.org", "ana@example", "ana@.org", "ana@example.", "a na@example.org", "ana@b@example.org"] {
+            assert_eq!(broken(&v, "email", Some(bad)), vec!["email"], "{bad}");
+        }
+    }
+
+    #[test]
+    fn year_format() {
+        let v = validator("fiscal_year", format(FieldFormat::Year));
+        for good in ["2024", "FY2024", "fy 2023", "2023-2024", "2024.0"] {
+            assert!(broken(&v, "fiscal_year", Some(good)).is_empty(), "{good}");
+        }
+        for bad in ["24", "1850", "2024.5", "FY24", "2023-", "next year"] {
+            assert_eq!(broken(&v, "fiscal_year", Some(bad)), vec!["year"], "{bad}");
+        }
+    }
+
+    #[test]
+    fn violations_report_row_column_and_value() {
+        let v = validator("email", format(FieldFormat::Email));
+        let values = HashMap::from([("email".to_string(), Some("nope".to_string()))]);
+        let violation = &v.validate(7, &values)[0];
+        assert_eq!((violation.row, violation.column.as_str(), violation.value.as_deref()), (7, "email", Some("nope")));
+        assert_eq!(violation.message, "email 'nope' is not a valid email address");
+    }
+}
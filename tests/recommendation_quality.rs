@@ -0,0 +1,174 @@
+// Recommendation quality against a labeled fixture set
+//
+//   cargo test --test recommendation_quality                       # fails on a regression or a missed floor
+//   UPDATE_BASELINE=1 cargo test --test recommendation_quality     # accept the current metrics
+//
+// Projects are tagged with the configured dictionaries exactly as the server's project index does,
+// so dictionary and mapping changes are evaluated together.
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+#[allow(dead_code)]
+#[path = "../src/recommendations.rs"]
+mod recommendations;
+#[allow(dead_code)]
+#[path = "../src/tagger.rs"]
+mod tagger;
+
+const LABELS_PATH: &str = "preferences/projects/evaluation/labels.json";
+const BASELINE_PATH: &str = "preferences/projects/evaluation/baseline.json";
+
+/// How far a metric may drop below the baseline before it counts as a regression
+const TOLERANCE: f64 = 0.001;
+
+/// Every profile must find at least half of its labeled projects in the top k
+const MIN_RECALL: f64 = 0.5;
+/// Every profile must reach half of the best attainable ranking quality
+const MIN_NDCG: f64 = 0.5;
+/// Precision is judged against what the labels allow: a profile with two relevant projects can
+/// reach at most 2/k, so raw P@k would punish small label sets rather than bad rankings
+const MIN_ATTAINABLE_PRECISION: f64 = 0.5;
+/// Averaged over all profiles, relevant projects must sit near the top, not just inside the top k
+const MIN_MEAN_NDCG: f64 = 0.8;
+
+#[derive(Deserialize)]
+struct Labels {
+    workbook: String,
+    k: usize,
+    profiles: Vec<Profile>,
+}
+
+#[derive(Deserialize)]
+struct Profile {
+    name: String,
+    request: recommendations::RecommendationRequest,
+    /// Project numbers a good ranking should put near the top
+    relevant: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+struct Metrics {
+    precision: f64,
+    recall: f64,
+    ndcg: f64,
+}
+
+impl Metrics {
+    fn named(&self) -> [(&'static str, f64); 3] {
+        [("precision", self.precision), ("recall", self.recall), ("ndcg", self.ndcg)]
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Baseline {
+    k: usize,
+    mean: Metrics,
+    profiles: BTreeMap<String, Metrics>,
+}
+
+/// Precision@k, recall@k and nDCG@k with binary relevance
+fn evaluate(ranked: &[String], relevant: &HashSet<&str>, k: usize) -> Metrics {
+    let top = &ranked[..ranked.len().min(k)];
+    let hits = top.iter().filter(|key| relevant.contains(key.as_str())).count() as f64;
+    let discount = |position: usize| 1.0 / (position as f64 + 2.0).log2();
+    let dcg: f64 = top
+        .iter()
+        .enumerate()
+        .filter(|(_, key)| relevant.contains(key.as_str()))
+        .map(|(i, _)| discount(i))
+        .sum();
+    let ideal: f64 = (0..relevant.len().min(k)).map(discount).sum();
+
+    Metrics {
+        precision: hits / k as f64,
+        recall: if relevant.is_empty() { 0.0 } else { hits / relevant.len() as f64 },
+        ndcg: if ideal > 0.0 { dcg / ideal } else { 0.0 },
+    }
+}
+
+fn rank(set: &recommendations::ProjectSet, request: &recommendations::RecommendationRequest, mappings: &recommendations::PreferenceMappings, k: usize) -> Vec<String> {
+    let scored = recommendations::score_projects(set, request, mappings);
+    let scored = match request.diversity {
+        Some(diversity) if diversity > 0.0 => recommendations::diversify(scored, diversity, Some(k), None),
+        _ => scored,
+    };
+    scored.into_iter().map(|s| s.project.key).collect()
+}
+
+/// Floors every profile must clear regardless of the baseline
+fn floor_failures(name: &str, metrics: &Metrics, relevant: usize, k: usize) -> Vec<String> {
+    let attainable = relevant.min(k) as f64 / k as f64;
+    let mut failures = Vec::new();
+    if metrics.recall < MIN_RECALL {
+        failures.push(format!("{name} recall {:.3} < {MIN_RECALL}", metrics.recall));
+    }
+    if metrics.ndcg < MIN_NDCG {
+        failures.push(format!("{name} ndcg {:.3} < {MIN_NDCG}", metrics.ndcg));
+    }
+    if attainable > 0.0 && metrics.precision / attainable < MIN_ATTAINABLE_PRECISION {
+        failures.push(format!(
+            "{name} precision {:.3} is below {MIN_ATTAINABLE_PRECISION} of the attainable {attainable:.3}",
+            metrics.precision
+        ));
+    }
+    failures
+}
+
+#[test]
+fn recommendations_meet_floors_and_baseline() {
+    let labels: Labels = serde_json::from_str(&std::fs::read_to_string(LABELS_PATH).unwrap()).unwrap();
+    let tagger = tagger::Tagger::load().unwrap();
+    let mut projects = recommendations::load_projects(&labels.workbook).unwrap();
+    projects.iter_mut().for_each(|p| tagger.tag_project(p));
+    let set = recommendations::ProjectSet::new(projects);
+    let mappings = recommendations::load_mappings(recommendations::MAPPINGS_PATH).unwrap();
+    let k = labels.k.max(1);
+
+    println!("Evaluating {} profiles against {} ({} projects), k = {k}", labels.profiles.len(), labels.workbook, set.projects.len());
+    println!("{:<24} {:>9} {:>9} {:>9}", "profile", "P@k", "R@k", "nDCG@k");
+
+    let mut failures = Vec::new();
+    let mut current = Baseline { k, ..Default::default() };
+    for profile in &labels.profiles {
+        let relevant: HashSet<&str> = profile.relevant.iter().map(String::as_str).collect();
+        let ranked = rank(&set, &profile.request, &mappings, k);
+        let metrics = evaluate(&ranked, &relevant, k);
+        println!("{:<24} {:>9.3} {:>9.3} {:>9.3}", profile.name, metrics.precision, metrics.recall, metrics.ndcg);
+        failures.extend(floor_failures(&profile.name, &metrics, relevant.len(), k));
+        current.profiles.insert(profile.name.clone(), metrics);
+    }
+
+    let count = current.profiles.len().max(1) as f64;
+    let sum = |metric: fn(&Metrics) -> f64| current.profiles.values().map(metric).sum::<f64>() / count;
+    current.mean = Metrics { precision: sum(|m| m.precision), recall: sum(|m| m.recall), ndcg: sum(|m| m.ndcg) };
+    println!("{:<24} {:>9.3} {:>9.3} {:>9.3}", "mean", current.mean.precision, current.mean.recall, current.mean.ndcg);
+    if current.mean.ndcg < MIN_MEAN_NDCG {
+        failures.push(format!("mean ndcg {:.3} < {MIN_MEAN_NDCG}", current.mean.ndcg));
+    }
+    assert!(failures.is_empty(), "quality floors missed:\n  {}", failures.join("\n  "));
+
+    if std::env::var("UPDATE_BASELINE").is_ok_and(|v| v == "1") {
+        std::fs::write(BASELINE_PATH, serde_json::to_string_pretty(&current).unwrap() + "\n").unwrap();
+        println!("Baseline written to {BASELINE_PATH}");
+        return;
+    }
+
+    let baseline: Baseline = serde_json::from_str(&std::fs::read_to_string(BASELINE_PATH).unwrap()).unwrap();
+    assert_eq!(baseline.k, k, "baseline was recorded with a different k; rerun with UPDATE_BASELINE=1");
+
+    let mut regressions = Vec::new();
+    let mut compare = |name: &str, now: &Metrics, before: Option<&Metrics>| match before {
+        Some(before) => {
+            for ((metric, value), (_, previous)) in now.named().into_iter().zip(before.named()) {
+                if value < previous - TOLERANCE {
+                    regressions.push(format!("{name} {metric}: {previous:.3} -> {value:.3}"));
+                }
+            }
+        }
+        None => regressions.push(format!("{name} has no baseline; rerun with UPDATE_BASELINE=1")),
+    };
+    compare("mean", &current.mean, Some(&baseline.mean));
+    for (name, metrics) in &current.profiles {
+        compare(name, metrics, baseline.profiles.get(name));
+    }
+    assert!(regressions.is_empty(), "metrics regressed against {BASELINE_PATH}:\n  {}", regressions.join("\n  "));
+}